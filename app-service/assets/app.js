@@ -6,19 +6,27 @@ logoutLink.addEventListener("click", (e) => {
     e.preventDefault();
 
     let url = logoutLink.href;
+    // the auth service wants its CSRF token with cookie-authenticated POSTs
+    let csrfUrl = new URL('/csrf-token', url);
 
-    fetch(url, {
-        method: 'POST',
-        credentials: 'include', // This will include cookies in the request
-    }).then(response => {
-        if (response.ok) {
-            loginLink.style.display = "block";
-            logoutLink.style.display = "none";
-            protectImg.src = "/assets/default.jpg";
-        } else {
-            alert("Failed to logout");
-        }
-    });
+    fetch(csrfUrl, { credentials: 'include' })
+        .then(response => response.json())
+        .then(data => fetch(url, {
+            method: 'POST',
+            credentials: 'include', // This will include cookies in the request
+            headers: {
+                'X-CSRF-Token': data.csrfToken,
+            },
+        }))
+        .then(response => {
+            if (response.ok) {
+                loginLink.style.display = "block";
+                logoutLink.style.display = "none";
+                protectImg.src = "/assets/default.jpg";
+            } else {
+                alert("Failed to logout");
+            }
+        });
 });
 
 (() => {
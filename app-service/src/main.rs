@@ -1,8 +1,10 @@
 use std::env;
 
+mod telemetry;
+
 use askama::Template;
 use axum::{
-    http::StatusCode,
+    http::{HeaderMap, StatusCode},
     response::{Html, IntoResponse},
     routing::get,
     Json, Router,
@@ -13,6 +15,7 @@ use tower_http::services::ServeDir;
 
 #[tokio::main]
 async fn main() {
+    let tracer_provider = telemetry::init();
     let app = Router::new()
         .nest_service("/assets", ServeDir::new("assets"))
         .route("/", get(root))
@@ -22,6 +25,7 @@ async fn main() {
 
     println!("listening on {}", listener.local_addr().unwrap());
     axum::serve(listener, app).await.unwrap();
+    let _ = tracer_provider.shutdown();
 }
 
 #[derive(Template)]
@@ -46,8 +50,15 @@ async fn root() -> impl IntoResponse {
     Html(template.render().unwrap())
 }
 
-async fn protected(jar: CookieJar) -> impl IntoResponse {
-    let jwt_cookie = match jar.get("jwt") {
+async fn protected(headers: HeaderMap, jar: CookieJar) -> impl IntoResponse {
+    let cx = telemetry::server_span("GET /protected", &headers);
+
+    // the auth service's `auth_cookie.name`, with `__Host-` if it sets `auth_cookie.host_prefix`
+    let cookie_name = env::var("AUTH_COOKIE_NAME")
+        .ok()
+        .filter(|name| !name.is_empty())
+        .unwrap_or("jwt".to_owned());
+    let jwt_cookie = match jar.get(&cookie_name) {
         Some(cookie) => cookie,
         None => {
             return StatusCode::UNAUTHORIZED.into_response();
@@ -63,7 +74,14 @@ async fn protected(jar: CookieJar) -> impl IntoResponse {
     let auth_hostname = env::var("AUTH_SERVICE_HOST_NAME").unwrap_or("0.0.0.0".to_owned());
     let url = format!("http://{}:3000/verify-token", auth_hostname);
 
-    let response = match api_client.post(&url).json(&verify_token_body).send().await {
+    // carries `traceparent` and `tracestate` to the auth service
+    let (_client_cx, trace_headers) = telemetry::client_span("POST /verify-token", &cx);
+    let mut request = api_client.post(&url).json(&verify_token_body);
+    for (name, value) in trace_headers {
+        request = request.header(name, value);
+    }
+
+    let response = match request.send().await {
         Ok(response) => response,
         Err(_) => {
             return StatusCode::INTERNAL_SERVER_ERROR.into_response();
@@ -0,0 +1,66 @@
+use std::collections::HashMap;
+use std::env;
+
+use axum::http::HeaderMap;
+use opentelemetry::global::{self, BoxedTracer};
+use opentelemetry::propagation::TextMapPropagator;
+use opentelemetry::trace::{SpanKind, TraceContextExt, Tracer};
+use opentelemetry::Context;
+use opentelemetry_http::HeaderExtractor;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use opentelemetry_sdk::Resource;
+
+const SERVICE_NAME: &str = "app-service";
+
+// Spans are exported over OTLP/HTTP if `OTEL_EXPORTER_OTLP_ENDPOINT` (or
+// `OTEL_EXPORTER_OTLP_TRACES_ENDPOINT`) is set. Either way every request gets a trace context,
+// which is passed on to the auth service so that its spans join the same trace.
+pub fn init() -> SdkTracerProvider {
+    let mut builder = SdkTracerProvider::builder()
+        .with_resource(Resource::builder().with_service_name(SERVICE_NAME).build());
+    let export = [
+        "OTEL_EXPORTER_OTLP_ENDPOINT",
+        "OTEL_EXPORTER_OTLP_TRACES_ENDPOINT",
+    ]
+    .iter()
+    .any(|name| env::var(name).is_ok_and(|value| !value.is_empty()));
+    if export {
+        let exporter = opentelemetry_otlp::SpanExporter::builder()
+            .with_http()
+            .build()
+            .expect("Failed to create OTLP span exporter");
+        builder = builder.with_batch_exporter(exporter);
+    }
+    let provider = builder.build();
+    global::set_tracer_provider(provider.clone());
+    provider
+}
+
+fn tracer() -> BoxedTracer {
+    global::tracer(SERVICE_NAME)
+}
+
+// A server span for an incoming request, continuing the browser's trace if it sent a
+// `traceparent`.
+pub fn server_span(name: &'static str, headers: &HeaderMap) -> Context {
+    let parent = TraceContextPropagator::new().extract(&HeaderExtractor(headers));
+    let span = tracer()
+        .span_builder(name)
+        .with_kind(SpanKind::Server)
+        .start_with_context(&tracer(), &parent);
+    parent.with_span(span)
+}
+
+// A client span for a call to another service, with the headers that carry it there.
+// Either span ends once the last `Context` holding it is dropped.
+pub fn client_span(name: &'static str, cx: &Context) -> (Context, HashMap<String, String>) {
+    let span = tracer()
+        .span_builder(name)
+        .with_kind(SpanKind::Client)
+        .start_with_context(&tracer(), cx);
+    let cx = cx.with_span(span);
+    let mut headers = HashMap::new();
+    TraceContextPropagator::new().inject_context(&cx, &mut headers);
+    (cx, headers)
+}
@@ -1,3 +1,24 @@
+// POSTs carry the CSRF token in a header, which pages on other sites can't set.
+let csrfTokenRequest;
+
+function withCsrfToken(request) {
+    if (csrfTokenRequest === undefined) {
+        csrfTokenRequest = fetch('/csrf-token')
+            .then(response => response.json())
+            .then(data => data.csrfToken)
+            .catch(() => "");
+    }
+    return csrfTokenRequest.then(request);
+}
+
+// Tokens are bound to the session, so logging in or out comes with a new one.
+function forgetCsrfToken(response) {
+    if (response.ok) {
+        csrfTokenRequest = undefined;
+    }
+    return response;
+}
+
 const loginSection = document.getElementById("login-section");
 const twoFASection = document.getElementById("2fa-section");
 const signupSection = document.getElementById("signup-section");
@@ -42,13 +63,14 @@ loginButton.addEventListener("click", (e) => {
     const email = loginForm.email.value;
     const password = loginForm.password.value;
 
-    fetch('/login', {
+    withCsrfToken(csrfToken => fetch('/login', {
         method: 'POST',
         headers: {
             'Content-Type': 'application/json',
+            'X-CSRF-Token': csrfToken,
         },
         body: JSON.stringify({ email, password }),
-    }).then(response => {
+    }).then(forgetCsrfToken)).then(response => {
         if (response.status === 206) {
             TwoFAForm.email.value = email;
             response.json().then(data => {
@@ -92,13 +114,14 @@ signupButton.addEventListener("click", (e) => {
     const password = signupForm.password.value;
     const requires2FA = signupForm.twoFA.checked;
 
-    fetch('/signup', {
+    withCsrfToken(csrfToken => fetch('/signup', {
         method: 'POST',
         headers: {
             'Content-Type': 'application/json',
+            'X-CSRF-Token': csrfToken,
         },
         body: JSON.stringify({ email, password, requires2FA }),
-    }).then(response => {
+    })).then(response => {
         if (response.ok) {
             signupForm.email.value = "";
             signupForm.password.value = "";
@@ -133,13 +156,14 @@ TwoFAButton.addEventListener("click", (e) => {
     const loginAttemptId = TwoFAForm.login_attempt_id.value;
     const TwoFACode = TwoFAForm.email_code.value;
 
-    fetch('/verify-2fa', {
+    withCsrfToken(csrfToken => fetch('/verify-2fa', {
         method: 'POST',
         headers: {
             'Content-Type': 'application/json',
+            'X-CSRF-Token': csrfToken,
         },
         body: JSON.stringify({ email, loginAttemptId, "2FACode": TwoFACode }),
-    }).then(response => {
+    }).then(forgetCsrfToken)).then(response => {
         if (response.ok) {
             TwoFAForm.email.value = "";
             TwoFAForm.email_code.value = "";
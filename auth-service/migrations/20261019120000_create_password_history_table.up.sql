@@ -0,0 +1,11 @@
+-- Previous password hashes per user, so that recently used passwords can be rejected.
+-- The current hash stays in users.password_hash; rows are pruned to the configured history size.
+CREATE TABLE IF NOT EXISTS password_history
+(
+    id            BIGSERIAL PRIMARY KEY,
+    email         TEXT        NOT NULL REFERENCES users (email) ON DELETE CASCADE,
+    password_hash TEXT        NOT NULL,
+    created_at    TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS password_history_email_idx ON password_history (email, id DESC);
@@ -0,0 +1,2 @@
+-- Preferred language for emails, as a language tag like `en` or `de`.
+ALTER TABLE users ADD COLUMN IF NOT EXISTS language TEXT NOT NULL DEFAULT 'en';
@@ -0,0 +1,18 @@
+-- Emails waiting to be delivered by the outbox worker. Rows stay 'pending' until they are
+-- sent or, after too many failed attempts, become 'dead' and wait for an admin to retry them.
+CREATE TABLE IF NOT EXISTS email_outbox
+(
+    id              BIGSERIAL PRIMARY KEY,
+    recipient       TEXT        NOT NULL,
+    subject         TEXT        NOT NULL,
+    text_body       TEXT        NOT NULL,
+    html_body       TEXT        NOT NULL,
+    status          TEXT        NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'sent', 'dead')),
+    attempts        INT         NOT NULL DEFAULT 0,
+    next_attempt_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_error      TEXT,
+    created_at      TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    sent_at         TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS email_outbox_due_idx ON email_outbox (next_attempt_at) WHERE status = 'pending';
@@ -0,0 +1,6 @@
+DROP INDEX IF EXISTS users_email_lower_idx;
+
+ALTER TABLE password_history
+    DROP CONSTRAINT password_history_email_fkey,
+    ADD CONSTRAINT password_history_email_fkey
+        FOREIGN KEY (email) REFERENCES users (email) ON DELETE CASCADE;
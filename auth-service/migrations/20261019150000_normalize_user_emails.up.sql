@@ -0,0 +1,8 @@
+-- Email addresses are now stored in their canonical form (see `domain::email`). Existing ones
+-- are rewritten by the service at startup, through the same parser as every lookup, which then
+-- adds `users_email_lower_idx` (see `PostgresUserStore::normalize_emails`). A rewritten
+-- address has to carry over to the user's password history.
+ALTER TABLE password_history
+    DROP CONSTRAINT password_history_email_fkey,
+    ADD CONSTRAINT password_history_email_fkey
+        FOREIGN KEY (email) REFERENCES users (email) ON DELETE CASCADE ON UPDATE CASCADE;
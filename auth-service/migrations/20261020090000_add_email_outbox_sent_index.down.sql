@@ -0,0 +1 @@
+DROP INDEX IF EXISTS email_outbox_sent_idx;
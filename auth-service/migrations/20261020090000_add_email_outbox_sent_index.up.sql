@@ -0,0 +1,2 @@
+-- Sent emails are deleted once they are older than `email.outbox.sent_retention`.
+CREATE INDEX IF NOT EXISTS email_outbox_sent_idx ON email_outbox (sent_at) WHERE status = 'sent';
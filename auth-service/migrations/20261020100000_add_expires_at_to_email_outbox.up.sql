@@ -0,0 +1,2 @@
+-- Emails that are useless after a while, like 2FA codes, are discarded instead of sent late.
+ALTER TABLE email_outbox ADD COLUMN IF NOT EXISTS expires_at TIMESTAMPTZ;
@@ -0,0 +1,302 @@
+use std::sync::Arc;
+
+use color_eyre::eyre::{eyre, Context, Report, Result};
+use tokio::sync::RwLock;
+
+use super::{AppState, EmailClientType};
+use crate::domain::data_stores::{BannedTokenStore, EmailOutbox, TwoFACodeStore, UserStore};
+use crate::domain::email_domain_policy::{EmailDomainPolicy, MxResolver};
+use crate::domain::health::HealthCheck;
+use crate::domain::EmailClient;
+use crate::services::dns_mx_resolver::DnsMxResolver;
+use crate::services::email_health_check::EmailHealthCheck;
+use crate::services::hashmap_email_outbox::HashmapEmailOutbox;
+use crate::services::hashmap_two_fa_code_store::HashmapTwoFACodeStore;
+use crate::services::hashmap_user_store::HashmapUserStore;
+use crate::services::hashset_bannedtoken_store::HashsetBannedTokenStore;
+use crate::services::http_email_client::{HttpEmailClient, HttpEmailConfig};
+use crate::services::mock_email_client::MockEmailClient;
+use crate::services::smtp_email_client::{SmtpConfig, SmtpEmailClient};
+use crate::settings::{EmailBackend, EmailSettings, Settings, StoreBackend};
+
+// Builds the stores, the email client and the email domain policy the settings ask for.
+// Postgres is only connected to (and migrated) if a store lives there, and likewise Redis.
+pub async fn build_app_state(settings: Arc<Settings>) -> Result<AppState> {
+    let backends = &settings.backends;
+    tracing::info!(
+        user_store = %backends.user_store,
+        banned_token_store = %backends.banned_token_store,
+        two_fa_code_store = %backends.two_fa_code_store,
+        email_outbox = %backends.email_outbox,
+        "configuring backends"
+    );
+    let connections = Connections::open(&settings).await?;
+    let user_store = connections.user_store(backends.user_store)?;
+    let ban_store = connections.banned_token_store(&settings)?;
+    let two_fa_code_store = connections.two_fa_code_store(&settings)?;
+    let email_outbox = connections.email_outbox(backends.email_outbox)?;
+    let email_client: EmailClientType = Arc::new(RwLock::new(email_client(&settings.email)?));
+    let mut health_checks = connections.health_checks();
+    health_checks.push(Box::new(EmailHealthCheck::new(Arc::clone(&email_client))));
+    let email_domain_policy =
+        EmailDomainPolicy::from_settings(&settings.email.domain_policy, || {
+            Ok(Box::new(DnsMxResolver::from_system_conf()?) as Box<dyn MxResolver>)
+        })
+        .wrap_err("invalid email domain policy")?;
+
+    Ok(AppState::new(
+        settings,
+        Arc::new(RwLock::new(user_store)),
+        Arc::new(RwLock::new(ban_store)),
+        Arc::new(RwLock::new(two_fa_code_store)),
+        email_client,
+        Arc::new(RwLock::new(email_outbox)),
+    )
+    .with_email_domain_policy(email_domain_policy)
+    .with_health_checks(health_checks)
+    .with_connections(connections))
+}
+
+// Without an explicit `email.backend`, SMTP is used if `email.smtp.host` is set; otherwise
+// emails (and with them 2FA codes) only show up in the logs.
+pub fn email_client(settings: &EmailSettings) -> Result<Box<dyn EmailClient>> {
+    let smtp_config = SmtpConfig::from_settings(settings).wrap_err("invalid SMTP configuration")?;
+    match (settings.backend, smtp_config) {
+        (EmailBackend::Http, _) => {
+            let config = HttpEmailConfig::from_settings(settings)
+                .wrap_err("invalid email API configuration")?;
+            tracing::info!(base_url = %config.base_url, "sending emails via HTTP API");
+            Ok(Box::new(HttpEmailClient::new(config)?))
+        }
+        (EmailBackend::Smtp | EmailBackend::Auto, Some(config)) => {
+            tracing::info!(host = %config.host, port = config.port, "sending emails via SMTP");
+            Ok(Box::new(SmtpEmailClient::new(config)?))
+        }
+        (EmailBackend::Smtp, None) => Err(eyre!(
+            "email.backend is smtp, but email.smtp.host is not set"
+        )),
+        (EmailBackend::Log | EmailBackend::Auto, _) => {
+            tracing::warn!("no email backend configured, emails are only logged");
+            Ok(Box::new(MockEmailClient))
+        }
+    }
+}
+
+// The connections shared by the stores, opened only for the backends in use.
+#[derive(Default)]
+pub struct Connections {
+    #[cfg(feature = "postgres")]
+    pg_pool: Option<sqlx::PgPool>,
+    #[cfg(feature = "redis")]
+    redis: Option<Arc<RwLock<redis::Connection>>>,
+    // a connection of its own, async so a probe can be cut short, and without waiting for the
+    // stores' lock
+    #[cfg(feature = "redis")]
+    redis_health: Option<redis::aio::MultiplexedConnection>,
+}
+
+impl Connections {
+    async fn open(settings: &Settings) -> Result<Self> {
+        #[allow(unused_mut)]
+        let mut connections = Connections::default();
+        #[cfg(feature = "postgres")]
+        if settings.backends.uses(StoreBackend::Postgres) {
+            let pg_pool = crate::get_postgres_pool(&settings.database)
+                .await
+                .wrap_err("failed to create Postgres connection pool")?;
+            sqlx::migrate!()
+                .run(&pg_pool)
+                .await
+                .wrap_err("failed to run migrations")?;
+            if settings.backends.user_store == StoreBackend::Postgres {
+                crate::services::data_stares::postgres_user_store::PostgresUserStore::new(
+                    pg_pool.clone(),
+                )
+                .normalize_emails()
+                .await
+                .wrap_err("failed to normalise user emails")?;
+            }
+            connections.pg_pool = Some(pg_pool);
+        }
+        #[cfg(feature = "redis")]
+        if settings.backends.uses(StoreBackend::Redis) {
+            let client = crate::get_redis_client(settings.redis.host_name.clone())
+                .wrap_err("failed to connect to Redis")?;
+            let connection = client
+                .get_connection()
+                .wrap_err("failed to connect to Redis")?;
+            let timeout = settings.health.check_timeout;
+            let health_connection = client
+                .get_multiplexed_tokio_connection_with_response_timeouts(timeout, timeout)
+                .await
+                .wrap_err("failed to connect to Redis")?;
+            connections.redis = Some(Arc::new(RwLock::new(connection)));
+            connections.redis_health = Some(health_connection);
+        }
+        #[cfg(not(any(feature = "postgres", feature = "redis")))]
+        let _ = settings;
+        Ok(connections)
+    }
+
+    // For shutdown, once no more requests are served. Waits for the stores to finish what they
+    // are doing; calls made afterwards fail.
+    pub async fn close(&self) {
+        #[cfg(feature = "postgres")]
+        if let Some(pg_pool) = &self.pg_pool {
+            pg_pool.close().await;
+        }
+        #[cfg(feature = "redis")]
+        if let Some(redis) = &self.redis {
+            let mut connection = redis.write().await;
+            if let Err(e) = redis::cmd("QUIT").query::<()>(&mut *connection) {
+                tracing::warn!("could not close the Redis connection: {:?}", e);
+            }
+        }
+    }
+
+    #[cfg(feature = "postgres")]
+    fn pg_pool(&self) -> sqlx::PgPool {
+        self.pg_pool
+            .clone()
+            .expect("Postgres is connected whenever a store uses it")
+    }
+
+    #[cfg(feature = "redis")]
+    fn redis(&self) -> Arc<RwLock<redis::Connection>> {
+        self.redis
+            .clone()
+            .expect("Redis is connected whenever a store uses it")
+    }
+
+    // One check per connection rather than per store; memory stores can't fail.
+    fn health_checks(&self) -> Vec<Box<dyn HealthCheck>> {
+        #[allow(unused_mut)]
+        let mut health_checks: Vec<Box<dyn HealthCheck>> = Vec::new();
+        #[cfg(feature = "postgres")]
+        if let Some(pg_pool) = &self.pg_pool {
+            health_checks.push(Box::new(
+                crate::services::data_stares::postgres_health_check::PostgresHealthCheck::new(
+                    pg_pool.clone(),
+                ),
+            ));
+        }
+        #[cfg(feature = "redis")]
+        if let Some(redis) = &self.redis_health {
+            health_checks.push(Box::new(
+                crate::services::data_stares::redis_health_check::RedisHealthCheck::new(
+                    redis.clone(),
+                ),
+            ));
+        }
+        health_checks
+    }
+
+    fn user_store(&self, backend: StoreBackend) -> Result<Box<dyn UserStore>> {
+        match backend {
+            #[cfg(feature = "postgres")]
+            StoreBackend::Postgres => Ok(Box::new(
+                crate::services::data_stares::postgres_user_store::PostgresUserStore::new(
+                    self.pg_pool(),
+                ),
+            )),
+            StoreBackend::Memory => Ok(Box::new(HashmapUserStore::default())),
+            backend => Err(unavailable("user_store", backend)),
+        }
+    }
+
+    fn banned_token_store(&self, settings: &Settings) -> Result<Box<dyn BannedTokenStore>> {
+        match settings.backends.banned_token_store {
+            #[cfg(feature = "redis")]
+            StoreBackend::Redis => Ok(Box::new(
+                crate::services::data_stares::redis_banned_token_store::RedisBannedTokenStore::new(
+                    self.redis(),
+                    settings.jwt.token_ttl,
+                ),
+            )),
+            StoreBackend::Memory => Ok(Box::new(HashsetBannedTokenStore::default())),
+            backend => Err(unavailable("banned_token_store", backend)),
+        }
+    }
+
+    fn two_fa_code_store(&self, settings: &Settings) -> Result<Box<dyn TwoFACodeStore>> {
+        match settings.backends.two_fa_code_store {
+            #[cfg(feature = "redis")]
+            StoreBackend::Redis => Ok(Box::new(
+                crate::services::data_stares::redis_two_fa_code_store::RedisTwoFACodeStore::new(
+                    self.redis(),
+                    settings.two_fa.code_ttl,
+                ),
+            )),
+            StoreBackend::Memory => Ok(Box::new(HashmapTwoFACodeStore::default())),
+            backend => Err(unavailable("two_fa_code_store", backend)),
+        }
+    }
+
+    fn email_outbox(&self, backend: StoreBackend) -> Result<Box<dyn EmailOutbox>> {
+        match backend {
+            #[cfg(feature = "postgres")]
+            StoreBackend::Postgres => Ok(Box::new(
+                crate::services::data_stares::postgres_email_outbox::PostgresEmailOutbox::new(
+                    self.pg_pool(),
+                ),
+            )),
+            StoreBackend::Memory => Ok(Box::new(HashmapEmailOutbox::default())),
+            backend => Err(unavailable("email_outbox", backend)),
+        }
+    }
+}
+
+// `Settings::validate` reports these already; this only guards against unvalidated settings.
+fn unavailable(store: &str, backend: StoreBackend) -> Report {
+    eyre!("backends.{} can't be {} in this build", store, backend)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::user::User;
+    use crate::domain::Email;
+    use secrecy::Secret;
+
+    fn memory_settings() -> Settings {
+        let mut settings = Settings::default();
+        settings.jwt.secret = Secret::new("secret".to_owned());
+        settings.backends.user_store = StoreBackend::Memory;
+        settings.backends.banned_token_store = StoreBackend::Memory;
+        settings.backends.two_fa_code_store = StoreBackend::Memory;
+        settings.backends.email_outbox = StoreBackend::Memory;
+        settings
+    }
+
+    #[tokio::test]
+    async fn test_memory_backends_need_no_connections() {
+        let settings = memory_settings();
+        settings.validate().unwrap();
+        let app_state = build_app_state(Arc::new(settings)).await.unwrap();
+
+        let user = User::new("herbert@email.com", "password123§!!", true)
+            .await
+            .unwrap();
+        app_state
+            .user_store
+            .write()
+            .await
+            .add_user(user)
+            .await
+            .unwrap();
+        app_state
+            .user_store
+            .read()
+            .await
+            .get_user(&Email::parse("herbert@email.com".to_string()).unwrap())
+            .await
+            .expect("Failed to get user");
+    }
+
+    #[tokio::test]
+    async fn test_unsupported_backend_is_an_error() {
+        let mut settings = memory_settings();
+        settings.backends.user_store = StoreBackend::Redis;
+        assert!(build_app_state(Arc::new(settings)).await.is_err());
+    }
+}
@@ -1,6 +1,13 @@
-use crate::domain::data_stores::{BannedTokenStore, TwoFACodeStore, UserStore};
+pub mod backends;
+
+use self::backends::Connections;
+use crate::domain::data_stores::{BannedTokenStore, EmailOutbox, TwoFACodeStore, UserStore};
+use crate::domain::email_domain_policy::EmailDomainPolicy;
+use crate::domain::health::HealthCheck;
 use crate::domain::EmailClient;
 pub use crate::services::hashmap_user_store::HashmapUserStore;
+use crate::settings::Settings;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
@@ -9,25 +16,64 @@ pub type UserStoreType = Arc<RwLock<Box<dyn UserStore>>>;
 pub type BanStoreType = Arc<RwLock<Box<dyn BannedTokenStore>>>;
 pub type TwoFACodeStoreType = Arc<RwLock<Box<dyn TwoFACodeStore>>>;
 pub type EmailClientType = Arc<RwLock<Box<dyn EmailClient>>>;
+pub type EmailOutboxType = Arc<RwLock<Box<dyn EmailOutbox>>>;
 pub struct AppState {
+    pub settings: Arc<Settings>,
     pub user_store: UserStoreType,
     pub ban_store: BanStoreType,
     pub two_fa_code_store: TwoFACodeStoreType,
     pub email_client: EmailClientType,
+    // request handlers queue emails here instead of sending them through `email_client`
+    pub email_outbox: EmailOutboxType,
+    // consulted on signup; accepts every domain unless configured
+    pub email_domain_policy: Arc<EmailDomainPolicy>,
+    // the dependencies `/health/ready` probes
+    pub health_checks: Arc<Vec<Box<dyn HealthCheck>>>,
+    // set once shutdown begins, so that `/health/ready` takes the instance out of rotation
+    pub draining: Arc<AtomicBool>,
+    // closed by `Application::run` once the server has drained
+    pub connections: Arc<Connections>,
 }
 
 impl AppState {
     pub fn new(
+        settings: Arc<Settings>,
         user_store: UserStoreType,
         ban_store: BanStoreType,
         two_fa_code_store: TwoFACodeStoreType,
         email_client: EmailClientType,
+        email_outbox: EmailOutboxType,
     ) -> Self {
         Self {
+            settings,
             user_store,
             ban_store,
             two_fa_code_store,
             email_client,
+            email_outbox,
+            email_domain_policy: Arc::new(EmailDomainPolicy::default()),
+            health_checks: Arc::new(Vec::new()),
+            draining: Arc::new(AtomicBool::new(false)),
+            connections: Arc::new(Connections::default()),
         }
     }
+
+    pub fn with_email_domain_policy(mut self, email_domain_policy: EmailDomainPolicy) -> Self {
+        self.email_domain_policy = Arc::new(email_domain_policy);
+        self
+    }
+
+    pub fn with_health_checks(mut self, health_checks: Vec<Box<dyn HealthCheck>>) -> Self {
+        self.health_checks = Arc::new(health_checks);
+        self
+    }
+
+    pub fn with_connections(mut self, connections: Connections) -> Self {
+        self.connections = Arc::new(connections);
+        self
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::Relaxed)
+    }
 }
@@ -0,0 +1,50 @@
+use auth_service::get_postgres_pool;
+use auth_service::services::data_stares::postgres_user_store::PostgresUserStore;
+use auth_service::services::user_import::{import_users, ImportFormat};
+use auth_service::settings::Settings;
+use color_eyre::eyre::{eyre, Result, WrapErr};
+use std::fs::File;
+use std::io::BufReader;
+
+// Bulk import of users exported from another system.
+//
+// usage: import_users <users.csv | users.jsonl>
+//
+// Each row holds `email`, `hash` and `requires_2fa`. Hashes may be Argon2, bcrypt,
+// scrypt or PBKDF2; they are upgraded to Argon2id on the user's next login.
+#[tokio::main]
+async fn main() -> Result<()> {
+    color_eyre::install()?;
+    let path = std::env::args()
+        .nth(1)
+        .ok_or(eyre!("usage: import_users <users.csv | users.jsonl>"))?;
+    let format = ImportFormat::from_path(&path)?;
+    let file = File::open(&path).wrap_err(format!("failed to open {}", path))?;
+
+    let settings = Settings::load()?;
+    let pg_pool = get_postgres_pool(&settings.database)
+        .await
+        .wrap_err("Failed to create Postgres connection pool!")?;
+    sqlx::migrate!()
+        .run(&pg_pool)
+        .await
+        .wrap_err("Failed to run migrations")?;
+    let mut user_store = PostgresUserStore::new(pg_pool);
+    // imported addresses are looked up among the existing ones in canonical form
+    user_store
+        .normalize_emails()
+        .await
+        .wrap_err("Failed to normalise user emails")?;
+
+    let report = import_users(BufReader::new(file), format, &mut user_store).await?;
+
+    for rejected in &report.rejected {
+        println!("rejected line {}: {}", rejected.line, rejected.reason);
+    }
+    println!(
+        "imported {} users, rejected {} rows",
+        report.imported,
+        report.rejected.len()
+    );
+    Ok(())
+}
@@ -1,11 +1,19 @@
 use crate::domain::email::Email;
-use crate::domain::password::Password;
+use crate::domain::email_message::EmailMessage;
+use crate::domain::language::Language;
+use crate::domain::password::{Password, PasswordError};
+use crate::domain::password_hash::PasswordHash;
 use crate::domain::user::User;
+use chrono::{DateTime, Utc};
 use color_eyre::eyre::{eyre, Context};
 use color_eyre::Report;
 use color_eyre::Result;
 use rand::Rng;
 use secrecy::{ExposeSecret, Secret};
+use serde::Serialize;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
 use thiserror::Error;
 use uuid::Uuid;
 
@@ -19,6 +27,8 @@ pub enum UserStoreError {
     InvalidCredentials,
     #[error("Invalid Password")]
     InvalidPassword,
+    #[error("New password is not allowed")]
+    InvalidNewPassword(PasswordError),
     #[error("Unexpected error")]
     UnexpectedError(#[source] Report),
 }
@@ -30,16 +40,72 @@ impl PartialEq for UserStoreError {
             (Self::UserAlreadyExists, Self::UserAlreadyExists)
                 | (Self::UserNotFound, Self::UserNotFound)
                 | (Self::InvalidCredentials, Self::InvalidCredentials)
+                | (Self::InvalidPassword, Self::InvalidPassword)
+                | (Self::InvalidNewPassword(_), Self::InvalidNewPassword(_))
                 | (Self::UnexpectedError(_), Self::UnexpectedError(_))
         )
     }
 }
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LegacyPasswordHashes {
+    // upgraded on the user's next login
+    pub legacy: u64,
+    // hashes that can't be parsed or checked; these users can't log in at all
+    pub unreadable: u64,
+}
+
+impl LegacyPasswordHashes {
+    // Counts one user's hash, given whether it needs a rehash.
+    pub fn count(&mut self, needs_rehash: Result<bool>) {
+        match needs_rehash {
+            Ok(true) => self.legacy += 1,
+            Ok(false) => {}
+            Err(e) => {
+                tracing::debug!("unreadable password hash: {:?}", e);
+                self.unreadable += 1;
+            }
+        }
+    }
+}
+
+// A count of legacy password hashes that doesn't borrow the store it came from.
+pub type LegacyPasswordHashCount =
+    Pin<Box<dyn Future<Output = Result<LegacyPasswordHashes, UserStoreError>> + Send>>;
+
 #[async_trait::async_trait]
 pub trait UserStore: Send + Sync {
     async fn add_user(&mut self, user: User) -> Result<(), UserStoreError>;
     async fn get_user(&self, email: &Email) -> Result<User, UserStoreError>;
     async fn validate_user(&self, email: &Email, password: &Password)
         -> Result<(), UserStoreError>;
+    async fn update_password_hash(
+        &mut self,
+        email: &Email,
+        password_hash: PasswordHash,
+    ) -> Result<(), UserStoreError>;
+    // Number of users whose password hash was computed with outdated parameters or algorithms.
+    // This reads every user, so the count is detached from the store: the lock around the
+    // store can be released before awaiting it, and writers don't wait for the whole scan.
+    fn count_legacy_password_hashes(&self) -> LegacyPasswordHashCount;
+    // Hashes of the user's previous passwords, newest first. The current one is not included.
+    async fn get_password_history(
+        &self,
+        email: &Email,
+    ) -> Result<Vec<PasswordHash>, UserStoreError>;
+    // Replaces the user's password, moving the current hash into the history and pruning the
+    // history to the `history_size` newest entries. Unlike `update_password_hash`, this is
+    // for an actual change of password; use `services::password_change` to check for reuse.
+    async fn update_password(
+        &mut self,
+        email: &Email,
+        password_hash: PasswordHash,
+        history_size: usize,
+    ) -> Result<(), UserStoreError>;
+    async fn update_language(
+        &mut self,
+        email: &Email,
+        language: Language,
+    ) -> Result<(), UserStoreError>;
 }
 #[derive(Debug, Error)]
 pub enum BannedTokenStoreError {
@@ -65,6 +131,95 @@ pub trait TwoFACodeStore: Send + Sync {
         email: &Email,
     ) -> Result<(LoginAttemptId, TwoFACode), TwoFACodeStoreError>;
 }
+#[derive(Debug, Error)]
+pub enum EmailOutboxError {
+    #[error("Outbox email not found")]
+    NotFound,
+    #[error("Outbox email has expired")]
+    Expired,
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+impl PartialEq for EmailOutboxError {
+    fn eq(&self, other: &Self) -> bool {
+        matches!(
+            (self, other),
+            (Self::NotFound, Self::NotFound)
+                | (Self::Expired, Self::Expired)
+                | (Self::UnexpectedError(_), Self::UnexpectedError(_))
+        )
+    }
+}
+
+// An email waiting in the outbox, as handed to the delivery worker.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutboxEmail {
+    pub id: i64,
+    pub recipient: Email,
+    pub message: EmailMessage,
+    // failed deliveries so far
+    pub attempts: u32,
+    // not worth sending anymore after this, e.g. because the 2FA code in it is no longer valid
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl OutboxEmail {
+    pub fn expired_at(&self, at: DateTime<Utc>) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= at)
+    }
+}
+
+// An email that could not be delivered and will not be retried, as shown to admins.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeadLetter {
+    pub id: i64,
+    pub recipient: String,
+    pub subject: String,
+    pub attempts: u32,
+    pub last_error: String,
+    pub created_at: DateTime<Utc>,
+}
+
+// Emails are written to the outbox while handling a request and delivered later by
+// `services::email_outbox_worker`, so that requests never wait for the email provider.
+#[async_trait::async_trait]
+pub trait EmailOutbox: Send + Sync {
+    // An email with `expires_at` is discarded rather than sent once that has passed.
+    async fn enqueue(
+        &mut self,
+        recipient: &Email,
+        message: &EmailMessage,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<i64, EmailOutboxError>;
+    // Up to `limit` emails that are due, each hidden from other workers for `lease`.
+    async fn claim_due(
+        &mut self,
+        limit: u32,
+        lease: Duration,
+    ) -> Result<Vec<OutboxEmail>, EmailOutboxError>;
+    // Also drops the bodies, which may hold 2FA codes.
+    async fn mark_sent(&mut self, id: i64) -> Result<(), EmailOutboxError>;
+    async fn retry_later(
+        &mut self,
+        id: i64,
+        error: &str,
+        at: DateTime<Utc>,
+    ) -> Result<(), EmailOutboxError>;
+    // Emails that expire lose their bodies like sent ones, and with them can't be requeued.
+    async fn dead_letter(&mut self, id: i64, error: &str) -> Result<(), EmailOutboxError>;
+    // Deletes a pending email that is no longer worth sending.
+    async fn discard(&mut self, id: i64) -> Result<(), EmailOutboxError>;
+    // Newest first.
+    async fn dead_letters(&self, limit: u32) -> Result<Vec<DeadLetter>, EmailOutboxError>;
+    // Puts a dead letter back into the outbox with a fresh set of attempts, unless it has
+    // expired.
+    async fn requeue(&mut self, id: i64) -> Result<(), EmailOutboxError>;
+    // Deletes emails sent before `before`; returns how many.
+    async fn purge_sent(&mut self, before: DateTime<Utc>) -> Result<u64, EmailOutboxError>;
+}
+
 #[derive(Debug, Clone)]
 pub struct LoginAttemptId(Secret<String>);
 impl LoginAttemptId {
@@ -76,7 +231,9 @@ impl LoginAttemptId {
                 .to_string(),
         )))
     }
-    pub fn as_ref(&self) -> &Secret<String> {
+}
+impl AsRef<Secret<String>> for LoginAttemptId {
+    fn as_ref(&self) -> &Secret<String> {
         &self.0
     }
 }
@@ -112,7 +269,9 @@ impl TwoFACode {
         }
         Ok(Self(code))
     }
-    pub fn as_ref(&self) -> &str {
+}
+impl AsRef<str> for TwoFACode {
+    fn as_ref(&self) -> &str {
         &self.0
     }
 }
@@ -1,8 +1,11 @@
 use color_eyre::eyre::Result;
 use secrecy::{ExposeSecret, Secret};
 use std::hash::Hash;
+use std::net::{Ipv4Addr, Ipv6Addr};
 use thiserror::Error;
 
+// An email address in its canonical form, which is what users are stored and looked up by:
+// lower case, with an IDNA (punycode) domain and without needless quoting of the local part.
 #[derive(Debug, Clone)] // Updated!
 pub struct Email(Secret<String>); // Updated!
 
@@ -29,9 +32,22 @@ pub enum ParseError {
     InvalidEmail,
 }
 
+// RFC 5321 limits
+const MAX_LOCAL_PART_LENGTH: usize = 64;
+const MAX_DOMAIN_LENGTH: usize = 253;
+const MAX_EMAIL_LENGTH: usize = 254;
+
 impl Email {
+    // Accepts an RFC 5321/5322 `addr-spec` (dot-atom or quoted local part, domain name or
+    // address literal), with UTF-8 allowed as in RFC 6531. Comments, folding whitespace and
+    // obsolete syntax are rejected.
     pub fn parse(email: String) -> Result<Self, ParseError> {
-        if !email.contains('@') {
+        let email = email.trim();
+        let (local_part, domain) = email.rsplit_once('@').ok_or(ParseError::InvalidEmail)?;
+        let local_part = normalize_local_part(local_part)?;
+        let domain = normalize_domain(domain)?;
+        let email = format!("{}@{}", local_part, domain);
+        if email.len() > MAX_EMAIL_LENGTH {
             return Err(ParseError::InvalidEmail);
         }
         Ok(Email(Secret::new(email)))
@@ -40,6 +56,121 @@ impl Email {
     pub fn unwrap(email: &str) -> Self {
         Self::parse(email.to_string()).unwrap()
     }
+
+    // e.g. `xn--bcher-kva.de` or `[192.0.2.1]`
+    pub fn domain(&self) -> &str {
+        let email = self.0.expose_secret();
+        email.rsplit_once('@').map_or("", |(_, domain)| domain)
+    }
+}
+
+// RFC 5322 `atext`, plus any non-ASCII character (RFC 6532)
+fn is_atext(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "!#$%&'*+-/=?^_`{|}~".contains(c) || is_utf8_non_ascii(c)
+}
+
+fn is_utf8_non_ascii(c: char) -> bool {
+    !c.is_ascii() && !c.is_control() && !c.is_whitespace()
+}
+
+fn is_dot_atom(s: &str) -> bool {
+    s.split('.')
+        .all(|atom| !atom.is_empty() && atom.chars().all(is_atext))
+}
+
+fn normalize_local_part(local_part: &str) -> Result<String, ParseError> {
+    let local_part = if let Some(quoted) = local_part
+        .strip_prefix('"')
+        .and_then(|rest| rest.strip_suffix('"'))
+    {
+        let content = unquote(quoted)?.to_lowercase();
+        // `"bob"@example.com` is the same mailbox as `bob@example.com`
+        if is_dot_atom(&content) {
+            content
+        } else {
+            quote(&content)
+        }
+    } else if is_dot_atom(local_part) {
+        local_part.to_lowercase()
+    } else {
+        return Err(ParseError::InvalidEmail);
+    };
+    if local_part.len() > MAX_LOCAL_PART_LENGTH {
+        return Err(ParseError::InvalidEmail);
+    }
+    Ok(local_part)
+}
+
+// Content of an RFC 5321 `Quoted-string`, with quoted pairs resolved.
+fn unquote(quoted: &str) -> Result<String, ParseError> {
+    let mut content = String::new();
+    let mut chars = quoted.chars();
+    while let Some(c) = chars.next() {
+        let c = match c {
+            '\\' => chars
+                .next()
+                .filter(|&c| c == ' ' || c.is_ascii_graphic())
+                .ok_or(ParseError::InvalidEmail)?,
+            '"' => return Err(ParseError::InvalidEmail),
+            c if c == ' ' || c.is_ascii_graphic() || is_utf8_non_ascii(c) => c,
+            _ => return Err(ParseError::InvalidEmail),
+        };
+        content.push(c);
+    }
+    Ok(content)
+}
+
+fn quote(content: &str) -> String {
+    let mut quoted = String::from('"');
+    for c in content.chars() {
+        if c == '"' || c == '\\' {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn normalize_domain(domain: &str) -> Result<String, ParseError> {
+    if let Some(literal) = domain
+        .strip_prefix('[')
+        .and_then(|rest| rest.strip_suffix(']'))
+    {
+        return normalize_address_literal(literal);
+    }
+    // applies UTS 46 mapping, which also lower-cases, and the STD3 hostname rules
+    let domain = idna::domain_to_ascii_strict(domain).map_err(|_| ParseError::InvalidEmail)?;
+    let labels: Vec<&str> = domain.split('.').collect();
+    let valid = domain.len() <= MAX_DOMAIN_LENGTH
+        // a bare host name like `localhost` can't be reached from the outside
+        && labels.len() >= 2
+        && labels.iter().all(|label| {
+            (1..=63).contains(&label.len())
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+        })
+        // otherwise it's an IP address without brackets
+        && !labels[labels.len() - 1].chars().all(|c| c.is_ascii_digit());
+    if !valid {
+        return Err(ParseError::InvalidEmail);
+    }
+    Ok(domain)
+}
+
+// `[192.0.2.1]` or `[IPv6:2001:db8::1]`
+fn normalize_address_literal(literal: &str) -> Result<String, ParseError> {
+    if let Some(ipv6) = literal
+        .get(..5)
+        .filter(|tag| tag.eq_ignore_ascii_case("ipv6:"))
+        .map(|_| &literal[5..])
+    {
+        let address: Ipv6Addr = ipv6.parse().map_err(|_| ParseError::InvalidEmail)?;
+        return Ok(format!("[ipv6:{}]", address));
+    }
+    let address: Ipv4Addr = literal.parse().map_err(|_| ParseError::InvalidEmail)?;
+    Ok(format!("[{}]", address))
 }
 
 impl AsRef<Secret<String>> for Email {
@@ -51,6 +182,19 @@ impl AsRef<Secret<String>> for Email {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use fake::faker::internet::en::SafeEmail;
+    use fake::rand::rngs::StdRng;
+    use fake::rand::SeedableRng;
+    use fake::Fake;
+    use quickcheck::Arbitrary;
+
+    fn normalized(email: &str) -> String {
+        Email::parse(email.to_owned())
+            .unwrap()
+            .as_ref()
+            .expose_secret()
+            .to_owned()
+    }
 
     #[test]
     fn test_valid_email() {
@@ -66,4 +210,116 @@ mod tests {
         let result = Email::parse(email);
         assert!(matches!(result, Err(ParseError::InvalidEmail)));
     }
+
+    #[test]
+    fn test_rejects_malformed_addresses() {
+        let too_long_local_part = format!("{}@example.com", "a".repeat(65));
+        let too_long_label = format!("bob@{}.com", "a".repeat(64));
+        let too_long_email = format!("bob@{}.com", vec!["a".repeat(60); 5].join("."));
+        for email in [
+            "",
+            "@",
+            "a@",
+            "@example.com",
+            "a b@example.com",
+            "bob@exa mple.com",
+            "bob@@example.com",
+            ".bob@example.com",
+            "bob.@example.com",
+            "bo..b@example.com",
+            "bo(b)@example.com",
+            "bo\"b@example.com",
+            "\"bo\"b\"@example.com",
+            "bob@localhost",
+            "bob@example",
+            "bob@example..com",
+            "bob@-example.com",
+            "bob@example-.com",
+            "bob@exa_mple.com",
+            "bob@192.168.0.1",
+            "bob@[192.168.0]",
+            "bob@[IPv6:1::2::3]",
+            too_long_local_part.as_str(),
+            too_long_label.as_str(),
+            too_long_email.as_str(),
+        ] {
+            assert!(
+                Email::parse(email.to_owned()).is_err(),
+                "accepted {:?}",
+                email
+            );
+        }
+    }
+
+    #[test]
+    fn test_accepts_rfc_5321_addresses() {
+        for (email, expected) in [
+            ("Bob@Example.COM", "bob@example.com"),
+            (" bob@example.com ", "bob@example.com"),
+            ("bob+tag@example.com", "bob+tag@example.com"),
+            ("o'brien@example.co.uk", "o'brien@example.co.uk"),
+            ("x{}|~!#$%&*=?^`@example.com", "x{}|~!#$%&*=?^`@example.com"),
+            ("\"Bob\"@example.com", "bob@example.com"),
+            ("\"bob smith\"@example.com", "\"bob smith\"@example.com"),
+            ("\"bob\\\"s\"@example.com", "\"bob\\\"s\"@example.com"),
+            ("\"a@b\"@example.com", "\"a@b\"@example.com"),
+            ("bob@[192.168.0.1]", "bob@[192.168.0.1]"),
+            ("bob@[IPv6:2001:DB8::1]", "bob@[ipv6:2001:db8::1]"),
+        ] {
+            assert_eq!(normalized(email), expected, "for {:?}", email);
+        }
+    }
+
+    #[test]
+    fn test_internationalized_domains_are_punycoded() {
+        assert_eq!(normalized("Jörg@Bücher.de"), "jörg@xn--bcher-kva.de");
+        assert_eq!(normalized("jörg@xn--bcher-kva.de"), "jörg@xn--bcher-kva.de");
+        assert_eq!(
+            normalized("user@例え.テスト"),
+            "user@xn--r8jz45g.xn--zckzah"
+        );
+    }
+
+    #[test]
+    fn test_domain() {
+        assert_eq!(Email::unwrap("\"a@b\"@Example.com").domain(), "example.com");
+        assert_eq!(Email::unwrap("bob@[192.0.2.1]").domain(), "[192.0.2.1]");
+    }
+
+    #[test]
+    fn test_addresses_differing_in_case_are_equal() {
+        assert_eq!(Email::unwrap("Bob@X.com"), Email::unwrap("bob@x.com"),);
+    }
+
+    #[derive(Debug, Clone)]
+    struct ValidEmailFixture(pub String);
+
+    impl Arbitrary for ValidEmailFixture {
+        fn arbitrary<G: quickcheck::Gen>(g: &mut G) -> Self {
+            // fake uses a newer `rand` than quickcheck, so bridge via a seed
+            let mut rng = StdRng::seed_from_u64(u64::arbitrary(g));
+            Self(SafeEmail().fake_with_rng(&mut rng))
+        }
+    }
+
+    #[quickcheck_macros::quickcheck]
+    fn valid_emails_are_parsed_successfully(valid_email: ValidEmailFixture) -> bool {
+        Email::parse(valid_email.0).is_ok()
+    }
+
+    #[quickcheck_macros::quickcheck]
+    fn normalization_is_idempotent(valid_email: ValidEmailFixture) -> bool {
+        let email = Email::parse(valid_email.0).unwrap();
+        Email::parse(email.as_ref().expose_secret().clone()).unwrap() == email
+    }
+
+    #[quickcheck_macros::quickcheck]
+    fn case_does_not_matter(valid_email: ValidEmailFixture) -> bool {
+        Email::parse(valid_email.0.to_uppercase()).unwrap() == Email::parse(valid_email.0).unwrap()
+    }
+
+    #[quickcheck_macros::quickcheck]
+    fn addresses_without_at_sign_are_rejected(input: String) -> bool {
+        input.contains('@') || Email::parse(input).is_err()
+    }
 }
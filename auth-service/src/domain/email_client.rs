@@ -1,8 +1,14 @@
 use crate::domain::email::Email;
+use crate::domain::email_message::EmailMessage;
 use color_eyre::eyre::Result;
 
 // This trait represents the interface all concrete email clients should implement
 #[async_trait::async_trait]
 pub trait EmailClient: Send + Sync {
-    async fn send_email(&self, recipient: &Email, subject: &str, content: &str) -> Result<()>;
+    async fn send_email(&self, recipient: &Email, message: &EmailMessage) -> Result<()>;
+
+    // Whether emails could be sent right now, without sending one.
+    async fn health_check(&self) -> Result<()> {
+        Ok(())
+    }
 }
@@ -0,0 +1,307 @@
+use std::collections::HashSet;
+use std::path::Path;
+use std::time::Duration;
+
+use color_eyre::eyre::{eyre, Context, Result};
+use thiserror::Error;
+
+use crate::domain::Email;
+use crate::settings::EmailDomainPolicySettings;
+
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum EmailDomainError {
+    #[error("Email addresses from this domain are not accepted")]
+    Blocked,
+    #[error("Only email addresses from approved domains are accepted")]
+    NotAllowed,
+    #[error("Email domain does not accept mail")]
+    NoMailServer,
+}
+
+// Looks up whether a domain has MX records, i.e. whether mail to it can be delivered at all.
+// `services::dns_mx_resolver` asks DNS; tests stub it.
+#[async_trait::async_trait]
+pub trait MxResolver: Send + Sync {
+    async fn has_mx(&self, domain: &str) -> Result<bool>;
+}
+
+// Which email domains may sign up. Every rule also covers subdomains, so blocking
+// `mailinator.com` blocks `eu.mailinator.com` as well. Address literals like
+// `bob@[192.0.2.1]` are rejected by any allowlist and never looked up.
+pub struct EmailDomainPolicy {
+    blocked_domains: HashSet<String>,
+    // `None` allows every domain that isn't blocked
+    allowed_domains: Option<HashSet<String>>,
+    mx_resolver: Option<Box<dyn MxResolver>>,
+    // the lookup runs during the signup request, so a slow resolver is given up on
+    mx_timeout: Duration,
+}
+
+pub const DEFAULT_MX_TIMEOUT: Duration = Duration::from_secs(1);
+
+impl Default for EmailDomainPolicy {
+    fn default() -> Self {
+        EmailDomainPolicy {
+            blocked_domains: HashSet::new(),
+            allowed_domains: None,
+            mx_resolver: None,
+            mx_timeout: DEFAULT_MX_TIMEOUT,
+        }
+    }
+}
+
+impl EmailDomainPolicy {
+    pub fn from_settings(
+        settings: &EmailDomainPolicySettings,
+        mx_resolver: impl FnOnce() -> Result<Box<dyn MxResolver>>,
+    ) -> Result<Self> {
+        let mut policy = EmailDomainPolicy::default();
+        if let Some(path) = &settings.blocklist_file {
+            policy = policy.with_blocklist_file(path)?;
+        }
+        if !settings.allowlist.is_empty() {
+            policy = policy.with_allowlist(settings.allowlist.iter().map(String::as_str))?;
+        }
+        if settings.check_mx {
+            policy = policy
+                .with_mx_resolver(mx_resolver()?)
+                .with_mx_timeout(settings.mx_timeout);
+        }
+        Ok(policy)
+    }
+
+    pub fn with_blocklist<'a>(
+        mut self,
+        domains: impl IntoIterator<Item = &'a str>,
+    ) -> Result<Self> {
+        for domain in domains {
+            self.blocked_domains.insert(normalize_domain(domain)?);
+        }
+        Ok(self)
+    }
+
+    // One domain per line; blank lines and lines starting with `#` are skipped.
+    pub fn with_blocklist_file(self, path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let contents =
+            std::fs::read_to_string(path).wrap_err(format!("failed to read {}", path.display()))?;
+        self.with_blocklist(
+            contents
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#')),
+        )
+        .wrap_err(format!("invalid domain in {}", path.display()))
+    }
+
+    pub fn with_allowlist<'a>(
+        mut self,
+        domains: impl IntoIterator<Item = &'a str>,
+    ) -> Result<Self> {
+        let allowed = self.allowed_domains.get_or_insert_with(HashSet::new);
+        for domain in domains {
+            allowed.insert(normalize_domain(domain)?);
+        }
+        Ok(self)
+    }
+
+    pub fn with_mx_resolver(mut self, mx_resolver: Box<dyn MxResolver>) -> Self {
+        self.mx_resolver = Some(mx_resolver);
+        self
+    }
+
+    pub fn with_mx_timeout(mut self, mx_timeout: Duration) -> Self {
+        self.mx_timeout = mx_timeout;
+        self
+    }
+
+    // The MX lookup runs last and fails open: if DNS itself fails or takes longer than
+    // `mx_timeout`, the signup goes ahead.
+    #[tracing::instrument(name = "Checking email domain policy", skip_all)]
+    pub async fn check(&self, email: &Email) -> Result<(), EmailDomainError> {
+        let domain = email.domain();
+        let is_literal = domain.starts_with('[');
+        if !is_literal && matches_any(domain, &self.blocked_domains) {
+            return Err(EmailDomainError::Blocked);
+        }
+        if let Some(allowed) = &self.allowed_domains {
+            if is_literal || !matches_any(domain, allowed) {
+                return Err(EmailDomainError::NotAllowed);
+            }
+        }
+        if let (Some(mx_resolver), false) = (&self.mx_resolver, is_literal) {
+            let has_mx = tokio::time::timeout(self.mx_timeout, mx_resolver.has_mx(domain))
+                .await
+                .unwrap_or_else(|_| Err(eyre!("MX lookup timed out after {:?}", self.mx_timeout)));
+            match has_mx {
+                Ok(true) => {}
+                Ok(false) => return Err(EmailDomainError::NoMailServer),
+                Err(e) => tracing::warn!("MX lookup failed, accepting domain: {:?}", e),
+            }
+        }
+        Ok(())
+    }
+}
+
+// The domain or any of its parent domains is in `domains`.
+fn matches_any(domain: &str, domains: &HashSet<String>) -> bool {
+    let mut suffix = domain;
+    loop {
+        if domains.contains(suffix) {
+            return true;
+        }
+        match suffix.split_once('.') {
+            Some((_, parent)) => suffix = parent,
+            None => return false,
+        }
+    }
+}
+
+// Lists are matched against normalised email domains, so they are normalised the same way.
+fn normalize_domain(domain: &str) -> Result<String> {
+    let domain = domain.trim().trim_end_matches('.');
+    idna::domain_to_ascii_strict(domain).wrap_err(format!("invalid domain {:?}", domain))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct StubMxResolver(Result<bool, String>);
+
+    #[async_trait::async_trait]
+    impl MxResolver for StubMxResolver {
+        async fn has_mx(&self, _: &str) -> Result<bool> {
+            self.0.clone().map_err(|e| eyre!(e))
+        }
+    }
+
+    async fn check(policy: &EmailDomainPolicy, email: &str) -> Result<(), EmailDomainError> {
+        policy.check(&Email::unwrap(email)).await
+    }
+
+    #[tokio::test]
+    async fn test_default_policy_accepts_everything() {
+        let policy = EmailDomainPolicy::default();
+        assert_eq!(check(&policy, "bob@mailinator.com").await, Ok(()));
+        assert_eq!(check(&policy, "bob@[192.0.2.1]").await, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_blocklist_covers_subdomains() {
+        let policy = EmailDomainPolicy::default()
+            .with_blocklist(["Mailinator.com", "bücher.de"])
+            .unwrap();
+        for email in [
+            "bob@mailinator.com",
+            "bob@eu.mailinator.com",
+            "bob@xn--bcher-kva.de",
+        ] {
+            assert_eq!(check(&policy, email).await, Err(EmailDomainError::Blocked));
+        }
+        assert_eq!(check(&policy, "bob@notmailinator.com").await, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_blocklist_file() {
+        let dir = std::env::temp_dir().join(format!("blocklist-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("disposable.txt");
+        std::fs::write(&path, "# disposable\n\nmailinator.com\n  yopmail.com  \n").unwrap();
+
+        let policy = EmailDomainPolicy::default()
+            .with_blocklist_file(&path)
+            .unwrap();
+        assert_eq!(
+            check(&policy, "bob@yopmail.com").await,
+            Err(EmailDomainError::Blocked)
+        );
+        assert_eq!(check(&policy, "bob@example.com").await, Ok(()));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_shipped_blocklist_is_valid() {
+        let policy = EmailDomainPolicy::default()
+            .with_blocklist_file("config/disposable_email_domains.txt")
+            .unwrap();
+        assert_eq!(
+            check(&policy, "bob@mailinator.com").await,
+            Err(EmailDomainError::Blocked)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_allowlist() {
+        let policy = EmailDomainPolicy::default()
+            .with_allowlist(["example.com"])
+            .unwrap();
+        assert_eq!(check(&policy, "bob@example.com").await, Ok(()));
+        assert_eq!(check(&policy, "bob@it.example.com").await, Ok(()));
+        for email in ["bob@example.org", "bob@[192.0.2.1]"] {
+            assert_eq!(
+                check(&policy, email).await,
+                Err(EmailDomainError::NotAllowed)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_blocklist_wins_over_allowlist() {
+        let policy = EmailDomainPolicy::default()
+            .with_allowlist(["example.com"])
+            .unwrap()
+            .with_blocklist(["guests.example.com"])
+            .unwrap();
+        assert_eq!(
+            check(&policy, "bob@guests.example.com").await,
+            Err(EmailDomainError::Blocked)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_mx_check() {
+        let without_mx =
+            EmailDomainPolicy::default().with_mx_resolver(Box::new(StubMxResolver(Ok(false))));
+        assert_eq!(
+            check(&without_mx, "bob@example.com").await,
+            Err(EmailDomainError::NoMailServer)
+        );
+        assert_eq!(check(&without_mx, "bob@[192.0.2.1]").await, Ok(()));
+
+        let with_mx =
+            EmailDomainPolicy::default().with_mx_resolver(Box::new(StubMxResolver(Ok(true))));
+        assert_eq!(check(&with_mx, "bob@example.com").await, Ok(()));
+
+        let failing = EmailDomainPolicy::default()
+            .with_mx_resolver(Box::new(StubMxResolver(Err("timeout".to_owned()))));
+        assert_eq!(check(&failing, "bob@example.com").await, Ok(()));
+    }
+
+    struct SlowMxResolver;
+
+    #[async_trait::async_trait]
+    impl MxResolver for SlowMxResolver {
+        async fn has_mx(&self, _: &str) -> Result<bool> {
+            tokio::time::sleep(Duration::from_secs(10)).await;
+            Ok(false)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_slow_mx_lookups_fail_open() {
+        let slow = EmailDomainPolicy::default()
+            .with_mx_resolver(Box::new(SlowMxResolver))
+            .with_mx_timeout(Duration::from_millis(50));
+        let started = std::time::Instant::now();
+        assert_eq!(check(&slow, "bob@example.com").await, Ok(()));
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_invalid_domains_are_rejected() {
+        assert!(EmailDomainPolicy::default()
+            .with_blocklist(["exa mple.com"])
+            .is_err());
+    }
+}
@@ -0,0 +1,8 @@
+// A rendered email, ready to be handed to an `EmailClient`.
+// Clients that support it send both bodies as `multipart/alternative`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmailMessage {
+    pub subject: String,
+    pub text_body: String,
+    pub html_body: String,
+}
@@ -1,5 +1,7 @@
-use crate::domain::data_stores::{TwoFACodeStoreError, UserStoreError};
+use crate::domain::data_stores::{EmailOutboxError, TwoFACodeStoreError, UserStoreError};
 use crate::domain::email::ParseError;
+use crate::domain::email_domain_policy::EmailDomainError;
+use crate::domain::language::LanguageError;
 use crate::domain::password::PasswordError;
 use crate::ErrorResponse;
 use axum::http::StatusCode;
@@ -30,11 +32,52 @@ pub enum AuthAPIError {
     MalformedRequest,
     #[error("InvalidLoginId")]
     InvalidLoginId,
+    #[error("Invalid password")]
+    InvalidPassword(#[source] PasswordError),
+    #[error("Unsupported language")]
+    UnsupportedLanguage,
+    #[error("Unauthorized")]
+    Unauthorized,
+    #[error("Not found")]
+    NotFound,
+    #[error("Email domain not allowed")]
+    EmailDomainNotAllowed(#[source] EmailDomainError),
+    #[error("Cross-site request")]
+    CrossSiteRequest,
+    #[error("Invalid CSRF token")]
+    InvalidCsrfToken,
+    #[error("Email expired")]
+    EmailExpired,
 }
+impl AuthAPIError {
+    // A stable, label-friendly name for the variant, e.g. the reason a login failed.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            AuthAPIError::UserAlreadyExists => "user_already_exists",
+            AuthAPIError::InvalidCredentials => "invalid_credentials",
+            AuthAPIError::IncorrectCredentials => "incorrect_credentials",
+            AuthAPIError::MissingToken => "missing_token",
+            AuthAPIError::InvalidToken => "invalid_token",
+            AuthAPIError::UnexpectedError(_) => "unexpected_error",
+            AuthAPIError::InvalidEmail => "invalid_email",
+            AuthAPIError::MalformedRequest => "malformed_request",
+            AuthAPIError::InvalidLoginId => "invalid_login_id",
+            AuthAPIError::InvalidPassword(_) => "invalid_password",
+            AuthAPIError::UnsupportedLanguage => "unsupported_language",
+            AuthAPIError::Unauthorized => "unauthorized",
+            AuthAPIError::NotFound => "not_found",
+            AuthAPIError::EmailDomainNotAllowed(_) => "email_domain_not_allowed",
+            AuthAPIError::CrossSiteRequest => "cross_site_request",
+            AuthAPIError::InvalidCsrfToken => "invalid_csrf_token",
+            AuthAPIError::EmailExpired => "email_expired",
+        }
+    }
+}
+
 impl IntoResponse for AuthAPIError {
     fn into_response(self) -> Response {
         log_error_chain(&self);
-        let (status, error_message) = match self {
+        let (status, error_message) = match &self {
             AuthAPIError::UserAlreadyExists => (StatusCode::CONFLICT, "User already exists"),
             AuthAPIError::InvalidCredentials => (StatusCode::BAD_REQUEST, "Invalid credentials"),
             AuthAPIError::InvalidLoginId => (StatusCode::BAD_REQUEST, "Invalid credentials"),
@@ -49,10 +92,31 @@ impl IntoResponse for AuthAPIError {
             AuthAPIError::IncorrectCredentials => (StatusCode::UNAUTHORIZED, "Wrong password"),
             AuthAPIError::MissingToken => (StatusCode::BAD_REQUEST, "Missing JWT Token"),
             AuthAPIError::InvalidToken => (StatusCode::UNAUTHORIZED, "Invalid JWT Token"),
+            AuthAPIError::UnsupportedLanguage => (StatusCode::BAD_REQUEST, "Unsupported language"),
+            AuthAPIError::Unauthorized => (StatusCode::UNAUTHORIZED, "Unauthorized"),
+            AuthAPIError::NotFound => (StatusCode::NOT_FOUND, "Not found"),
+            AuthAPIError::CrossSiteRequest => (StatusCode::FORBIDDEN, "Cross-site request refused"),
+            AuthAPIError::InvalidCsrfToken => {
+                (StatusCode::FORBIDDEN, "Missing or invalid CSRF token")
+            }
+            AuthAPIError::EmailExpired => (StatusCode::GONE, "Email has expired"),
+            // tell the user which rule the new password or email breaks
+            AuthAPIError::InvalidPassword(e) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(ErrorResponse::new(e.to_string())),
+                )
+                    .into_response()
+            }
+            AuthAPIError::EmailDomainNotAllowed(e) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(ErrorResponse::new(e.to_string())),
+                )
+                    .into_response()
+            }
         };
-        let body = Json(ErrorResponse {
-            error: error_message.to_string(),
-        });
+        let body = Json(ErrorResponse::new(error_message));
         (status, body).into_response()
     }
 }
@@ -82,6 +146,18 @@ impl From<ParseError> for AuthAPIError {
         }
     }
 }
+impl From<LanguageError> for AuthAPIError {
+    fn from(error: LanguageError) -> Self {
+        match error {
+            LanguageError::Unsupported => AuthAPIError::UnsupportedLanguage,
+        }
+    }
+}
+impl From<EmailDomainError> for AuthAPIError {
+    fn from(error: EmailDomainError) -> Self {
+        AuthAPIError::EmailDomainNotAllowed(error)
+    }
+}
 impl From<String> for AuthAPIError {
     fn from(error: String) -> Self {
         AuthAPIError::UnexpectedError(eyre!(error))
@@ -97,8 +173,20 @@ impl From<TwoFACodeStoreError> for AuthAPIError {
 }
 
 impl From<PasswordError> for AuthAPIError {
-    fn from(_error: PasswordError) -> Self {
-        AuthAPIError::InvalidCredentials
+    fn from(error: PasswordError) -> Self {
+        match error {
+            PasswordError::BreachCheckFailed => AuthAPIError::UnexpectedError(error.into()),
+            e => AuthAPIError::InvalidPassword(e),
+        }
+    }
+}
+impl From<EmailOutboxError> for AuthAPIError {
+    fn from(error: EmailOutboxError) -> Self {
+        match error {
+            EmailOutboxError::NotFound => AuthAPIError::NotFound,
+            EmailOutboxError::Expired => AuthAPIError::EmailExpired,
+            EmailOutboxError::UnexpectedError(e) => AuthAPIError::UnexpectedError(e),
+        }
     }
 }
 impl From<UserStoreError> for AuthAPIError {
@@ -111,6 +199,7 @@ impl From<UserStoreError> for AuthAPIError {
             UserStoreError::InvalidCredentials => AuthAPIError::InvalidCredentials,
             UserStoreError::UnexpectedError(e) => AuthAPIError::UnexpectedError(e),
             UserStoreError::InvalidPassword => AuthAPIError::IncorrectCredentials,
+            UserStoreError::InvalidNewPassword(e) => e.into(),
         }
     }
 }
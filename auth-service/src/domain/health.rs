@@ -0,0 +1,9 @@
+use color_eyre::eyre::Result;
+
+// A dependency `/health/ready` probes, such as a database or the email backend.
+#[async_trait::async_trait]
+pub trait HealthCheck: Send + Sync {
+    // the key the check is reported under
+    fn name(&self) -> &'static str;
+    async fn check(&self) -> Result<()>;
+}
@@ -0,0 +1,56 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, PartialEq, Clone, thiserror::Error)]
+pub enum LanguageError {
+    #[error("Language is not supported")]
+    Unsupported,
+}
+
+// The languages emails can be sent in. Users without a preference get English.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Language {
+    #[default]
+    En,
+    De,
+}
+
+impl Language {
+    // Accepts a language tag like `de` or `de-AT`; only the primary language is used.
+    pub fn parse(tag: &str) -> Result<Self, LanguageError> {
+        let primary = tag.split(['-', '_']).next().unwrap_or_default();
+        match primary.to_ascii_lowercase().as_str() {
+            "en" => Ok(Language::En),
+            "de" => Ok(Language::De),
+            _ => Err(LanguageError::Unsupported),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Language::En => "en",
+            Language::De => "de",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_language_tags() {
+        assert_eq!(Language::parse("en"), Ok(Language::En));
+        assert_eq!(Language::parse("de-AT"), Ok(Language::De));
+        assert_eq!(Language::parse("DE_de"), Ok(Language::De));
+        assert_eq!(Language::parse("fr"), Err(LanguageError::Unsupported));
+        assert_eq!(Language::parse(""), Err(LanguageError::Unsupported));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for language in [Language::En, Language::De] {
+            assert_eq!(Language::parse(language.as_str()), Ok(language));
+        }
+    }
+}
@@ -3,6 +3,13 @@ pub mod email;
 pub use email::*;
 pub mod email_client;
 pub use email_client::*;
+pub mod email_domain_policy;
+pub mod email_message;
+pub use email_message::*;
 pub mod error;
+pub mod health;
+pub mod language;
 pub mod password;
+pub mod password_hash;
+pub mod password_policy;
 pub mod user;
@@ -3,17 +3,47 @@ use secrecy::{ExposeSecret, Secret};
 
 #[derive(Debug, PartialEq, Clone, thiserror::Error)]
 pub enum PasswordError {
-    #[error("Password must be at least 8 characters long")]
-    TooShort,
-    #[error("Password must be at most 100 characters long")]
-    TooLong,
+    #[error("Password must not be empty")]
+    Empty,
+    #[error("Password must be at least {0} characters long")]
+    TooShort(usize),
+    #[error("Password must be at most {0} characters long")]
+    TooLong(usize),
     #[error("Password must contain at least one number")]
     NoNumber,
     #[error("Password must contain at least one special character")]
     NoSpecialCharacter,
+    #[error("Password must contain at least one uppercase letter")]
+    NoUppercase,
+    #[error("Password must contain at least one lowercase letter")]
+    NoLowercase,
+    #[error("Password is too easy to guess")]
+    TooWeak,
+    #[error("Password has appeared in a data breach, please choose another one")]
+    Breached,
+    #[error("Password could not be checked against breached passwords")]
+    BreachCheckFailed,
+    // `password.history_size`, the number of previous passwords besides the current one
+    #[error("{}", recently_used_message(*.0))]
+    RecentlyUsed(usize),
 }
 
-#[derive(sqlx::FromRow, Debug, Clone)]
+fn recently_used_message(history_size: usize) -> String {
+    match history_size {
+        0 => "Password must not be your current password".to_owned(),
+        n => format!(
+            "Password must not be your current password or one of your {} previous ones",
+            n
+        ),
+    }
+}
+
+// Upper bound for any password we accept at all, so that hashing cost stays bounded.
+// The rules a new password has to follow live in `PasswordPolicy`.
+pub const MAX_PASSWORD_LENGTH: usize = 1024;
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "postgres", derive(sqlx::FromRow))]
 pub struct Password(Secret<String>);
 
 impl PartialEq for Password {
@@ -27,29 +57,15 @@ impl PartialEq for Password {
 
 impl Password {
     pub fn parse(password: Secret<String>) -> Result<Self, PasswordError> {
-        if password.expose_secret().len() < 8 {
-            return Err(PasswordError::TooShort);
+        if password.expose_secret().is_empty() {
+            return Err(PasswordError::Empty);
         }
-        if password.expose_secret().len() > 100 {
-            return Err(PasswordError::TooLong);
-        }
-        if !password.expose_secret().chars().any(|c| c.is_numeric()) {
-            return Err(PasswordError::NoNumber);
-        }
-        if !password
-            .expose_secret()
-            .chars()
-            .any(|c| "!§@#$%^&*()_+-=[]{}|;:,.<>?".contains(c))
-        {
-            return Err(PasswordError::NoSpecialCharacter);
+        if password.expose_secret().chars().count() > MAX_PASSWORD_LENGTH {
+            return Err(PasswordError::TooLong(MAX_PASSWORD_LENGTH));
         }
 
         Ok(Password(password))
     }
-    //
-    // pub fn unwrap(password: &str) -> Self {
-    //     Self::parse(password.to_string()).unwrap()
-    // }
 }
 impl AsRef<Secret<String>> for Password {
     // Updated!
@@ -63,16 +79,22 @@ mod tests {
     use super::*;
 
     #[test]
-    fn empty_string_is_rejected() {
-        let password = Secret::new("".to_string()); // Updated!
-        assert!(Password::parse(password).is_err());
+    fn recently_used_names_the_history_size() {
+        assert_eq!(
+            PasswordError::RecentlyUsed(5).to_string(),
+            "Password must not be your current password or one of your 5 previous ones"
+        );
+        assert_eq!(
+            PasswordError::RecentlyUsed(0).to_string(),
+            "Password must not be your current password"
+        );
     }
+
     #[test]
-    fn string_less_than_8_characters_is_rejected() {
-        let password = Secret::new("1234567".to_string()); // Updated!
+    fn empty_string_is_rejected() {
+        let password = Secret::new("".to_string()); // Updated!
         assert!(Password::parse(password).is_err());
     }
-
     #[test]
     fn test_valid_password() {
         let password = Secret::new("password123!".to_string());
@@ -84,28 +106,13 @@ mod tests {
         );
     }
 
-    #[test]
-    fn test_too_short_password() {
-        let result = Password::parse(Secret::new("short".to_string()));
-        assert_eq!(result.err().unwrap(), PasswordError::TooShort);
-    }
-
     #[test]
     fn test_too_long_password() {
-        let result = Password::parse(Secret::new("x".repeat(101)));
-        assert_eq!(result.err().unwrap(), PasswordError::TooLong);
-    }
-
-    #[test]
-    fn test_password_without_number() {
-        let result = Password::parse(Secret::new("password!".to_string()));
-        assert_eq!(result.err().unwrap(), PasswordError::NoNumber);
-    }
-
-    #[test]
-    fn test_password_without_special_character() {
-        let result = Password::parse(Secret::new("password123".to_string()));
-        assert_eq!(result.err().unwrap(), PasswordError::NoSpecialCharacter);
+        let result = Password::parse(Secret::new("ü".repeat(MAX_PASSWORD_LENGTH + 1)));
+        assert_eq!(
+            result.err().unwrap(),
+            PasswordError::TooLong(MAX_PASSWORD_LENGTH)
+        );
     }
 
     // #[derive(Debug, Clone)]
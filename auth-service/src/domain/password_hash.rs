@@ -0,0 +1,132 @@
+use secrecy::{ExposeSecret, Secret};
+use std::str::FromStr;
+
+#[derive(Debug, PartialEq, Clone, thiserror::Error)]
+pub enum PasswordHashError {
+    #[error("Password hash is not in PHC string format")]
+    InvalidFormat,
+    #[error("Password hash algorithm is not supported")]
+    UnsupportedAlgorithm,
+}
+
+// The algorithms a stored hash may have been produced with. Only Argon2 is used for new
+// hashes; the others exist for users imported from older systems.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum HashAlgorithm {
+    Argon2,
+    Pbkdf2,
+    Scrypt,
+    Bcrypt,
+}
+
+impl HashAlgorithm {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HashAlgorithm::Argon2 => "argon2",
+            HashAlgorithm::Pbkdf2 => "pbkdf2",
+            HashAlgorithm::Scrypt => "scrypt",
+            HashAlgorithm::Bcrypt => "bcrypt",
+        }
+    }
+}
+
+// A password hash in PHC string format, e.g. `$argon2id$v=19$m=15000,t=2,p=1$...`,
+// or a bcrypt hash in its modular crypt format, e.g. `$2b$12$...`.
+// This is the only form in which a password is ever kept by a user store.
+#[derive(Debug, Clone)]
+pub struct PasswordHash {
+    hash: Secret<String>,
+    algorithm: HashAlgorithm,
+}
+
+impl PartialEq for PasswordHash {
+    fn eq(&self, other: &Self) -> bool {
+        self.hash.expose_secret() == other.hash.expose_secret()
+    }
+}
+
+const BCRYPT_PREFIXES: [&str; 4] = ["$2a$", "$2b$", "$2x$", "$2y$"];
+
+impl PasswordHash {
+    pub fn parse(hash: String) -> Result<Self, PasswordHashError> {
+        let algorithm = if BCRYPT_PREFIXES.iter().any(|p| hash.starts_with(p)) {
+            bcrypt::HashParts::from_str(&hash).map_err(|_| PasswordHashError::InvalidFormat)?;
+            HashAlgorithm::Bcrypt
+        } else {
+            let phc =
+                argon2::PasswordHash::new(&hash).map_err(|_| PasswordHashError::InvalidFormat)?;
+            match phc.algorithm.as_str() {
+                "argon2i" | "argon2d" | "argon2id" => HashAlgorithm::Argon2,
+                "pbkdf2" | "pbkdf2-sha256" | "pbkdf2-sha512" => HashAlgorithm::Pbkdf2,
+                "scrypt" => HashAlgorithm::Scrypt,
+                _ => return Err(PasswordHashError::UnsupportedAlgorithm),
+            }
+        };
+        Ok(PasswordHash {
+            hash: Secret::new(hash),
+            algorithm,
+        })
+    }
+
+    pub fn algorithm(&self) -> HashAlgorithm {
+        self.algorithm
+    }
+}
+
+impl AsRef<Secret<String>> for PasswordHash {
+    fn as_ref(&self) -> &Secret<String> {
+        &self.hash
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_valid_phc_string() {
+        let hash = "$argon2id$v=19$m=15000,t=2,p=1$c29tZXNhbHQ$wPB3NvGcZHhkoUdcKVDSYW1tiabnqsrIaeHx4Ctw0rQ";
+        let result = PasswordHash::parse(hash.to_string());
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap().as_ref().expose_secret(), hash);
+    }
+
+    #[test]
+    fn test_plaintext_is_rejected() {
+        let result = PasswordHash::parse("password123!".to_string());
+        assert_eq!(result.err().unwrap(), PasswordHashError::InvalidFormat);
+    }
+
+    #[test]
+    fn test_legacy_algorithms() {
+        let cases = [
+            (
+                "$2b$04$SU6FTRoLIY.i0hQraKudM..2OSoN.5OhSUBeIH9K4ogTHVQAXUJDa",
+                HashAlgorithm::Bcrypt,
+            ),
+            (
+                "$pbkdf2-sha256$i=1000,l=32$YGmqm6LXbGZvFpdIjzHFUg$OzJs5ERvlG6WXYAnJLYxyEotp9AkJoV3MV7iGocnnqE",
+                HashAlgorithm::Pbkdf2,
+            ),
+            (
+                "$scrypt$ln=4,r=8,p=1$YGmqm6LXbGZvFpdIjzHFUg$9lFOnyFiO+s84bveuP7QqPzT2f7M/gVPlruYwJ56aMg",
+                HashAlgorithm::Scrypt,
+            ),
+        ];
+        for (hash, algorithm) in cases {
+            let result = PasswordHash::parse(hash.to_string()).unwrap();
+            assert_eq!(result.algorithm(), algorithm);
+        }
+    }
+
+    #[test]
+    fn test_unsupported_algorithm_is_rejected() {
+        let result = PasswordHash::parse("$balloon-sha256$s=1024,t=3,p=1$YGmqm6LXbGZvFpdIjzHFUg$OzJs5ERvlG6WXYAnJLYxyEotp9AkJoV3MV7iGocnnqE".to_string());
+        assert_eq!(
+            result.err().unwrap(),
+            PasswordHashError::UnsupportedAlgorithm
+        );
+        let result = PasswordHash::parse("$2b$04$tooshort".to_string());
+        assert_eq!(result.err().unwrap(), PasswordHashError::InvalidFormat);
+    }
+}
@@ -0,0 +1,262 @@
+use std::path::PathBuf;
+
+use color_eyre::eyre::{Context, Result};
+use secrecy::ExposeSecret;
+use serde::Deserialize;
+use sha1::{Digest, Sha1};
+
+use crate::domain::password::{Password, PasswordError};
+
+// The rules a password has to follow whenever it is set, e.g. on signup.
+// Logins only verify against the stored hash, so tightening the policy never locks anyone out.
+// Lengths are counted in Unicode characters, not bytes.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    pub max_length: usize,
+    pub require_number: bool,
+    pub require_special_character: bool,
+    pub require_uppercase: bool,
+    pub require_lowercase: bool,
+    // Minimum score from `strength_score`, 0 (anything goes) to 4 (very hard to guess).
+    pub min_strength: u8,
+    // Directory of Have I Been Pwned range files, one `<first five hex digits of SHA-1>.txt`
+    // per prefix, each line holding the remaining 35 hex digits and a count: `<suffix>:<count>`.
+    pub breached_passwords_dir: Option<PathBuf>,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        PasswordPolicy {
+            min_length: 8,
+            max_length: 100,
+            require_number: true,
+            require_special_character: true,
+            require_uppercase: false,
+            require_lowercase: false,
+            min_strength: 0,
+            breached_passwords_dir: None,
+        }
+    }
+}
+
+impl PasswordPolicy {
+    // Checks the cheap rules first, so the breached password lookup only runs for otherwise
+    // acceptable passwords.
+    #[tracing::instrument(name = "Checking password policy", skip_all)]
+    pub async fn check(&self, password: &Password) -> Result<(), PasswordError> {
+        let password = password.as_ref().expose_secret();
+        let length = password.chars().count();
+        if length < self.min_length {
+            return Err(PasswordError::TooShort(self.min_length));
+        }
+        if length > self.max_length {
+            return Err(PasswordError::TooLong(self.max_length));
+        }
+        if self.require_number && !password.chars().any(|c| c.is_numeric()) {
+            return Err(PasswordError::NoNumber);
+        }
+        if self.require_special_character && !password.chars().any(is_special_character) {
+            return Err(PasswordError::NoSpecialCharacter);
+        }
+        if self.require_uppercase && !password.chars().any(char::is_uppercase) {
+            return Err(PasswordError::NoUppercase);
+        }
+        if self.require_lowercase && !password.chars().any(char::is_lowercase) {
+            return Err(PasswordError::NoLowercase);
+        }
+        if strength_score(password) < self.min_strength {
+            return Err(PasswordError::TooWeak);
+        }
+        if let Some(dir) = &self.breached_passwords_dir {
+            let breached = is_breached(dir, password).await.map_err(|e| {
+                tracing::warn!("breached password lookup failed: {:?}", e);
+                PasswordError::BreachCheckFailed
+            })?;
+            if breached {
+                return Err(PasswordError::Breached);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn is_special_character(c: char) -> bool {
+    !c.is_alphanumeric() && !c.is_whitespace()
+}
+
+// A rough, zxcvbn-style estimate of how hard a password is to guess, from 0 to 4.
+// Guesses are estimated from the size of the character pool and the length, where repeated
+// characters and runs like "abc" or "321" count for less. The score buckets follow zxcvbn:
+// fewer than 10^3, 10^6, 10^8 and 10^10 guesses give 0, 1, 2 and 3.
+pub fn strength_score(password: &str) -> u8 {
+    let chars: Vec<char> = password.chars().collect();
+    let mut pool = 0;
+    if chars.iter().any(|c| c.is_ascii_lowercase()) {
+        pool += 26;
+    }
+    if chars.iter().any(|c| c.is_ascii_uppercase()) {
+        pool += 26;
+    }
+    if chars.iter().any(|c| c.is_ascii_digit()) {
+        pool += 10;
+    }
+    if chars
+        .iter()
+        .any(|c| c.is_ascii() && is_special_character(*c))
+    {
+        pool += 33;
+    }
+    if chars.iter().any(|c| !c.is_ascii()) {
+        pool += 100;
+    }
+    if pool == 0 {
+        return 0;
+    }
+
+    let mut effective_length = 0.0;
+    for (i, c) in chars.iter().enumerate() {
+        let previous = i.checked_sub(1).map(|j| chars[j] as i64);
+        effective_length += match previous {
+            Some(p) if p == *c as i64 => 0.25,
+            Some(p) if (p - *c as i64).abs() == 1 => 0.5,
+            _ => 1.0,
+        };
+    }
+
+    let log10_guesses = effective_length * (pool as f64).log10();
+    match log10_guesses {
+        g if g < 3.0 => 0,
+        g if g < 6.0 => 1,
+        g if g < 8.0 => 2,
+        g if g < 10.0 => 3,
+        _ => 4,
+    }
+}
+
+async fn is_breached(dir: &std::path::Path, password: &str) -> Result<bool> {
+    let digest = hex::encode_upper(Sha1::digest(password.as_bytes()));
+    let (prefix, suffix) = digest.split_at(5);
+    let range_file = dir.join(format!("{}.txt", prefix));
+    let range = match tokio::fs::read_to_string(&range_file).await {
+        Ok(range) => range,
+        // a local copy may only hold some of the ranges
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => {
+            return Err(e).wrap_err(format!("failed to read {}", range_file.display()));
+        }
+    };
+    Ok(range.lines().any(|line| match line.trim().split_once(':') {
+        // padding entries added by the HIBP API have a count of 0
+        Some((s, count)) => s.eq_ignore_ascii_case(suffix) && count.trim() != "0",
+        None => false,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use secrecy::Secret;
+
+    fn password(p: &str) -> Password {
+        Password::parse(Secret::new(p.to_string())).unwrap()
+    }
+
+    async fn check(policy: &PasswordPolicy, p: &str) -> Result<(), PasswordError> {
+        policy.check(&password(p)).await
+    }
+
+    #[tokio::test]
+    async fn test_default_policy() {
+        let policy = PasswordPolicy::default();
+        assert_eq!(check(&policy, "password123!").await, Ok(()));
+        assert_eq!(
+            check(&policy, "1234567").await,
+            Err(PasswordError::TooShort(8))
+        );
+        assert_eq!(
+            check(&policy, &"x".repeat(101)).await,
+            Err(PasswordError::TooLong(100))
+        );
+        assert_eq!(
+            check(&policy, "password!").await,
+            Err(PasswordError::NoNumber)
+        );
+        assert_eq!(
+            check(&policy, "password123").await,
+            Err(PasswordError::NoSpecialCharacter)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_length_counts_characters() {
+        let policy = PasswordPolicy {
+            max_length: 10,
+            require_number: false,
+            require_special_character: false,
+            ..PasswordPolicy::default()
+        };
+        // 10 characters, but 20 bytes
+        assert_eq!(check(&policy, "ääääääääää").await, Ok(()));
+        assert_eq!(check(&policy, "äää").await, Err(PasswordError::TooShort(8)));
+    }
+
+    #[tokio::test]
+    async fn test_passphrase_policy() {
+        let policy = PasswordPolicy {
+            min_length: 15,
+            require_number: false,
+            require_special_character: false,
+            require_uppercase: true,
+            min_strength: 4,
+            ..PasswordPolicy::default()
+        };
+        assert_eq!(check(&policy, "Correct horse battery staple").await, Ok(()));
+        assert_eq!(
+            check(&policy, "correct horse battery staple").await,
+            Err(PasswordError::NoUppercase)
+        );
+        assert_eq!(
+            check(&policy, "Aaaaaaaaaaaaaaa").await,
+            Err(PasswordError::TooWeak)
+        );
+    }
+
+    #[test]
+    fn test_strength_score() {
+        assert_eq!(strength_score("aaa"), 0);
+        assert_eq!(strength_score("111111"), 0);
+        assert!(strength_score("abcdefgh") < strength_score("kqzwtmpa"));
+        assert_eq!(strength_score("Tr0ub4dor&3 correct horse"), 4);
+    }
+
+    #[tokio::test]
+    async fn test_breached_password() {
+        let dir = std::env::temp_dir().join(format!("hibp-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        // SHA-1("password123!") = ADDBD3AA5619F2932733104EB8CEEF08F6FD2693
+        std::fs::write(
+            dir.join("ADDBD.txt"),
+            "0018A45C4D1DEF81644B54AB7F969B88D65:3\r\n3AA5619F2932733104EB8CEEF08F6FD2693:3024\r\n",
+        )
+        .unwrap();
+        // SHA-1("not-in-the-list-42") = E005FBF090A73B13CBE714885573CF12E75507CF, as padding
+        std::fs::write(
+            dir.join("E005F.txt"),
+            "BF090A73B13CBE714885573CF12E75507CF:0\r\n",
+        )
+        .unwrap();
+        let policy = PasswordPolicy {
+            breached_passwords_dir: Some(dir.clone()),
+            ..PasswordPolicy::default()
+        };
+
+        assert_eq!(
+            check(&policy, "password123!").await,
+            Err(PasswordError::Breached)
+        );
+        assert_eq!(check(&policy, "not-in-the-list-42").await, Ok(()));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
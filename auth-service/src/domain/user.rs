@@ -1,20 +1,26 @@
 use crate::domain::email::{Email, ParseError};
+use crate::domain::language::{Language, LanguageError};
 use crate::domain::password::{Password, PasswordError};
+use crate::domain::password_hash::{PasswordHash, PasswordHashError};
+use crate::services::password_hasher::compute_password_hash;
 use secrecy::Secret;
 use thiserror::Error;
 
-// The User struct should contain 3 fields. email, which is a String;
-// password, which is also a String; and requires_2fa, which is a boolean.
-#[derive(sqlx::FromRow, Debug, PartialEq, Clone)]
+// The User struct contains the email; password_hash, which only ever holds a hash;
+// requires_2fa, which is a boolean; and the language emails to the user are written in.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "postgres", derive(sqlx::FromRow))]
 pub struct User {
     pub email: Email,
-    pub password_hash: Password,
+    pub password_hash: PasswordHash,
     pub requires_2fa: bool,
+    pub language: Language,
 }
 pub struct UserRow {
     pub email: String,
     pub password_hash: String,
     pub requires_2fa: bool,
+    pub language: String,
 }
 
 #[derive(Debug, Clone, Error)]
@@ -23,6 +29,12 @@ pub enum UserError {
     EmailError(ParseError),
     #[error("PasswordError")]
     PasswordError(PasswordError),
+    #[error("PasswordHashError")]
+    PasswordHashError(PasswordHashError),
+    #[error("LanguageError")]
+    LanguageError(LanguageError),
+    #[error("HashingError")]
+    HashingError,
     #[error("DBLoadError")]
     DBLoadError,
 }
@@ -38,31 +50,53 @@ impl From<PasswordError> for UserError {
     }
 }
 
+impl From<PasswordHashError> for UserError {
+    fn from(error: PasswordHashError) -> Self {
+        UserError::PasswordHashError(error)
+    }
+}
+
+impl From<LanguageError> for UserError {
+    fn from(error: LanguageError) -> Self {
+        UserError::LanguageError(error)
+    }
+}
+
 impl TryFrom<UserRow> for User {
     type Error = UserError;
 
     fn try_from(row: UserRow) -> Result<Self, Self::Error> {
         Ok(User {
             email: Email::parse(row.email)?,
-            password_hash: Password::parse(Secret::new(row.password_hash))?,
+            password_hash: PasswordHash::parse(row.password_hash)?,
             requires_2fa: row.requires_2fa,
+            language: Language::parse(&row.language)?,
         })
     }
 }
 
 impl User {
-    pub fn new(email: &str, password: &str, requires_2fa: bool) -> Result<Self, UserError> {
+    pub async fn new(email: &str, password: &str, requires_2fa: bool) -> Result<Self, UserError> {
+        let password = Password::parse(Secret::new(password.to_string()))?;
+        let password_hash = compute_password_hash(&password)
+            .await
+            .map_err(|_| UserError::HashingError)?;
         Ok(User {
             email: Email::parse(email.to_string())?,
-            password_hash: Password::parse(Secret::new(password.to_string()))?,
+            password_hash,
             requires_2fa,
+            language: Language::default(),
         })
     }
-    pub fn new2(email: Email, password: Password, requires_2fa: bool) -> Self {
+    pub fn new2(email: Email, password_hash: PasswordHash, requires_2fa: bool) -> Self {
         User {
             email,
-            password_hash: password,
+            password_hash,
             requires_2fa,
+            language: Language::default(),
         }
     }
+    pub fn with_language(self, language: Language) -> Self {
+        User { language, ..self }
+    }
 }
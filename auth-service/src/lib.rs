@@ -1,54 +1,95 @@
-use crate::app_state::AppState;
-use crate::routes::{login, logout, signup, verify_2fa, verify_token};
-use http::Method;
-
+use crate::app_state::backends::Connections;
+use crate::app_state::{AppState, UserStoreType};
+use crate::routes::{
+    change_password, csrf_token, get_metrics, health_live, health_ready, list_dead_letters, login,
+    logout, retry_dead_letter, signup, update_language, verify_2fa, verify_token,
+};
+use crate::services::email_outbox_worker::EmailOutboxWorker;
+use crate::settings::Settings;
+use crate::util::cors;
+use crate::util::csrf::protect_from_csrf;
+use crate::util::metrics::{audit_password_hashes, track_http_metrics};
+use crate::util::request_id::{current_request_id, propagate_request_id};
+use crate::util::security_headers::{set_security_headers, SecurityHeaders};
+use crate::util::tls;
 use crate::util::tracing::{make_span_with_request_id, on_request, on_response};
-use axum::routing::get;
-use axum::routing::post;
+use axum::routing::{get, post};
 use axum::serve::Serve;
 use axum::Router;
-use http;
-use redis::{Client, RedisResult};
+use axum_server::tls_rustls::RustlsConfig;
 use serde::{Deserialize, Serialize};
-use sqlx::postgres::PgPoolOptions;
-use sqlx::PgPool;
 use std::error::Error;
+use std::future::{Future, IntoFuture};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use tower_http::services::ServeFile;
+use tokio::net::TcpListener;
+use tokio::sync::watch;
+use tower_http::services::{ServeDir, ServeFile};
 use tower_http::trace::TraceLayer;
-use tower_http::{cors::CorsLayer, services::ServeDir};
 
 pub mod app_state;
 pub mod domain;
 pub mod routes;
 pub mod services;
+pub mod settings;
 pub mod util;
 
 // This struct encapsulates our application-related logic.
 pub struct Application {
-    server: Serve<Router, Router>,
+    listener: TcpListener,
+    router: Router,
+    // served over HTTPS with this if `tls.enabled`
+    tls_config: Option<RustlsConfig>,
     // address is exposed as a public field
     // so we have access to it in tests.
     pub address: String,
+    // `/metrics` on its own port, if `metrics.port` is set
+    metrics_server: Option<Serve<Router, Router>>,
+    pub metrics_address: Option<String>,
+    // plain HTTP redirecting to HTTPS, if `tls.redirect_port` is set
+    redirect_server: Option<Serve<Router, Router>>,
+    pub redirect_address: Option<String>,
+    settings: Arc<Settings>,
+    // delivers the email outbox while serving, stopped together with the listeners
+    outbox_worker: EmailOutboxWorker,
+    // the store whose password hashes feed the metrics, if `metrics.enabled`
+    password_hash_audit: Option<UserStoreType>,
+    // shared with the app state, to fail readiness while shutting down
+    draining: Arc<AtomicBool>,
+    connections: Arc<Connections>,
 }
 
 impl Application {
-    pub async fn build(app_state: AppState, address: &str) -> Result<Self, Box<dyn Error>> {
+    // Listens on `application.host` and `application.port` from the app state's settings.
+    pub async fn build(app_state: AppState) -> Result<Self, Box<dyn Error>> {
+        let settings = Arc::clone(&app_state.settings);
+        let draining = Arc::clone(&app_state.draining);
+        let connections = Arc::clone(&app_state.connections);
         let asset_dir =
             ServeDir::new("assets").not_found_service(ServeFile::new("assets/index.html"));
-        let allowed_origins = [
-            "http://localhost:8000".parse()?,
-            // TODO: Replace [YOUR_DROPLET_IP] with your Droplet IP address
-            // "http://[YOUR_DROPLET_IP]:8000".parse()?,
-        ];
-
-        let cors = CorsLayer::new()
-            .allow_methods([Method::GET, Method::POST])
-            .allow_credentials(true)
-            .allow_origin(allowed_origins);
-
-        let router = Router::new()
-            .fallback_service(asset_dir)
+        let mut ui = Router::new().fallback_service(asset_dir);
+        if settings.security_headers.enabled {
+            ui = ui.layer(axum::middleware::from_fn_with_state(
+                SecurityHeaders::ui(&settings.security_headers),
+                set_security_headers,
+            ));
+        }
+        let cors = cors::cors_layer(&settings.cors)?;
+
+        let outbox_worker = EmailOutboxWorker::new(
+            Arc::clone(&app_state.email_outbox),
+            Arc::clone(&app_state.email_client),
+            settings.email.outbox.clone(),
+        );
+        let password_hash_audit = settings
+            .metrics
+            .enabled
+            .then(|| Arc::clone(&app_state.user_store));
+
+        let state = Arc::new(app_state);
+        let mut router = Router::new()
+            .fallback_service(ui)
             // .nest_service("/", ServeDir::new("assets"))
             // .route("/", get(login))
             .route("/signup", post(signup))
@@ -56,38 +97,292 @@ impl Application {
             .route("/verify-2fa", post(verify_2fa))
             .route("/logout", post(logout))
             .route("/verify-token", post(verify_token))
-            .with_state(Arc::new(app_state))
-            .layer(cors)
+            .route("/change-password", post(change_password))
+            .route("/language", post(update_language))
+            .route("/admin/email-outbox/dead", get(list_dead_letters))
+            .route(
+                "/admin/email-outbox/dead/:id/retry",
+                post(retry_dead_letter),
+            )
+            .route("/health/live", get(health_live))
+            .route("/health/ready", get(health_ready));
+        if settings.csrf.enabled {
+            router = router.route("/csrf-token", get(csrf_token)).layer(
+                axum::middleware::from_fn_with_state(Arc::clone(&state), protect_from_csrf),
+            );
+        }
+        let mut router = router.with_state(state);
+        let metrics_address = settings.metrics.address(&settings.application);
+        if settings.metrics.enabled {
+            if metrics_address.is_none() {
+                router = router.route("/metrics", get(get_metrics));
+            }
+            router = router.layer(axum::middleware::from_fn(track_http_metrics));
+        }
+        let mut router = router.layer(cors);
+        // the UI's own headers are already set, this fills in the rest
+        if settings.security_headers.enabled {
+            router = router.layer(axum::middleware::from_fn_with_state(
+                SecurityHeaders::api(&settings.security_headers),
+                set_security_headers,
+            ));
+        }
+        let router = router
             .layer(
                 TraceLayer::new_for_http()
                     .make_span_with(make_span_with_request_id)
                     .on_request(on_request)
                     .on_response(on_response),
-            );
-        let listener = tokio::net::TcpListener::bind(address).await?;
-        let address = listener.local_addr()?.to_string();
-        let server = axum::serve(listener, router);
+            )
+            // outermost, so the request span already sees the id
+            .layer(axum::middleware::from_fn(propagate_request_id));
+        let listener = TcpListener::bind(settings.application.address()).await?;
+        let local_address = listener.local_addr()?;
+        let address = local_address.to_string();
+        let tls_config = match settings.tls.enabled {
+            true => Some(RustlsConfig::from_config(tls::load_server_config(
+                &settings.tls,
+            )?)),
+            false => None,
+        };
+
+        let (metrics_server, metrics_address) = match metrics_address {
+            Some(metrics_address) => {
+                let listener = tokio::net::TcpListener::bind(metrics_address).await?;
+                let metrics_address = listener.local_addr()?.to_string();
+                let router = Router::new().route("/metrics", get(get_metrics));
+                (Some(axum::serve(listener, router)), Some(metrics_address))
+            }
+            None => (None, None),
+        };
+        let (redirect_server, redirect_address) =
+            match settings.tls.redirect_address(&settings.application) {
+                Some(redirect_address) => {
+                    let listener = TcpListener::bind(redirect_address).await?;
+                    let redirect_address = listener.local_addr()?.to_string();
+                    let router =
+                        tls::redirect_router(settings.tls.public_port(local_address.port()));
+                    (Some(axum::serve(listener, router)), Some(redirect_address))
+                }
+                None => (None, None),
+            };
 
         // Create a new Application instance and return it
-        Ok(Application { server, address })
+        Ok(Application {
+            listener,
+            router,
+            tls_config,
+            address,
+            metrics_server,
+            metrics_address,
+            redirect_server,
+            redirect_address,
+            settings,
+            outbox_worker,
+            password_hash_audit,
+            draining,
+            connections,
+        })
     }
 
+    // Serves until SIGTERM or SIGINT, then shuts down gracefully.
     pub async fn run(self) -> Result<(), std::io::Error> {
-        tracing::info!("listening on {}", &self.address);
-        self.server.await
+        self.run_until(shutdown_signal()).await
+    }
+
+    // Serves until `signal` completes. Then `/health/ready` fails for `shutdown.readiness_delay`
+    // while requests are still accepted, the listeners close and in-flight requests get
+    // `shutdown.drain_timeout` to finish, as does the email outbox worker's current pass.
+    // Postgres and Redis are closed last, within
+    // `shutdown.close_timeout`.
+    pub async fn run_until(
+        self,
+        signal: impl Future<Output = ()> + Send + 'static,
+    ) -> Result<(), std::io::Error> {
+        let (stop, stopped) = watch::channel(false);
+        let mut reload_tls = None;
+        let server: Pin<Box<dyn Future<Output = std::io::Result<()>> + Send>> =
+            match self.tls_config {
+                Some(tls_config) => {
+                    tracing::info!("listening on {} (HTTPS)", &self.address);
+                    reload_tls = Some(tokio::spawn(tls::reload_on_change(
+                        tls_config.clone(),
+                        self.settings.tls.clone(),
+                    )));
+                    let handle = axum_server::Handle::new();
+                    tokio::spawn({
+                        let handle = handle.clone();
+                        let stopped = stopped.clone();
+                        async move {
+                            wait_for_stop(stopped).await;
+                            handle.graceful_shutdown(None);
+                        }
+                    });
+                    Box::pin(
+                        axum_server::from_tcp_rustls(self.listener.into_std()?, tls_config)
+                            .handle(handle)
+                            .serve(self.router.into_make_service()),
+                    )
+                }
+                None => {
+                    tracing::info!("listening on {}", &self.address);
+                    Box::pin(
+                        axum::serve(self.listener, self.router)
+                            .with_graceful_shutdown(wait_for_stop(stopped.clone()))
+                            .into_future(),
+                    )
+                }
+            };
+        let metrics_server = serve_until_stopped(
+            self.metrics_server,
+            self.metrics_address.as_deref(),
+            "serving metrics",
+            stopped.clone(),
+        );
+        let redirect_server = serve_until_stopped(
+            self.redirect_server,
+            self.redirect_address.as_deref(),
+            "redirecting to HTTPS",
+            stopped.clone(),
+        );
+        // drained like a request: emails it is sending when the drain timeout is up are sent
+        // again once their lease runs out
+        let password_hash_audit = {
+            let stopped = stopped.clone();
+            let interval = self.settings.metrics.password_hash_audit_interval;
+            async move {
+                if let Some(user_store) = self.password_hash_audit {
+                    audit_password_hashes(user_store, interval, stopped).await;
+                }
+                Ok(())
+            }
+        };
+        let outbox_worker = async {
+            self.outbox_worker.run(stopped).await;
+            Ok(())
+        };
+        let serve = async {
+            tokio::try_join!(
+                server,
+                metrics_server,
+                redirect_server,
+                outbox_worker,
+                password_hash_audit
+            )
+            .map(|_| ())
+        };
+        tokio::pin!(serve);
+
+        let draining = self.draining;
+        let shutdown = self.settings.shutdown.clone();
+        let begin_shutdown = async move {
+            signal.await;
+            tracing::info!("shutting down, failing readiness");
+            draining.store(true, Ordering::Relaxed);
+            tokio::time::sleep(shutdown.readiness_delay).await;
+            tracing::info!("draining in-flight requests");
+            // the receivers live as long as the servers
+            let _ = stop.send(true);
+        };
+
+        tokio::select! {
+            result = &mut serve => result?,
+            _ = begin_shutdown => {
+                match tokio::time::timeout(shutdown.drain_timeout, &mut serve).await {
+                    Ok(result) => result?,
+                    Err(_) => tracing::warn!(
+                        "requests still in flight after {:?}, dropping them",
+                        shutdown.drain_timeout
+                    ),
+                }
+            }
+        }
+        if let Some(reload_tls) = reload_tls {
+            reload_tls.abort();
+        }
+        let closed = tokio::time::timeout(shutdown.close_timeout, self.connections.close()).await;
+        if closed.is_err() {
+            tracing::warn!(
+                "connections still in use after {:?}, exiting anyway",
+                shutdown.close_timeout
+            );
+        }
+        tracing::info!("shutdown complete");
+        Ok(())
+    }
+}
+
+// One of the plain HTTP side servers, if configured.
+async fn serve_until_stopped(
+    server: Option<Serve<Router, Router>>,
+    address: Option<&str>,
+    what: &str,
+    stopped: watch::Receiver<bool>,
+) -> Result<(), std::io::Error> {
+    let Some(server) = server else {
+        return Ok(());
+    };
+    tracing::info!("{} on {}", what, address.unwrap_or_default());
+    server.with_graceful_shutdown(wait_for_stop(stopped)).await
+}
+
+async fn wait_for_stop(mut stopped: watch::Receiver<bool>) {
+    // an error means the sender is gone, which only happens once shutdown has begun
+    let _ = stopped.wait_for(|stopped| *stopped).await;
+}
+
+async fn shutdown_signal() {
+    let interrupt = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to listen for SIGINT");
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = interrupt => {}
+        _ = terminate => {}
     }
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct ErrorResponse {
     pub error: String,
+    // the `X-Request-Id` of the failed request, to quote when reporting the error
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+impl ErrorResponse {
+    pub fn new(error: impl Into<String>) -> Self {
+        ErrorResponse {
+            error: error.into(),
+            request_id: current_request_id(),
+        }
+    }
 }
-pub async fn get_postgres_pool(url: &str) -> Result<PgPool, sqlx::Error> {
+#[cfg(feature = "postgres")]
+pub async fn get_postgres_pool(
+    settings: &settings::DatabaseSettings,
+) -> Result<sqlx::PgPool, sqlx::Error> {
+    use secrecy::ExposeSecret;
     // Create a new PostgreSQL connection pool
-    PgPoolOptions::new().max_connections(5).connect(url).await
+    sqlx::postgres::PgPoolOptions::new()
+        .max_connections(settings.max_connections)
+        .connect(settings.url.expose_secret())
+        .await
 }
 
-pub fn get_redis_client(redis_hostname: String) -> RedisResult<Client> {
+#[cfg(feature = "redis")]
+pub fn get_redis_client(redis_hostname: String) -> redis::RedisResult<redis::Client> {
     let redis_url = format!("redis://{}/", redis_hostname);
     redis::Client::open(redis_url)
 }
@@ -1,58 +1,24 @@
-use auth_service::services::data_stares::postgres_user_store::PostgresUserStore;
-use auth_service::services::data_stares::redis_banned_token_store::RedisBannedTokenStore;
-use auth_service::services::hashmap_two_fa_code_store::HashmapTwoFACodeStore;
-use auth_service::services::mock_email_client::MockEmailClient;
-use auth_service::util::constants::{prod, DATABASE_URL, REDIS_HOST_NAME};
+use auth_service::app_state::backends::build_app_state;
+use auth_service::services::password_hasher;
+use auth_service::settings::Settings;
 use auth_service::util::tracing::init_tracing;
-use auth_service::{app_state, get_postgres_pool, get_redis_client, Application};
-use sqlx::PgPool;
+use auth_service::Application;
 use std::sync::Arc;
-use tokio::sync::RwLock;
 
 #[tokio::main]
 async fn main() {
     color_eyre::install().expect("Failed to install color_eyre");
-    init_tracing().expect("Init tracing failed");
-    let pg_pool = configure_postgresql().await;
-    let redis_pool = Arc::new(RwLock::new(configure_redis()));
-
-    let user_store = Box::new(PostgresUserStore::new(pg_pool));
-    let ban_store = Box::new(RedisBannedTokenStore::new(redis_pool));
-    let two_fa_store = Box::new(HashmapTwoFACodeStore::default());
-    let email_client = Box::new(MockEmailClient::default());
-    let app_state = app_state::AppState::new(
-        Arc::new(RwLock::new(user_store)),
-        Arc::new(RwLock::new(ban_store)),
-        Arc::new(RwLock::new(two_fa_store)),
-        Arc::new(RwLock::new(email_client)),
-    );
+    let settings = Arc::new(Settings::load().expect("Invalid configuration"));
+    let telemetry = init_tracing(&settings.tracing).expect("Init tracing failed");
+    password_hasher::configure(&settings.password).expect("Invalid password hashing configuration");
+    let app_state = build_app_state(Arc::clone(&settings))
+        .await
+        .expect("Failed to configure backends");
 
-    let app = Application::build(app_state, prod::APP_ADDRESS)
+    let app = Application::build(app_state)
         .await
         .expect("Failed to start app");
 
     app.run().await.expect("Failed to run app");
-}
-async fn configure_postgresql() -> PgPool {
-    // Create a new database connection pool
-    println!("Configuring database at {}", &DATABASE_URL.to_string());
-    let pg_pool = get_postgres_pool(&DATABASE_URL)
-        .await
-        .expect("Failed to create Postgres connection pool! ");
-    println!("database running.");
-
-    // Run database migrations against our test database!
-    sqlx::migrate!()
-        .run(&pg_pool)
-        .await
-        .expect("Failed to run migrations");
-
-    pg_pool
-}
-
-pub fn configure_redis() -> redis::Connection {
-    get_redis_client(REDIS_HOST_NAME.to_owned())
-        .expect("Failed to get Redis client")
-        .get_connection()
-        .expect("Failed to get Redis connection")
+    telemetry.shutdown().expect("Failed to flush spans");
 }
@@ -0,0 +1,76 @@
+use crate::app_state::AppState;
+use crate::domain::data_stores::DeadLetter;
+use crate::domain::error::AuthAPIError;
+use crate::util::auth::{bearer_token, constant_time_eq};
+use axum::extract::{Path, Query, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::IntoResponse;
+use axum::Json;
+use secrecy::ExposeSecret;
+use serde::Deserialize;
+use std::sync::Arc;
+
+// Admin routes expect `Authorization: Bearer <admin.token>`. Without a configured token
+// they answer 404, as if they didn't exist.
+fn authorize_admin(state: &AppState, headers: &HeaderMap) -> Result<(), AuthAPIError> {
+    let expected = state
+        .settings
+        .admin
+        .token
+        .as_ref()
+        .ok_or(AuthAPIError::NotFound)?;
+    let token = bearer_token(headers).ok_or(AuthAPIError::Unauthorized)?;
+    if !constant_time_eq(token.as_bytes(), expected.expose_secret().as_bytes()) {
+        return Err(AuthAPIError::Unauthorized);
+    }
+    Ok(())
+}
+
+#[derive(Deserialize)]
+pub struct DeadLettersQuery {
+    pub limit: Option<u32>,
+}
+
+const DEFAULT_DEAD_LETTERS_LIMIT: u32 = 100;
+
+// Emails the outbox worker gave up on, newest first.
+#[tracing::instrument(name = "List dead letters", skip_all)]
+pub async fn list_dead_letters(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(query): Query<DeadLettersQuery>,
+) -> Result<Json<Vec<DeadLetter>>, AuthAPIError> {
+    authorize_admin(&state, &headers)?;
+    let dead_letters = state
+        .email_outbox
+        .read()
+        .await
+        .dead_letters(query.limit.unwrap_or(DEFAULT_DEAD_LETTERS_LIMIT))
+        .await?;
+    Ok(Json(dead_letters))
+}
+
+// Puts a dead letter back into the outbox for another round of attempts.
+#[tracing::instrument(name = "Retry dead letter", skip_all)]
+pub async fn retry_dead_letter(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<i64>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    authorize_admin(&state, &headers)?;
+    state.email_outbox.write().await.requeue(id).await?;
+    Ok(StatusCode::ACCEPTED)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret2"));
+        assert!(!constant_time_eq(b"", b"secret"));
+    }
+}
@@ -0,0 +1,69 @@
+use crate::app_state::AppState;
+use crate::domain::error::AuthAPIError;
+use crate::domain::password::Password;
+use crate::services::email_templates::{EmailTemplate, SecurityEvent};
+use crate::services::password_change;
+use crate::util::auth::authenticated_email;
+use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::IntoResponse;
+use axum::Json;
+use axum_extra::extract::CookieJar;
+use chrono::Utc;
+use color_eyre::Report;
+use secrecy::Secret;
+use serde::Deserialize;
+use std::sync::Arc;
+
+#[derive(Deserialize)]
+pub struct ChangePasswordRequest {
+    #[serde(rename = "currentPassword")]
+    pub current_password: Secret<String>,
+    #[serde(rename = "newPassword")]
+    pub new_password: Secret<String>,
+}
+
+#[tracing::instrument(name = "Change password", skip_all)]
+pub async fn change_password(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    jar: CookieJar,
+    Json(request): Json<ChangePasswordRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let email = authenticated_email(&state, &headers, &jar).await?;
+
+    let current_password =
+        Password::parse(request.current_password).map_err(|_| AuthAPIError::InvalidCredentials)?;
+    let new_password = Password::parse(request.new_password)?;
+    state.settings.password.policy.check(&new_password).await?;
+
+    let user = password_change::change_password(
+        &state.user_store,
+        &email,
+        &current_password,
+        &new_password,
+        state.settings.password.history_size,
+    )
+    .await?;
+
+    // the password is changed either way, so a failed alert is only logged
+    let alert = EmailTemplate::SecurityAlert {
+        event: SecurityEvent::PasswordChanged,
+        occurred_at: Utc::now(),
+    };
+    let queued = match alert.render(user.language) {
+        Ok(message) => state
+            .email_outbox
+            .write()
+            .await
+            .enqueue(&email, &message, None)
+            .await
+            .map_err(Report::from),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = queued {
+        tracing::warn!("could not queue password change alert: {:?}", e);
+    }
+
+    Ok(StatusCode::OK)
+}
@@ -0,0 +1,17 @@
+use crate::util::csrf::CsrfToken;
+use axum::{Extension, Json};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+pub struct CsrfTokenResponse {
+    #[serde(rename = "csrfToken")]
+    pub csrf_token: String,
+}
+
+// The token to send in `X-CSRF-Token`, for pages that can't read the CSRF cookie, e.g. ones on
+// another origin. The cookie is set along with it if the request didn't have one.
+pub async fn csrf_token(
+    Extension(CsrfToken(token)): Extension<CsrfToken>,
+) -> Json<CsrfTokenResponse> {
+    Json(CsrfTokenResponse { csrf_token: token })
+}
@@ -0,0 +1,91 @@
+use crate::app_state::AppState;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Instant;
+
+#[derive(Serialize, Deserialize)]
+pub struct LivenessResponse {
+    pub status: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ReadinessResponse {
+    // `ready`, `not_ready` or `draining`
+    pub status: String,
+    pub checks: BTreeMap<String, DependencyStatus>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DependencyStatus {
+    // `up` or `down`
+    pub status: String,
+    pub latency_ms: f64,
+}
+
+// Answers as long as the process can serve requests at all; nothing is checked.
+pub async fn health_live() -> Json<LivenessResponse> {
+    Json(LivenessResponse {
+        status: "ok".to_owned(),
+    })
+}
+
+// Probes every dependency in turn, each within `health.check_timeout`. Why a dependency is
+// down is only logged, so that the response gives nothing away about the infrastructure.
+pub async fn health_ready(
+    State(state): State<Arc<AppState>>,
+) -> (StatusCode, Json<ReadinessResponse>) {
+    if state.is_draining() {
+        let response = ReadinessResponse {
+            status: "draining".to_owned(),
+            checks: BTreeMap::new(),
+        };
+        return (StatusCode::SERVICE_UNAVAILABLE, Json(response));
+    }
+
+    let timeout = state.settings.health.check_timeout;
+    let mut checks = BTreeMap::new();
+    for health_check in state.health_checks.iter() {
+        let started = Instant::now();
+        let result = tokio::time::timeout(timeout, health_check.check()).await;
+        let latency_ms = started.elapsed().as_secs_f64() * 1000.0;
+        let up = match result {
+            Ok(Ok(())) => true,
+            Ok(Err(e)) => {
+                tracing::warn!(
+                    dependency = health_check.name(),
+                    "health check failed: {:?}",
+                    e
+                );
+                false
+            }
+            Err(_) => {
+                tracing::warn!(dependency = health_check.name(), "health check timed out");
+                false
+            }
+        };
+        let status = if up { "up" } else { "down" };
+        checks.insert(
+            health_check.name().to_owned(),
+            DependencyStatus {
+                status: status.to_owned(),
+                latency_ms,
+            },
+        );
+    }
+
+    let ready = checks.values().all(|check| check.status == "up");
+    let (status_code, status) = if ready {
+        (StatusCode::OK, "ready")
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, "not_ready")
+    };
+    let response = ReadinessResponse {
+        status: status.to_owned(),
+        checks,
+    };
+    (status_code, Json(response))
+}
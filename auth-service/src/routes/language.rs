@@ -0,0 +1,37 @@
+use crate::app_state::AppState;
+use crate::domain::error::AuthAPIError;
+use crate::domain::language::Language;
+use crate::util::auth::authenticated_email;
+use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::IntoResponse;
+use axum::Json;
+use axum_extra::extract::CookieJar;
+use serde::Deserialize;
+use std::sync::Arc;
+
+#[derive(Deserialize)]
+pub struct LanguageRequest {
+    pub language: String,
+}
+
+// Stores the language the user's emails are written in.
+#[tracing::instrument(name = "Update language", skip_all)]
+pub async fn update_language(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    jar: CookieJar,
+    Json(request): Json<LanguageRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let email = authenticated_email(&state, &headers, &jar).await?;
+    let language = Language::parse(&request.language)?;
+
+    state
+        .user_store
+        .write()
+        .await
+        .update_language(&email, language)
+        .await?;
+
+    Ok(StatusCode::OK)
+}
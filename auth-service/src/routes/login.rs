@@ -3,10 +3,15 @@ use crate::domain::data_stores::{LoginAttemptId, TwoFACode};
 use crate::domain::email::Email;
 use crate::domain::error::AuthAPIError;
 use crate::domain::password::Password;
+use crate::domain::user::User;
+use crate::services::email_templates::EmailTemplate;
+use crate::services::password_hasher::{compute_password_hash, needs_rehash};
+use crate::util::metrics::metrics;
 use axum::extract::State;
 use axum::response::IntoResponse;
 use axum::Json;
 use axum_extra::extract::CookieJar;
+use chrono::Utc;
 use color_eyre::eyre::eyre;
 use http::StatusCode;
 use secrecy::{ExposeSecret, Secret};
@@ -24,41 +29,101 @@ pub async fn login(
     jar: CookieJar,
     Json(request): Json<LoginRequest>,
 ) -> Result<impl IntoResponse, AuthAPIError> {
+    let result = attempt_login(&state, jar, request).await;
+    match &result {
+        Ok(_) => metrics().login_succeeded(),
+        Err(e) => metrics().login_failed(e.reason()),
+    }
+    result
+}
+
+// A login that ends in a 2FA challenge counts as successful; the challenge is counted apart.
+async fn attempt_login(
+    state: &Arc<AppState>,
+    jar: CookieJar,
+    request: LoginRequest,
+) -> Result<(CookieJar, (StatusCode, Json<LoginResponse>)), AuthAPIError> {
     let email = Email::parse(request.email)?;
-    let password = Password::parse(request.password)?;
+    let password =
+        Password::parse(request.password).map_err(|_| AuthAPIError::InvalidCredentials)?;
 
-    let user_store = &state.user_store.read().await;
-    user_store.validate_user(&email, &password).await?;
+    let user = {
+        let user_store = state.user_store.read().await;
+        user_store.validate_user(&email, &password).await?;
+        user_store.get_user(&email).await?
+    };
+    rehash_if_outdated(&user, &password, state).await;
 
-    let auth_cookie = crate::util::auth::generate_auth_cookie(&email)
+    let auth_cookie = crate::util::auth::generate_auth_cookie(&email, &state.settings)
         .map_err(|e| AuthAPIError::UnexpectedError(eyre!(e)))?;
 
     let updated_jar = jar.add(auth_cookie);
-    let user = user_store.get_user(&email).await?;
     let res = if user.requires_2fa {
-        handle_2fa(&user.email, &state).await?
+        handle_2fa(&user, state).await?
     } else {
         handle_no_2fa().await?
     };
     Ok((updated_jar, res))
 }
 
+// The plaintext password is only available during login, so this is where hashes made with
+// outdated Argon2 parameters, a legacy algorithm or an old pepper are upgraded.
+// A failure here must not fail the login itself.
+#[tracing::instrument(name = "rehash_if_outdated", skip_all)]
+async fn rehash_if_outdated(user: &User, password: &Password, app_state: &Arc<AppState>) {
+    match needs_rehash(&user.password_hash) {
+        Ok(false) => return,
+        Ok(true) => {}
+        Err(e) => {
+            tracing::warn!("could not inspect password hash: {:?}", e);
+            return;
+        }
+    }
+    let password_hash = match compute_password_hash(password).await {
+        Ok(password_hash) => password_hash,
+        Err(e) => {
+            tracing::warn!("could not rehash password: {:?}", e);
+            return;
+        }
+    };
+    match app_state
+        .user_store
+        .write()
+        .await
+        .update_password_hash(&user.email, password_hash)
+        .await
+    {
+        Ok(()) => tracing::info!("upgraded outdated password hash"),
+        Err(e) => tracing::warn!("could not store rehashed password: {:?}", e),
+    }
+}
+
 #[tracing::instrument(name = "handle_2fa", skip_all)]
 async fn handle_2fa(
-    email: &Email,
+    user: &User,
     app_state: &Arc<AppState>,
 ) -> Result<(StatusCode, Json<LoginResponse>), AuthAPIError> {
+    let email = &user.email;
     // First, we must generate a new random login attempt ID and 2FA code
     let login_attempt_id = LoginAttemptId::default();
     let two_fa_code = TwoFACode::default();
+    let message = EmailTemplate::TwoFactorCode {
+        code: two_fa_code.as_ref().to_owned(),
+    }
+    .render(user.language)
+    .map_err(AuthAPIError::UnexpectedError)?;
 
+    // delivered by the outbox worker, so that a slow email provider doesn't slow down logins;
+    // once the code is no longer valid, there is no point in sending it
+    let expires_at = Utc::now()
+        + chrono::Duration::from_std(app_state.settings.two_fa.code_ttl)
+            .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
     app_state
-        .email_client
+        .email_outbox
         .write()
         .await
-        .send_email(email, "Here is your 2FA Token", two_fa_code.as_ref())
-        .await
-        .map_err(|e| AuthAPIError::UnexpectedError(e))?;
+        .enqueue(email, &message, Some(expires_at))
+        .await?;
 
     let mut write_lock = app_state.two_fa_code_store.write().await;
 
@@ -68,6 +133,7 @@ async fn handle_2fa(
         .await?;
 
     std::mem::drop(write_lock);
+    metrics().two_fa_codes_issued.inc();
     // Finally, we need to return the login attempt ID to the client
     let response = Json(LoginResponse::TwoFactorAuth(TwoFactorAuthResponse {
         message: "2FA required".to_string(),
@@ -0,0 +1,11 @@
+use crate::util::metrics::metrics;
+use axum::http::header;
+use axum::response::IntoResponse;
+
+// Served on the main port, or only on `metrics.port` if that is set.
+pub async fn get_metrics() -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, prometheus::TEXT_FORMAT)],
+        metrics().render(),
+    )
+}
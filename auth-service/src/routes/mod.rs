@@ -1,12 +1,24 @@
+mod admin;
+mod change_password;
+mod csrf;
+mod health;
+mod language;
 mod login;
 mod logout;
+mod metrics;
 mod signup;
 mod verify_2fa;
 mod verify_token;
 
 // re-export items from sub-modules
+pub use admin::*;
+pub use change_password::*;
+pub use csrf::*;
+pub use health::*;
+pub use language::*;
 pub use login::*;
 pub use logout::*;
+pub use metrics::*;
 pub use signup::*;
 pub use verify_2fa::*;
 pub use verify_token::*;
@@ -1,8 +1,11 @@
 use crate::app_state::AppState;
+use crate::domain::data_stores::UserStoreError;
 use crate::domain::email::Email;
 use crate::domain::error::AuthAPIError;
+use crate::domain::language::Language;
 use crate::domain::password::Password;
 use crate::domain::user::User;
+use crate::services::password_hasher::compute_password_hash;
 use axum::extract::State;
 use axum::http::StatusCode;
 use axum::response::IntoResponse;
@@ -17,6 +20,9 @@ pub struct SignupRequest {
     pub password: Secret<String>,
     #[serde(rename = "requires2FA")]
     pub requires_2fa: bool,
+    // language tag for the user's emails, e.g. `de`; English if not given
+    #[serde(default)]
+    pub language: Option<String>,
 }
 #[tracing::instrument(name = "Signup", skip_all)]
 pub async fn signup(
@@ -26,20 +32,29 @@ pub async fn signup(
     // Create a new `User` instance using data in the `request`
     let email = Email::parse(request.email.expose_secret().clone())
         .map_err(|_| AuthAPIError::InvalidCredentials)?;
-    let password =
-        Password::parse(request.password).map_err(|_| AuthAPIError::InvalidCredentials)?;
+    state.email_domain_policy.check(&email).await?;
+    let password = Password::parse(request.password)?;
+    state.settings.password.policy.check(&password).await?;
+    let language = match &request.language {
+        Some(tag) => Language::parse(tag)?,
+        None => Language::default(),
+    };
 
     let mut user_store = state.user_store.write().await;
     if user_store.get_user(&email).await.is_ok() {
         return Err(AuthAPIError::UserAlreadyExists);
     }
 
-    let user = User::new2(email, password, request.requires_2fa);
-
-    user_store
-        .add_user(user)
+    let password_hash = compute_password_hash(&password)
         .await
-        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+        .map_err(AuthAPIError::UnexpectedError)?;
+    let user = User::new2(email, password_hash, request.requires_2fa).with_language(language);
+
+    // another instance sharing the database may have added it in the meantime
+    user_store.add_user(user).await.map_err(|e| match e {
+        UserStoreError::UserAlreadyExists => AuthAPIError::UserAlreadyExists,
+        e => AuthAPIError::UnexpectedError(e.into()),
+    })?;
 
     let response = Json(SignupResponse {
         message: "User created successfully!".to_string(),
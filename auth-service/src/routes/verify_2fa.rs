@@ -2,6 +2,7 @@ use crate::app_state::AppState;
 use crate::domain::data_stores::{LoginAttemptId, TwoFACode};
 use crate::domain::error::AuthAPIError;
 use crate::domain::Email;
+use crate::util::metrics::metrics;
 use axum::extract::State;
 use axum::response::IntoResponse;
 use axum::Json;
@@ -51,6 +52,7 @@ pub async fn verify_2fa(
         .await
         .remove_code(&email)
         .await?;
+    metrics().two_fa_codes_verified.inc();
 
     Ok(())
 }
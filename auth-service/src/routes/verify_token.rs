@@ -14,12 +14,12 @@ pub struct TokenRequest {
 }
 
 pub async fn verify_token(
-    State(_state): State<Arc<AppState>>,
+    State(state): State<Arc<AppState>>,
     _jar: CookieJar,
     Json(request): Json<TokenRequest>,
 ) -> Result<impl IntoResponse, AuthAPIError> {
     let token = request.token;
-    auth::validate_token(&token)
+    auth::validate_token(&token, &state.settings.jwt)
         .await
         .map_err(|_| AuthAPIError::InvalidToken)?;
     Ok(())
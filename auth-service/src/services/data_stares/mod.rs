@@ -1,3 +1,12 @@
+#[cfg(feature = "postgres")]
+pub mod postgres_email_outbox;
+#[cfg(feature = "postgres")]
+pub mod postgres_health_check;
+#[cfg(feature = "postgres")]
 pub mod postgres_user_store;
+#[cfg(feature = "redis")]
 pub mod redis_banned_token_store;
+#[cfg(feature = "redis")]
+pub mod redis_health_check;
+#[cfg(feature = "redis")]
 pub mod redis_two_fa_code_store;
@@ -0,0 +1,228 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use secrecy::ExposeSecret;
+use sqlx::{query, PgPool};
+
+use crate::domain::data_stores::{DeadLetter, EmailOutbox, EmailOutboxError, OutboxEmail};
+use crate::domain::{Email, EmailMessage};
+use crate::util::metrics::metrics;
+
+pub struct PostgresEmailOutbox {
+    pool: PgPool,
+}
+
+impl PostgresEmailOutbox {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+fn unexpected(e: sqlx::Error) -> EmailOutboxError {
+    EmailOutboxError::UnexpectedError(e.into())
+}
+
+fn found(rows_affected: u64) -> Result<(), EmailOutboxError> {
+    if rows_affected == 0 {
+        return Err(EmailOutboxError::NotFound);
+    }
+    Ok(())
+}
+
+#[async_trait::async_trait]
+impl EmailOutbox for PostgresEmailOutbox {
+    #[tracing::instrument(name = "Adding email to outbox in PostgreSQL", skip_all)]
+    async fn enqueue(
+        &mut self,
+        recipient: &Email,
+        message: &EmailMessage,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<i64, EmailOutboxError> {
+        let _timer = metrics().time_db_query("enqueue");
+        let row = query!(
+            "INSERT INTO email_outbox (recipient, subject, text_body, html_body, expires_at) VALUES ($1, $2, $3, $4, $5) RETURNING id",
+            recipient.as_ref().expose_secret(),
+            message.subject,
+            message.text_body,
+            message.html_body,
+            expires_at
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(unexpected)?;
+        Ok(row.id)
+    }
+
+    // Several workers may poll the same table: SKIP LOCKED hands each due row to only one of
+    // them, and pushing next_attempt_at past the lease keeps it from being claimed again
+    // unless the worker dies before reporting back.
+    #[tracing::instrument(name = "Claiming due emails from outbox in PostgreSQL", skip_all)]
+    async fn claim_due(
+        &mut self,
+        limit: u32,
+        lease: Duration,
+    ) -> Result<Vec<OutboxEmail>, EmailOutboxError> {
+        let _timer = metrics().time_db_query("claim_due");
+        let rows = query!(
+            r#"
+            UPDATE email_outbox SET next_attempt_at = NOW() + make_interval(secs => $2)
+            WHERE id IN (
+                SELECT id FROM email_outbox
+                WHERE status = 'pending' AND next_attempt_at <= NOW()
+                ORDER BY next_attempt_at
+                LIMIT $1
+                FOR UPDATE SKIP LOCKED
+            )
+            RETURNING id, recipient, subject, text_body, html_body, attempts, expires_at
+            "#,
+            i64::from(limit),
+            lease.as_secs_f64()
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(unexpected)?;
+        rows.into_iter()
+            .map(|row| {
+                Ok(OutboxEmail {
+                    id: row.id,
+                    recipient: Email::parse(row.recipient)
+                        .map_err(|e| EmailOutboxError::UnexpectedError(e.into()))?,
+                    message: EmailMessage {
+                        subject: row.subject,
+                        text_body: row.text_body,
+                        html_body: row.html_body,
+                    },
+                    attempts: row.attempts.try_into().unwrap_or_default(),
+                    expires_at: row.expires_at,
+                })
+            })
+            .collect()
+    }
+
+    #[tracing::instrument(name = "Marking outbox email as sent in PostgreSQL", skip_all)]
+    async fn mark_sent(&mut self, id: i64) -> Result<(), EmailOutboxError> {
+        let _timer = metrics().time_db_query("mark_sent");
+        let result = query!(
+            "UPDATE email_outbox SET status = 'sent', sent_at = NOW(), last_error = NULL, text_body = '', html_body = '' WHERE id = $1",
+            id
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(unexpected)?;
+        found(result.rows_affected())
+    }
+
+    #[tracing::instrument(name = "Rescheduling outbox email in PostgreSQL", skip_all)]
+    async fn retry_later(
+        &mut self,
+        id: i64,
+        error: &str,
+        at: DateTime<Utc>,
+    ) -> Result<(), EmailOutboxError> {
+        let _timer = metrics().time_db_query("retry_later");
+        let result = query!(
+            "UPDATE email_outbox SET attempts = attempts + 1, last_error = $2, next_attempt_at = $3 WHERE id = $1 AND status = 'pending'",
+            id,
+            error,
+            at
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(unexpected)?;
+        found(result.rows_affected())
+    }
+
+    #[tracing::instrument(name = "Dead-lettering outbox email in PostgreSQL", skip_all)]
+    async fn dead_letter(&mut self, id: i64, error: &str) -> Result<(), EmailOutboxError> {
+        let _timer = metrics().time_db_query("dead_letter");
+        let result = query!(
+            r#"
+            UPDATE email_outbox SET status = 'dead', attempts = attempts + 1, last_error = $2,
+                text_body = CASE WHEN expires_at IS NULL THEN text_body ELSE '' END,
+                html_body = CASE WHEN expires_at IS NULL THEN html_body ELSE '' END,
+                expires_at = CASE WHEN expires_at > NOW() THEN NOW() ELSE expires_at END
+            WHERE id = $1 AND status = 'pending'
+            "#,
+            id,
+            error
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(unexpected)?;
+        found(result.rows_affected())
+    }
+
+    #[tracing::instrument(name = "Discarding outbox email in PostgreSQL", skip_all)]
+    async fn discard(&mut self, id: i64) -> Result<(), EmailOutboxError> {
+        let _timer = metrics().time_db_query("discard");
+        let result = query!(
+            "DELETE FROM email_outbox WHERE id = $1 AND status = 'pending'",
+            id
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(unexpected)?;
+        found(result.rows_affected())
+    }
+
+    #[tracing::instrument(name = "Listing dead letters from outbox in PostgreSQL", skip_all)]
+    async fn dead_letters(&self, limit: u32) -> Result<Vec<DeadLetter>, EmailOutboxError> {
+        let _timer = metrics().time_db_query("dead_letters");
+        let rows = query!(
+            "SELECT id, recipient, subject, attempts, last_error, created_at FROM email_outbox WHERE status = 'dead' ORDER BY id DESC LIMIT $1",
+            i64::from(limit)
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(unexpected)?;
+        Ok(rows
+            .into_iter()
+            .map(|row| DeadLetter {
+                id: row.id,
+                recipient: row.recipient,
+                subject: row.subject,
+                attempts: row.attempts.try_into().unwrap_or_default(),
+                last_error: row.last_error.unwrap_or_default(),
+                created_at: row.created_at,
+            })
+            .collect())
+    }
+
+    #[tracing::instrument(name = "Requeuing dead letter in PostgreSQL", skip_all)]
+    async fn requeue(&mut self, id: i64) -> Result<(), EmailOutboxError> {
+        let _timer = metrics().time_db_query("requeue");
+        let row = query!(
+            r#"
+            WITH dead AS (SELECT id, expires_at FROM email_outbox WHERE id = $1 AND status = 'dead'),
+            requeued AS (
+                UPDATE email_outbox SET status = 'pending', attempts = 0, next_attempt_at = NOW()
+                WHERE id IN (SELECT id FROM dead WHERE expires_at IS NULL OR expires_at > NOW())
+                RETURNING id
+            )
+            SELECT EXISTS (SELECT 1 FROM requeued) AS "requeued!" FROM dead
+            "#,
+            id
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(unexpected)?
+        .ok_or(EmailOutboxError::NotFound)?;
+        if !row.requeued {
+            return Err(EmailOutboxError::Expired);
+        }
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Purging sent emails from outbox in PostgreSQL", skip_all)]
+    async fn purge_sent(&mut self, before: DateTime<Utc>) -> Result<u64, EmailOutboxError> {
+        let _timer = metrics().time_db_query("purge_sent");
+        let result = query!(
+            "DELETE FROM email_outbox WHERE status = 'sent' AND sent_at < $1",
+            before
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(unexpected)?;
+        Ok(result.rows_affected())
+    }
+}
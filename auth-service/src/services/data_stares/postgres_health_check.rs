@@ -0,0 +1,30 @@
+use color_eyre::eyre::{Context, Result};
+use sqlx::PgPool;
+
+use crate::domain::health::HealthCheck;
+
+// Shared by every store kept in Postgres, so the pool is only checked once.
+pub struct PostgresHealthCheck {
+    pool: PgPool,
+}
+
+impl PostgresHealthCheck {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl HealthCheck for PostgresHealthCheck {
+    fn name(&self) -> &'static str {
+        "postgres"
+    }
+
+    async fn check(&self) -> Result<()> {
+        sqlx::query("SELECT 1")
+            .execute(&self.pool)
+            .await
+            .wrap_err("failed to query Postgres")?;
+        Ok(())
+    }
+}
@@ -1,45 +1,144 @@
-use argon2::{
-    password_hash::SaltString, Algorithm, Argon2, Params, PasswordHash, PasswordHasher,
-    PasswordVerifier, Version,
-};
-use secrecy::{ExposeSecret, Secret};
+use color_eyre::Report;
+use futures_util::TryStreamExt;
+use secrecy::ExposeSecret;
 use sqlx::{query, PgPool};
+use tracing::Instrument;
 
-use crate::domain::data_stores::{UserStore, UserStoreError};
+use crate::domain::data_stores::{
+    LegacyPasswordHashCount, LegacyPasswordHashes, UserStore, UserStoreError,
+};
+use crate::domain::language::Language;
 use crate::domain::password::Password;
+use crate::domain::password_hash::PasswordHash;
 use crate::domain::user::{User, UserRow};
 use crate::domain::Email;
+use crate::services::password_hasher::{needs_rehash, verify_password_hash};
+use crate::util::metrics::metrics;
 
 pub struct PostgresUserStore {
     pool: PgPool,
 }
 
+// What `PostgresUserStore::normalize_emails` did.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct EmailNormalization {
+    // addresses rewritten into their canonical form
+    pub normalized: u64,
+    // addresses `Email::parse` rejects; these users can't log in until they are fixed by hand
+    pub unparseable: u64,
+    // addresses whose canonical form belongs to another user; these have to be merged by hand
+    pub conflicting: u64,
+}
+
 impl PostgresUserStore {
     pub fn new(pool: PgPool) -> Self {
         Self { pool }
     }
+
+    // Rewrites addresses stored before they were kept in canonical form, with `Email::parse`
+    // like every lookup, then adds `users_email_lower_idx` as a guard against addresses that
+    // differ only in case. Once the index exists this does nothing, so it is only run in full
+    // until no conflicts are left. Meant for startup, before requests are served.
+    #[tracing::instrument(name = "Normalising user emails in PostgreSQL", skip_all)]
+    pub async fn normalize_emails(&self) -> Result<EmailNormalization, UserStoreError> {
+        let unexpected = |e: sqlx::Error| UserStoreError::UnexpectedError(e.into());
+        let mut report = EmailNormalization::default();
+        let done = query!(r#"SELECT to_regclass('users_email_lower_idx') IS NOT NULL AS "done!""#)
+            .fetch_one(&self.pool)
+            .await
+            .map_err(unexpected)?
+            .done;
+        if done {
+            return Ok(report);
+        }
+
+        let mut renames = Vec::new();
+        let mut rows = query!("SELECT email FROM users").fetch(&self.pool);
+        while let Some(row) = rows.try_next().await.map_err(unexpected)? {
+            match Email::parse(row.email.clone()) {
+                Ok(email) if email.as_ref().expose_secret() != &row.email => {
+                    renames.push((row.email, email))
+                }
+                Ok(_) => {}
+                Err(_) => report.unparseable += 1,
+            }
+        }
+        drop(rows);
+        for (stored, email) in renames {
+            let renamed = query!(
+                "UPDATE users SET email = $2 WHERE email = $1",
+                stored,
+                email.as_ref().expose_secret()
+            )
+            .execute(&self.pool)
+            .await;
+            match renamed {
+                Ok(result) => report.normalized += result.rows_affected(),
+                Err(e) if is_unique_violation(&e) => report.conflicting += 1,
+                Err(e) => return Err(unexpected(e)),
+            }
+        }
+
+        if report.normalized > 0 {
+            tracing::info!(normalized = report.normalized, "normalised user emails");
+        }
+        if report.unparseable > 0 {
+            tracing::warn!(
+                unparseable = report.unparseable,
+                "user emails that aren't valid addresses; these users can't log in"
+            );
+        }
+        if report.conflicting > 0 {
+            tracing::error!(
+                conflicting = report.conflicting,
+                "user emails that are another user's in canonical form; merge these users, \
+                 normalisation is retried on the next start"
+            );
+            return Ok(report);
+        }
+        if let Err(e) = query!(
+            "CREATE UNIQUE INDEX IF NOT EXISTS users_email_lower_idx ON users (lower(email))"
+        )
+        .execute(&self.pool)
+        .await
+        {
+            tracing::error!("could not add users_email_lower_idx: {:?}", e);
+        }
+        Ok(report)
+    }
+}
+
+fn is_unique_violation(e: &sqlx::Error) -> bool {
+    e.as_database_error()
+        .is_some_and(|e| e.is_unique_violation())
 }
 
 #[async_trait::async_trait]
 impl UserStore for PostgresUserStore {
     #[tracing::instrument(name = "Adding user to PostgreSQL", skip_all)]
     async fn add_user(&mut self, user: User) -> Result<(), UserStoreError> {
-        let hash = compute_password_hash(user.password_hash.as_ref())
-            .await
-            .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+        let _timer = metrics().time_db_query("add_user");
         query!(
-            "INSERT INTO users (email, password_hash, requires_2fa) VALUES ($1, $2, $3)",
+            "INSERT INTO users (email, password_hash, requires_2fa, language) VALUES ($1, $2, $3, $4)",
             user.email.as_ref().expose_secret(),
-            hash,
-            user.requires_2fa
+            user.password_hash.as_ref().expose_secret(),
+            user.requires_2fa,
+            user.language.as_str()
         )
         .execute(&self.pool)
         .await
-        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+        .map_err(|e| {
+            if is_unique_violation(&e) {
+                UserStoreError::UserAlreadyExists
+            } else {
+                UserStoreError::UnexpectedError(e.into())
+            }
+        })?;
         Ok(())
     }
     #[tracing::instrument(name = "Retrieving user from PostgreSQL", skip_all)]
     async fn get_user(&self, email: &Email) -> Result<User, UserStoreError> {
+        let _timer = metrics().time_db_query("get_user");
         let row = sqlx::query_as!(
             UserRow,
             "SELECT * FROM USERS WHERE email = $1",
@@ -65,55 +164,152 @@ impl UserStore for PostgresUserStore {
         password: &Password,
     ) -> Result<(), UserStoreError> {
         let user = self.get_user(email).await?;
-        verify_password_hash(
-            user.password_hash.as_ref().expose_secret(),
-            password.as_ref().expose_secret(),
+        verify_password_hash(&user.password_hash, password)
+            .await
+            .map_err(|_| UserStoreError::InvalidPassword)
+    }
+
+    #[tracing::instrument(name = "Updating password hash in PostgreSQL", skip_all)]
+    async fn update_password_hash(
+        &mut self,
+        email: &Email,
+        password_hash: PasswordHash,
+    ) -> Result<(), UserStoreError> {
+        let _timer = metrics().time_db_query("update_password_hash");
+        let result = query!(
+            "UPDATE users SET password_hash = $1 WHERE email = $2",
+            password_hash.as_ref().expose_secret(),
+            email.as_ref().expose_secret()
         )
+        .execute(&self.pool)
         .await
-        .map_err(|_| UserStoreError::InvalidPassword)
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+        Ok(())
+    }
+    fn count_legacy_password_hashes(&self) -> LegacyPasswordHashCount {
+        let pool = self.pool.clone();
+        let count = async move {
+            let _timer = metrics().time_db_query("count_legacy_password_hashes");
+            // streamed, so only one hash at a time is held in memory
+            let mut rows = query!("SELECT password_hash FROM users").fetch(&pool);
+            let mut counts = LegacyPasswordHashes::default();
+            while let Some(row) = rows
+                .try_next()
+                .await
+                .map_err(|e| UserStoreError::UnexpectedError(e.into()))?
+            {
+                counts.count(
+                    PasswordHash::parse(row.password_hash)
+                        .map_err(Report::from)
+                        .and_then(|hash| needs_rehash(&hash)),
+                );
+            }
+            Ok(counts)
+        };
+        Box::pin(count.instrument(tracing::info_span!(
+            "Counting legacy password hashes in PostgreSQL"
+        )))
     }
-}
-#[tracing::instrument(name = "Verify password hash", skip_all)]
-async fn verify_password_hash(
-    expected_password_hash: &str,
-    password_candidate: &str,
-) -> color_eyre::eyre::Result<()> {
-    let current_span: tracing::Span = tracing::Span::current();
-
-    let exp_hash = expected_password_hash.to_owned();
-    let cand = password_candidate.to_owned();
-    tokio::task::spawn_blocking(move || {
-        current_span.in_scope(|| {
-            let exp_hash_str = PasswordHash::new(&*exp_hash)?;
-
-            Argon2::default()
-                .verify_password(cand.as_bytes(), &exp_hash_str)
-                .map_err(|e| e.into())
-        })
-    })
-    .await?
-}
 
-#[tracing::instrument(name = "Computing password hash", skip_all)]
-async fn compute_password_hash(password: &Secret<String>) -> color_eyre::eyre::Result<String> {
-    let pwd = password.to_owned();
-    let current_span: tracing::Span = tracing::Span::current();
-
-    tokio::task::spawn_blocking(move || -> color_eyre::eyre::Result<String> {
-        current_span.in_scope(|| {
-            let salt: SaltString = SaltString::generate(&mut rand::thread_rng());
-            let password_hash = Argon2::new(
-                Algorithm::Argon2id,
-                Version::V0x13,
-                Params::new(15000, 2, 1, None)?,
-            )
-            .hash_password(pwd.expose_secret().as_bytes(), &salt)?
-            .to_string();
-
-            // Err(eyre!("oh no!"))
-            Ok(password_hash)
-            // Err(Box::new(std::io::Error::other("oh no!"))) // as Box<dyn Error + Send + Sync>)
-        })
-    })
-    .await?
+    #[tracing::instrument(name = "Retrieving password history from PostgreSQL", skip_all)]
+    async fn get_password_history(
+        &self,
+        email: &Email,
+    ) -> Result<Vec<PasswordHash>, UserStoreError> {
+        let _timer = metrics().time_db_query("get_password_history");
+        self.get_user(email).await?;
+        let rows = query!(
+            "SELECT password_hash FROM password_history WHERE email = $1 ORDER BY id DESC",
+            email.as_ref().expose_secret()
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+        rows.into_iter()
+            .map(|row| {
+                PasswordHash::parse(row.password_hash)
+                    .map_err(|e| UserStoreError::UnexpectedError(e.into()))
+            })
+            .collect()
+    }
+
+    #[tracing::instrument(name = "Updating password in PostgreSQL", skip_all)]
+    async fn update_password(
+        &mut self,
+        email: &Email,
+        password_hash: PasswordHash,
+        history_size: usize,
+    ) -> Result<(), UserStoreError> {
+        let _timer = metrics().time_db_query("update_password");
+        let email = email.as_ref().expose_secret();
+        let mut transaction = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        let previous = query!(
+            "SELECT password_hash FROM users WHERE email = $1 FOR UPDATE",
+            email
+        )
+        .fetch_optional(&mut *transaction)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?
+        .ok_or(UserStoreError::UserNotFound)?;
+
+        query!(
+            "INSERT INTO password_history (email, password_hash) VALUES ($1, $2)",
+            email,
+            previous.password_hash
+        )
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+        query!(
+            "UPDATE users SET password_hash = $1 WHERE email = $2",
+            password_hash.as_ref().expose_secret(),
+            email
+        )
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+        query!(
+            "DELETE FROM password_history WHERE email = $1 AND id NOT IN \
+             (SELECT id FROM password_history WHERE email = $1 ORDER BY id DESC LIMIT $2)",
+            email,
+            history_size as i64
+        )
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        transaction
+            .commit()
+            .await
+            .map_err(|e| UserStoreError::UnexpectedError(e.into()))
+    }
+
+    #[tracing::instrument(name = "Updating language in PostgreSQL", skip_all)]
+    async fn update_language(
+        &mut self,
+        email: &Email,
+        language: Language,
+    ) -> Result<(), UserStoreError> {
+        let _timer = metrics().time_db_query("update_language");
+        let result = query!(
+            "UPDATE users SET language = $1 WHERE email = $2",
+            language.as_str(),
+            email.as_ref().expose_secret()
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+        Ok(())
+    }
 }
@@ -1,19 +1,22 @@
 use color_eyre::eyre::{Context, Result};
 use redis::{Commands, Connection};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::RwLock;
 
 use crate::domain::data_stores::{BannedTokenStore, BannedTokenStoreError};
-use crate::util::auth::TOKEN_TTL_SECONDS;
+use crate::util::metrics::metrics;
 
 pub struct RedisBannedTokenStore {
     conn: Arc<RwLock<Connection>>,
+    // entries expire on their own after this long
+    token_ttl: Duration,
 }
 
 impl RedisBannedTokenStore {
     #[tracing::instrument(name = "new redis", skip_all)]
-    pub fn new(conn: Arc<RwLock<Connection>>) -> Self {
-        Self { conn }
+    pub fn new(conn: Arc<RwLock<Connection>>, token_ttl: Duration) -> Self {
+        Self { conn, token_ttl }
     }
 }
 
@@ -21,12 +24,13 @@ impl RedisBannedTokenStore {
 impl BannedTokenStore for RedisBannedTokenStore {
     #[tracing::instrument(name = "add token", skip_all)]
     async fn add_token(&mut self, token: String) -> Result<(), BannedTokenStoreError> {
+        let _timer = metrics().time_redis_command("add_token");
         let key = get_key(&token);
 
         self.conn
             .write()
             .await
-            .set_ex::<_, _, ()>(&key, true, TOKEN_TTL_SECONDS as u64)
+            .set_ex::<_, _, ()>(&key, true, self.token_ttl.as_secs())
             .wrap_err("failed to set banned token in Redis")
             .map_err(BannedTokenStoreError::UnexpectedError)
 
@@ -34,11 +38,12 @@ impl BannedTokenStore for RedisBannedTokenStore {
     }
     #[tracing::instrument(name = "contains token", skip_all)]
     async fn contains_token(&self, token: &str) -> Result<bool, BannedTokenStoreError> {
+        let _timer = metrics().time_redis_command("contains_token");
         // Check if the token exists by calling the exists method on the Redis connection
         self.conn
             .write()
             .await
-            .exists(&get_key(&token))
+            .exists(get_key(token))
             .wrap_err("failed to check if token exists in Redis")
             .map_err(BannedTokenStoreError::UnexpectedError)
     }
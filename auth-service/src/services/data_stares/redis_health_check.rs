@@ -0,0 +1,35 @@
+use color_eyre::eyre::{eyre, Context, Result};
+use redis::aio::MultiplexedConnection;
+
+use crate::domain::health::HealthCheck;
+
+// Shared by every store kept in Redis, so the server is only checked once. The stores' own
+// connection is blocking, so the check pings over a separate async one, which the probe's
+// timeout can cancel.
+pub struct RedisHealthCheck {
+    conn: MultiplexedConnection,
+}
+
+impl RedisHealthCheck {
+    pub fn new(conn: MultiplexedConnection) -> Self {
+        Self { conn }
+    }
+}
+
+#[async_trait::async_trait]
+impl HealthCheck for RedisHealthCheck {
+    fn name(&self) -> &'static str {
+        "redis"
+    }
+
+    async fn check(&self) -> Result<()> {
+        let reply: String = redis::cmd("PING")
+            .query_async(&mut self.conn.clone())
+            .await
+            .wrap_err("failed to ping Redis")?;
+        if reply != "PONG" {
+            return Err(eyre!("Redis answered PING with {}", reply));
+        }
+        Ok(())
+    }
+}
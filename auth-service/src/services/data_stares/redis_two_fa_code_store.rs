@@ -3,21 +3,25 @@ use redis::{Commands, Connection};
 use secrecy::ExposeSecret;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::RwLock;
 
 use crate::domain::{
     data_stores::{LoginAttemptId, TwoFACode, TwoFACodeStore, TwoFACodeStoreError},
     Email,
 };
+use crate::util::metrics::metrics;
 
 pub struct RedisTwoFACodeStore {
     conn: Arc<RwLock<Connection>>,
+    // entries expire on their own after this long
+    code_ttl: Duration,
 }
 
 impl RedisTwoFACodeStore {
     #[tracing::instrument(name = "new 2fa redis", skip_all)]
-    pub fn new(conn: Arc<RwLock<Connection>>) -> Self {
-        Self { conn }
+    pub fn new(conn: Arc<RwLock<Connection>>, code_ttl: Duration) -> Self {
+        Self { conn, code_ttl }
     }
 }
 
@@ -30,6 +34,7 @@ impl TwoFACodeStore for RedisTwoFACodeStore {
         login_attempt_id: LoginAttemptId,
         code: TwoFACode,
     ) -> Result<(), TwoFACodeStoreError> {
+        let _timer = metrics().time_redis_command("add_code");
         // 1. Create a new key using the get_key helper function.
         let key = get_key(&email);
         // 2. Create a TwoFATuple instance.
@@ -44,12 +49,12 @@ impl TwoFACodeStore for RedisTwoFACodeStore {
         // Return TwoFACodeStoreError::UnexpectedError if serialization fails.
         // 4. Call the set_ex command on the Redis connection to set a new key/value pair with an expiration time (TTL).
         // The value should be the serialized 2FA tuple.
-        // The expiration time should be set to TEN_MINUTES_IN_SECONDS.
+        // The expiration time should be set to the code TTL.
         // Return TwoFACodeStoreError::UnexpectedError if casting fails or the call to set_ex fails.
         self.conn
             .write()
             .await
-            .set_ex::<_, _, ()>(&key, jfa, TEN_MINUTES_IN_SECONDS)
+            .set_ex::<_, _, ()>(&key, jfa, self.code_ttl.as_secs())
             .wrap_err("failed to set 2FA code")
             .map_err(TwoFACodeStoreError::UnexpectedError)?;
 
@@ -57,8 +62,9 @@ impl TwoFACodeStore for RedisTwoFACodeStore {
     }
     #[tracing::instrument(name = "remove code", skip_all)]
     async fn remove_code(&mut self, email: &Email) -> Result<(), TwoFACodeStoreError> {
+        let _timer = metrics().time_redis_command("remove_code");
         // 1. Create a new key using the get_key helper function.
-        let key = get_key(&email);
+        let key = get_key(email);
         // 2. Call the del command on the Redis connection to delete the 2FA code entry.
         // Return TwoFACodeStoreError::UnexpectedError if the operation fails.
         self.conn
@@ -73,8 +79,9 @@ impl TwoFACodeStore for RedisTwoFACodeStore {
         &self,
         email: &Email,
     ) -> Result<(LoginAttemptId, TwoFACode), TwoFACodeStoreError> {
+        let _timer = metrics().time_redis_command("get_code");
         // 1. Create a new key using the get_key helper function.
-        let key = get_key(&email);
+        let key = get_key(email);
         // 2. Call the get command on the Redis connection to get the value stored for the key.
         // Return TwoFACodeStoreError::LoginAttemptIdNotFound if the operation fails.
         let code: String = self
@@ -85,7 +92,7 @@ impl TwoFACodeStore for RedisTwoFACodeStore {
             .wrap_err("failed to get 2FA code")
             .map_err(|_| TwoFACodeStoreError::LoginAttemptIdNotFound)?;
         // If the operation succeeds, call serde_json::from_str to parse the JSON string into a TwoFATuple.
-        let TwoFATuple(login_att, code2fa) = serde_json::from_str(&*code)
+        let TwoFATuple(login_att, code2fa) = serde_json::from_str(&code)
             .wrap_err("failed to get 2FA code.")
             .map_err(TwoFACodeStoreError::UnexpectedError)?;
         // Then, parse the login attempt ID string and 2FA code string into a LoginAttemptId and TwoFACode type respectively.
@@ -101,7 +108,6 @@ impl TwoFACodeStore for RedisTwoFACodeStore {
 #[derive(Serialize, Deserialize)]
 struct TwoFATuple(pub String, pub String);
 
-const TEN_MINUTES_IN_SECONDS: u64 = 600;
 const TWO_FA_CODE_PREFIX: &str = "two_fa_code:";
 #[tracing::instrument(name = "get key", skip_all)]
 fn get_key(email: &Email) -> String {
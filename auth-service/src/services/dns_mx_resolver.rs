@@ -0,0 +1,32 @@
+use color_eyre::eyre::{Context, Result};
+use hickory_resolver::error::ResolveErrorKind;
+use hickory_resolver::TokioAsyncResolver;
+
+use crate::domain::email_domain_policy::MxResolver;
+
+// Resolves MX records with the system's resolver configuration (`/etc/resolv.conf`).
+pub struct DnsMxResolver {
+    resolver: TokioAsyncResolver,
+}
+
+impl DnsMxResolver {
+    pub fn from_system_conf() -> Result<Self> {
+        let resolver = TokioAsyncResolver::tokio_from_system_conf()
+            .wrap_err("failed to read the system's DNS configuration")?;
+        Ok(Self { resolver })
+    }
+}
+
+#[async_trait::async_trait]
+impl MxResolver for DnsMxResolver {
+    #[tracing::instrument(name = "Looking up MX records", skip_all)]
+    async fn has_mx(&self, domain: &str) -> Result<bool> {
+        // a trailing dot keeps the resolver from appending search domains
+        match self.resolver.mx_lookup(format!("{}.", domain)).await {
+            // a single "." exchange is a null MX (RFC 7505): the domain accepts no mail
+            Ok(lookup) => Ok(lookup.iter().any(|mx| !mx.exchange().is_root())),
+            Err(e) if matches!(e.kind(), ResolveErrorKind::NoRecordsFound { .. }) => Ok(false),
+            Err(e) => Err(e).wrap_err(format!("MX lookup for {} failed", domain)),
+        }
+    }
+}
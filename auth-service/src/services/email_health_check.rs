@@ -0,0 +1,26 @@
+use color_eyre::eyre::Result;
+
+use crate::app_state::EmailClientType;
+use crate::domain::health::HealthCheck;
+
+// Asks whichever email client is configured whether it could send right now.
+pub struct EmailHealthCheck {
+    email_client: EmailClientType,
+}
+
+impl EmailHealthCheck {
+    pub fn new(email_client: EmailClientType) -> Self {
+        Self { email_client }
+    }
+}
+
+#[async_trait::async_trait]
+impl HealthCheck for EmailHealthCheck {
+    fn name(&self) -> &'static str {
+        "email"
+    }
+
+    async fn check(&self) -> Result<()> {
+        self.email_client.read().await.health_check().await
+    }
+}
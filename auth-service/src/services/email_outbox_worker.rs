@@ -0,0 +1,416 @@
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+use color_eyre::eyre::Result;
+use tokio::sync::watch;
+
+use crate::app_state::{EmailClientType, EmailOutboxType};
+use crate::domain::data_stores::OutboxEmail;
+use crate::settings::EmailOutboxSettings;
+
+// how often the worker deletes sent emails older than `sent_retention`
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+// how long to wait after the `attempts`th failed delivery
+fn backoff(config: &EmailOutboxSettings, attempts: u32) -> Duration {
+    let factor = 2u32.saturating_pow(attempts.saturating_sub(1));
+    config
+        .initial_backoff
+        .saturating_mul(factor)
+        .min(config.max_backoff)
+}
+
+// Delivers what request handlers put into the outbox. Failed deliveries are retried with
+// exponential backoff; after `max_attempts` the email is dead-lettered and shows up on
+// `GET /admin/email-outbox/dead`. Emails that expire before they can be sent are discarded.
+// Sent emails are deleted after `sent_retention`.
+pub struct EmailOutboxWorker {
+    outbox: EmailOutboxType,
+    email_client: EmailClientType,
+    config: EmailOutboxSettings,
+}
+
+impl EmailOutboxWorker {
+    pub fn new(
+        outbox: EmailOutboxType,
+        email_client: EmailClientType,
+        config: EmailOutboxSettings,
+    ) -> Self {
+        Self {
+            outbox,
+            email_client,
+            config,
+        }
+    }
+
+    // Runs until `stopped` turns true. The pass under way is finished first, so no claimed
+    // email is left waiting for its lease to run out.
+    pub async fn run(self, mut stopped: watch::Receiver<bool>) {
+        let mut last_purge: Option<Instant> = None;
+        while !*stopped.borrow() {
+            if last_purge.is_none_or(|at| at.elapsed() >= PURGE_INTERVAL) {
+                if let Err(e) = self.purge_sent().await {
+                    tracing::error!("purging sent emails failed: {:?}", e);
+                }
+                last_purge = Some(Instant::now());
+            }
+            match self.deliver_due().await {
+                // there may be more waiting
+                Ok(claimed) if claimed == self.config.batch_size as usize => continue,
+                Ok(_) => {}
+                Err(e) => tracing::error!("email outbox worker failed: {:?}", e),
+            }
+            tokio::select! {
+                _ = tokio::time::sleep(self.config.poll_interval) => {}
+                // an error means the sender is gone, which is as good as a stop
+                _ = stopped.wait_for(|stopped| *stopped) => break,
+            }
+        }
+    }
+
+    // Deletes the emails sent longer than `sent_retention` ago; returns how many.
+    #[tracing::instrument(name = "Purging sent outbox emails", skip_all)]
+    pub async fn purge_sent(&self) -> Result<u64> {
+        let before = Utc::now() - chrono::Duration::from_std(self.config.sent_retention)?;
+        let purged = self.outbox.write().await.purge_sent(before).await?;
+        if purged > 0 {
+            tracing::info!(purged, "purged sent emails");
+        }
+        Ok(purged)
+    }
+
+    // One pass over the emails that are due; returns how many were claimed.
+    #[tracing::instrument(name = "Delivering outbox emails", skip_all)]
+    pub async fn deliver_due(&self) -> Result<usize> {
+        let due = self
+            .outbox
+            .write()
+            .await
+            .claim_due(self.config.batch_size, self.config.lease)
+            .await?;
+        let claimed = due.len();
+        for email in due {
+            if email.expired_at(Utc::now()) {
+                tracing::warn!(id = email.id, "discarding expired email");
+                if let Err(e) = self.outbox.write().await.discard(email.id).await {
+                    tracing::error!(id = email.id, "discarding expired email failed: {:?}", e);
+                }
+                continue;
+            }
+            let sent = self
+                .email_client
+                .read()
+                .await
+                .send_email(&email.recipient, &email.message)
+                .await;
+            // the rest of the batch is still worth trying; this one is retried once its lease
+            // runs out
+            if let Err(e) = self.record(&email, sent).await {
+                tracing::error!(id = email.id, "recording outcome of email failed: {:?}", e);
+            }
+        }
+        Ok(claimed)
+    }
+
+    // Marks the email sent, or schedules its next attempt unless that would be too late.
+    async fn record(&self, email: &OutboxEmail, sent: Result<()>) -> Result<()> {
+        let mut outbox = self.outbox.write().await;
+        let Err(e) = sent else {
+            outbox.mark_sent(email.id).await?;
+            return Ok(());
+        };
+        let error = format!("{:#}", e);
+        let attempts = email.attempts + 1;
+        if attempts >= self.config.max_attempts {
+            tracing::error!(id = email.id, attempts, "giving up on email: {}", error);
+            outbox.dead_letter(email.id, &error).await?;
+        } else {
+            let backoff = backoff(&self.config, attempts);
+            let at = Utc::now() + chrono::Duration::from_std(backoff)?;
+            if email.expired_at(at) {
+                tracing::warn!(
+                    id = email.id,
+                    attempts,
+                    "sending email failed, discarding it as it expires before the next attempt: {}",
+                    error
+                );
+                outbox.discard(email.id).await?;
+                return Ok(());
+            }
+            tracing::warn!(
+                id = email.id,
+                attempts,
+                "sending email failed, retrying in {:?}: {}",
+                backoff,
+                error
+            );
+            outbox.retry_later(email.id, &error, at).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::data_stores::{DeadLetter, EmailOutbox, EmailOutboxError};
+    use crate::domain::{Email, EmailClient, EmailMessage};
+    use crate::services::hashmap_email_outbox::HashmapEmailOutbox;
+    use color_eyre::eyre::eyre;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+
+    // Fails the first `failures` deliveries, then succeeds.
+    struct FlakyEmailClient {
+        failures: u32,
+        calls: Arc<AtomicU32>,
+    }
+
+    #[async_trait::async_trait]
+    impl EmailClient for FlakyEmailClient {
+        async fn send_email(&self, _: &Email, _: &EmailMessage) -> Result<()> {
+            if self.calls.fetch_add(1, Ordering::SeqCst) < self.failures {
+                return Err(eyre!("connection refused"));
+            }
+            Ok(())
+        }
+    }
+
+    fn message() -> EmailMessage {
+        EmailMessage {
+            subject: "Subject".to_owned(),
+            text_body: "Content".to_owned(),
+            html_body: "<p>Content</p>".to_owned(),
+        }
+    }
+
+    struct Setup {
+        outbox: EmailOutboxType,
+        calls: Arc<AtomicU32>,
+        worker: EmailOutboxWorker,
+    }
+
+    async fn setup(failures: u32, max_attempts: u32) -> Setup {
+        let outbox: EmailOutboxType =
+            Arc::new(RwLock::new(Box::new(HashmapEmailOutbox::default())));
+        let calls = Arc::new(AtomicU32::new(0));
+        let email_client: EmailClientType = Arc::new(RwLock::new(Box::new(FlakyEmailClient {
+            failures,
+            calls: Arc::clone(&calls),
+        })));
+        outbox
+            .write()
+            .await
+            .enqueue(&Email::unwrap("herbert@email.com"), &message(), None)
+            .await
+            .unwrap();
+        let config = EmailOutboxSettings {
+            max_attempts,
+            initial_backoff: Duration::ZERO,
+            ..EmailOutboxSettings::default()
+        };
+        let worker = EmailOutboxWorker::new(Arc::clone(&outbox), email_client, config);
+        Setup {
+            outbox,
+            calls,
+            worker,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_delivers_due_emails_once() {
+        let setup = setup(0, 3).await;
+        assert_eq!(setup.worker.deliver_due().await.unwrap(), 1);
+        assert_eq!(setup.worker.deliver_due().await.unwrap(), 0);
+        assert_eq!(setup.calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_retries_failed_deliveries() {
+        let setup = setup(2, 3).await;
+        for _ in 0..3 {
+            setup.worker.deliver_due().await.unwrap();
+        }
+        assert_eq!(setup.calls.load(Ordering::SeqCst), 3);
+        assert!(setup
+            .outbox
+            .read()
+            .await
+            .dead_letters(10)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(setup.worker.deliver_due().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_dead_letters_after_max_attempts() {
+        let setup = setup(u32::MAX, 3).await;
+        for _ in 0..5 {
+            setup.worker.deliver_due().await.unwrap();
+        }
+        assert_eq!(setup.calls.load(Ordering::SeqCst), 3);
+        let dead = setup.outbox.read().await.dead_letters(10).await.unwrap();
+        assert_eq!(dead.len(), 1);
+        assert_eq!(dead[0].attempts, 3);
+        assert_eq!(dead[0].last_error, "connection refused");
+    }
+
+    #[tokio::test]
+    async fn test_run_stops_when_told() {
+        let setup = setup(0, 3).await;
+        let (stop, stopped) = watch::channel(false);
+        let run = tokio::spawn(setup.worker.run(stopped));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(setup.calls.load(Ordering::SeqCst), 1);
+
+        stop.send(true).unwrap();
+        tokio::time::timeout(Duration::from_secs(1), run)
+            .await
+            .expect("the worker didn't stop")
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_purges_sent_emails_after_the_retention() {
+        let mut setup = setup(0, 3).await;
+        setup.worker.deliver_due().await.unwrap();
+        assert_eq!(setup.worker.purge_sent().await.unwrap(), 0);
+
+        setup.worker.config.sent_retention = Duration::ZERO;
+        assert_eq!(setup.worker.purge_sent().await.unwrap(), 1);
+    }
+
+    // Loses track of the first email it hands out.
+    struct ForgetfulOutbox(HashmapEmailOutbox);
+
+    #[async_trait::async_trait]
+    impl EmailOutbox for ForgetfulOutbox {
+        async fn enqueue(
+            &mut self,
+            recipient: &Email,
+            message: &EmailMessage,
+            expires_at: Option<chrono::DateTime<Utc>>,
+        ) -> Result<i64, EmailOutboxError> {
+            self.0.enqueue(recipient, message, expires_at).await
+        }
+        async fn claim_due(
+            &mut self,
+            limit: u32,
+            lease: Duration,
+        ) -> Result<Vec<OutboxEmail>, EmailOutboxError> {
+            self.0.claim_due(limit, lease).await
+        }
+        async fn mark_sent(&mut self, id: i64) -> Result<(), EmailOutboxError> {
+            match id {
+                1 => Err(EmailOutboxError::NotFound),
+                _ => self.0.mark_sent(id).await,
+            }
+        }
+        async fn retry_later(
+            &mut self,
+            id: i64,
+            error: &str,
+            at: chrono::DateTime<Utc>,
+        ) -> Result<(), EmailOutboxError> {
+            self.0.retry_later(id, error, at).await
+        }
+        async fn dead_letter(&mut self, id: i64, error: &str) -> Result<(), EmailOutboxError> {
+            self.0.dead_letter(id, error).await
+        }
+        async fn discard(&mut self, id: i64) -> Result<(), EmailOutboxError> {
+            self.0.discard(id).await
+        }
+        async fn dead_letters(&self, limit: u32) -> Result<Vec<DeadLetter>, EmailOutboxError> {
+            self.0.dead_letters(limit).await
+        }
+        async fn requeue(&mut self, id: i64) -> Result<(), EmailOutboxError> {
+            self.0.requeue(id).await
+        }
+        async fn purge_sent(
+            &mut self,
+            before: chrono::DateTime<Utc>,
+        ) -> Result<u64, EmailOutboxError> {
+            self.0.purge_sent(before).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_bookkeeping_errors_dont_stop_the_batch() {
+        let mut setup = setup(0, 3).await;
+        let mut outbox = HashmapEmailOutbox::default();
+        for _ in 0..2 {
+            outbox
+                .enqueue(&Email::unwrap("herbert@email.com"), &message(), None)
+                .await
+                .unwrap();
+        }
+        setup.worker.outbox = Arc::new(RwLock::new(Box::new(ForgetfulOutbox(outbox))));
+
+        assert_eq!(setup.worker.deliver_due().await.unwrap(), 2);
+        assert_eq!(setup.calls.load(Ordering::SeqCst), 2);
+    }
+
+    async fn enqueue_expiring(outbox: &EmailOutboxType, expires_in: chrono::Duration) {
+        outbox
+            .write()
+            .await
+            .enqueue(
+                &Email::unwrap("herbert@email.com"),
+                &message(),
+                Some(Utc::now() + expires_in),
+            )
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_expired_emails_are_discarded_unsent() {
+        let setup = setup(0, 3).await;
+        enqueue_expiring(&setup.outbox, chrono::Duration::seconds(-1)).await;
+
+        assert_eq!(setup.worker.deliver_due().await.unwrap(), 2);
+        assert_eq!(setup.calls.load(Ordering::SeqCst), 1);
+        assert!(setup
+            .outbox
+            .write()
+            .await
+            .claim_due(10, Duration::ZERO)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_emails_are_not_retried_past_their_expiry() {
+        let mut setup = setup(u32::MAX, 3).await;
+        setup.worker.config.initial_backoff = Duration::from_secs(60);
+        setup.worker.outbox = Arc::new(RwLock::new(Box::new(HashmapEmailOutbox::default())));
+        enqueue_expiring(&setup.worker.outbox, chrono::Duration::seconds(30)).await;
+
+        assert_eq!(setup.worker.deliver_due().await.unwrap(), 1);
+        assert_eq!(setup.calls.load(Ordering::SeqCst), 1);
+        let mut outbox = setup.worker.outbox.write().await;
+        assert!(outbox.dead_letters(10).await.unwrap().is_empty());
+        // gone rather than waiting for a retry
+        assert_eq!(
+            outbox.retry_later(1, "", Utc::now()).await,
+            Err(EmailOutboxError::NotFound)
+        );
+    }
+
+    #[test]
+    fn test_backoff_doubles_up_to_max() {
+        let config = EmailOutboxSettings {
+            initial_backoff: Duration::from_secs(5),
+            max_backoff: Duration::from_secs(60),
+            ..EmailOutboxSettings::default()
+        };
+        assert_eq!(backoff(&config, 1), Duration::from_secs(5));
+        assert_eq!(backoff(&config, 2), Duration::from_secs(10));
+        assert_eq!(backoff(&config, 4), Duration::from_secs(40));
+        assert_eq!(backoff(&config, 5), Duration::from_secs(60));
+        assert_eq!(backoff(&config, 100), Duration::from_secs(60));
+    }
+}
@@ -0,0 +1,205 @@
+use askama::Template;
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::{Context, Result};
+
+use crate::domain::language::Language;
+use crate::domain::EmailMessage;
+
+// Something that happened to an account which the owner should know about.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SecurityEvent {
+    PasswordChanged,
+}
+
+// Every kind of email the service sends. Each one has a plain text and an HTML template
+// per language under `templates/email/<language>/`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EmailTemplate {
+    TwoFactorCode {
+        code: String,
+    },
+    Verification {
+        link: String,
+    },
+    PasswordReset {
+        link: String,
+    },
+    SecurityAlert {
+        event: SecurityEvent,
+        occurred_at: DateTime<Utc>,
+    },
+}
+
+// Declares one askama template struct per template file, as askama needs the path at
+// compile time.
+macro_rules! email_templates {
+    ($($name:ident($path:literal) { $($field:ident: $ty:ty),* })*) => {
+        $(
+            #[derive(Template)]
+            #[template(path = $path)]
+            struct $name<'a> {
+                $($field: &'a $ty),*
+            }
+        )*
+    };
+}
+
+email_templates! {
+    TwoFactorCodeTextEn("email/en/two_fa.txt") { code: str }
+    TwoFactorCodeHtmlEn("email/en/two_fa.html") { code: str }
+    TwoFactorCodeTextDe("email/de/two_fa.txt") { code: str }
+    TwoFactorCodeHtmlDe("email/de/two_fa.html") { code: str }
+    VerificationTextEn("email/en/verification.txt") { link: str }
+    VerificationHtmlEn("email/en/verification.html") { link: str }
+    VerificationTextDe("email/de/verification.txt") { link: str }
+    VerificationHtmlDe("email/de/verification.html") { link: str }
+    PasswordResetTextEn("email/en/password_reset.txt") { link: str }
+    PasswordResetHtmlEn("email/en/password_reset.html") { link: str }
+    PasswordResetTextDe("email/de/password_reset.txt") { link: str }
+    PasswordResetHtmlDe("email/de/password_reset.html") { link: str }
+    SecurityAlertTextEn("email/en/security_alert.txt") { event: SecurityEvent, occurred_at: str }
+    SecurityAlertHtmlEn("email/en/security_alert.html") { event: SecurityEvent, occurred_at: str }
+    SecurityAlertTextDe("email/de/security_alert.txt") { event: SecurityEvent, occurred_at: str }
+    SecurityAlertHtmlDe("email/de/security_alert.html") { event: SecurityEvent, occurred_at: str }
+}
+
+impl EmailTemplate {
+    #[tracing::instrument(name = "Rendering email", skip_all)]
+    pub fn render(&self, language: Language) -> Result<EmailMessage> {
+        let (text_body, html_body) = match (self, language) {
+            (EmailTemplate::TwoFactorCode { code }, Language::En) => (
+                TwoFactorCodeTextEn { code }.render(),
+                TwoFactorCodeHtmlEn { code }.render(),
+            ),
+            (EmailTemplate::TwoFactorCode { code }, Language::De) => (
+                TwoFactorCodeTextDe { code }.render(),
+                TwoFactorCodeHtmlDe { code }.render(),
+            ),
+            (EmailTemplate::Verification { link }, Language::En) => (
+                VerificationTextEn { link }.render(),
+                VerificationHtmlEn { link }.render(),
+            ),
+            (EmailTemplate::Verification { link }, Language::De) => (
+                VerificationTextDe { link }.render(),
+                VerificationHtmlDe { link }.render(),
+            ),
+            (EmailTemplate::PasswordReset { link }, Language::En) => (
+                PasswordResetTextEn { link }.render(),
+                PasswordResetHtmlEn { link }.render(),
+            ),
+            (EmailTemplate::PasswordReset { link }, Language::De) => (
+                PasswordResetTextDe { link }.render(),
+                PasswordResetHtmlDe { link }.render(),
+            ),
+            (EmailTemplate::SecurityAlert { event, occurred_at }, Language::En) => {
+                let occurred_at = &occurred_at.format("%Y-%m-%d %H:%M UTC").to_string();
+                (
+                    SecurityAlertTextEn { event, occurred_at }.render(),
+                    SecurityAlertHtmlEn { event, occurred_at }.render(),
+                )
+            }
+            (EmailTemplate::SecurityAlert { event, occurred_at }, Language::De) => {
+                let occurred_at = &occurred_at.format("%d.%m.%Y %H:%M UTC").to_string();
+                (
+                    SecurityAlertTextDe { event, occurred_at }.render(),
+                    SecurityAlertHtmlDe { event, occurred_at }.render(),
+                )
+            }
+        };
+        Ok(EmailMessage {
+            subject: self.subject(language).to_owned(),
+            text_body: text_body.wrap_err("failed to render text body")?,
+            html_body: html_body.wrap_err("failed to render HTML body")?,
+        })
+    }
+
+    fn subject(&self, language: Language) -> &'static str {
+        match (self, language) {
+            (EmailTemplate::TwoFactorCode { .. }, Language::En) => "Your login code",
+            (EmailTemplate::TwoFactorCode { .. }, Language::De) => "Dein Anmeldecode",
+            (EmailTemplate::Verification { .. }, Language::En) => "Confirm your email address",
+            (EmailTemplate::Verification { .. }, Language::De) => "Bestätige deine E-Mail-Adresse",
+            (EmailTemplate::PasswordReset { .. }, Language::En) => "Reset your password",
+            (EmailTemplate::PasswordReset { .. }, Language::De) => "Passwort zurücksetzen",
+            (EmailTemplate::SecurityAlert { .. }, Language::En) => "Security alert",
+            (EmailTemplate::SecurityAlert { .. }, Language::De) => "Sicherheitshinweis",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn all_templates() -> Vec<EmailTemplate> {
+        vec![
+            EmailTemplate::TwoFactorCode {
+                code: "123456".to_owned(),
+            },
+            EmailTemplate::Verification {
+                link: "https://example.com/verify?token=abc&lang=en".to_owned(),
+            },
+            EmailTemplate::PasswordReset {
+                link: "https://example.com/reset?token=abc".to_owned(),
+            },
+            EmailTemplate::SecurityAlert {
+                event: SecurityEvent::PasswordChanged,
+                occurred_at: Utc.with_ymd_and_hms(2026, 10, 19, 8, 30, 0).unwrap(),
+            },
+        ]
+    }
+
+    #[test]
+    fn test_every_template_renders_in_every_language() {
+        for template in all_templates() {
+            for language in [Language::En, Language::De] {
+                let message = template.render(language).unwrap();
+                assert!(!message.subject.is_empty());
+                assert!(!message.text_body.is_empty());
+                assert!(message
+                    .html_body
+                    .contains(&format!("<html lang=\"{}\">", language.as_str())));
+            }
+        }
+    }
+
+    #[test]
+    fn test_two_fa_code_is_in_both_bodies() {
+        let message = EmailTemplate::TwoFactorCode {
+            code: "123456".to_owned(),
+        }
+        .render(Language::De)
+        .unwrap();
+        assert_eq!(message.subject, "Dein Anmeldecode");
+        assert!(message.text_body.lines().any(|line| line == "123456"));
+        assert!(message.html_body.contains(">123456<"));
+    }
+
+    #[test]
+    fn test_html_is_escaped_but_text_is_not() {
+        let message = EmailTemplate::Verification {
+            link: "https://example.com/verify?token=abc&lang=en".to_owned(),
+        }
+        .render(Language::En)
+        .unwrap();
+        assert!(message
+            .text_body
+            .contains("https://example.com/verify?token=abc&lang=en"));
+        assert!(message
+            .html_body
+            .contains("https://example.com/verify?token=abc&amp;lang=en"));
+    }
+
+    #[test]
+    fn test_security_alert_dates_are_localized() {
+        let template = EmailTemplate::SecurityAlert {
+            event: SecurityEvent::PasswordChanged,
+            occurred_at: Utc.with_ymd_and_hms(2026, 10, 19, 8, 30, 0).unwrap(),
+        };
+        let en = template.render(Language::En).unwrap();
+        assert!(en.text_body.contains("2026-10-19 08:30 UTC"));
+        let de = template.render(Language::De).unwrap();
+        assert!(de.text_body.contains("19.10.2026 08:30 UTC"));
+    }
+}
@@ -0,0 +1,323 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use secrecy::ExposeSecret;
+
+use crate::domain::data_stores::{DeadLetter, EmailOutbox, EmailOutboxError, OutboxEmail};
+use crate::domain::{Email, EmailMessage};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Status {
+    Pending,
+    Sent,
+    Dead,
+}
+
+struct Entry {
+    recipient: Email,
+    message: EmailMessage,
+    status: Status,
+    attempts: u32,
+    next_attempt_at: DateTime<Utc>,
+    last_error: Option<String>,
+    created_at: DateTime<Utc>,
+    sent_at: Option<DateTime<Utc>>,
+    expires_at: Option<DateTime<Utc>>,
+}
+
+// Keeps the outbox in memory, so queued emails are lost on restart. Only meant for tests
+// and local runs without Postgres.
+#[derive(Default)]
+pub struct HashmapEmailOutbox {
+    entries: BTreeMap<i64, Entry>,
+    next_id: i64,
+}
+
+impl HashmapEmailOutbox {
+    fn pending(&mut self, id: i64) -> Result<&mut Entry, EmailOutboxError> {
+        self.entries
+            .get_mut(&id)
+            .filter(|entry| entry.status == Status::Pending)
+            .ok_or(EmailOutboxError::NotFound)
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailOutbox for HashmapEmailOutbox {
+    async fn enqueue(
+        &mut self,
+        recipient: &Email,
+        message: &EmailMessage,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<i64, EmailOutboxError> {
+        self.next_id += 1;
+        let now = Utc::now();
+        self.entries.insert(
+            self.next_id,
+            Entry {
+                recipient: recipient.clone(),
+                message: message.clone(),
+                status: Status::Pending,
+                attempts: 0,
+                next_attempt_at: now,
+                last_error: None,
+                created_at: now,
+                sent_at: None,
+                expires_at,
+            },
+        );
+        Ok(self.next_id)
+    }
+
+    async fn claim_due(
+        &mut self,
+        limit: u32,
+        lease: Duration,
+    ) -> Result<Vec<OutboxEmail>, EmailOutboxError> {
+        let now = Utc::now();
+        let lease = chrono::Duration::from_std(lease)
+            .map_err(|e| EmailOutboxError::UnexpectedError(e.into()))?;
+        Ok(self
+            .entries
+            .iter_mut()
+            .filter(|(_, entry)| entry.status == Status::Pending && entry.next_attempt_at <= now)
+            .take(limit as usize)
+            .map(|(id, entry)| {
+                entry.next_attempt_at = now + lease;
+                OutboxEmail {
+                    id: *id,
+                    recipient: entry.recipient.clone(),
+                    message: entry.message.clone(),
+                    attempts: entry.attempts,
+                    expires_at: entry.expires_at,
+                }
+            })
+            .collect())
+    }
+
+    async fn mark_sent(&mut self, id: i64) -> Result<(), EmailOutboxError> {
+        let entry = self
+            .entries
+            .get_mut(&id)
+            .ok_or(EmailOutboxError::NotFound)?;
+        entry.status = Status::Sent;
+        entry.last_error = None;
+        entry.sent_at = Some(Utc::now());
+        entry.message.text_body.clear();
+        entry.message.html_body.clear();
+        Ok(())
+    }
+
+    async fn retry_later(
+        &mut self,
+        id: i64,
+        error: &str,
+        at: DateTime<Utc>,
+    ) -> Result<(), EmailOutboxError> {
+        let entry = self.pending(id)?;
+        entry.attempts += 1;
+        entry.last_error = Some(error.to_owned());
+        entry.next_attempt_at = at;
+        Ok(())
+    }
+
+    async fn dead_letter(&mut self, id: i64, error: &str) -> Result<(), EmailOutboxError> {
+        let entry = self.pending(id)?;
+        entry.status = Status::Dead;
+        entry.attempts += 1;
+        entry.last_error = Some(error.to_owned());
+        if let Some(expires_at) = &mut entry.expires_at {
+            *expires_at = (*expires_at).min(Utc::now());
+            entry.message.text_body.clear();
+            entry.message.html_body.clear();
+        }
+        Ok(())
+    }
+
+    async fn discard(&mut self, id: i64) -> Result<(), EmailOutboxError> {
+        self.pending(id)?;
+        self.entries.remove(&id);
+        Ok(())
+    }
+
+    async fn dead_letters(&self, limit: u32) -> Result<Vec<DeadLetter>, EmailOutboxError> {
+        Ok(self
+            .entries
+            .iter()
+            .rev()
+            .filter(|(_, entry)| entry.status == Status::Dead)
+            .take(limit as usize)
+            .map(|(id, entry)| DeadLetter {
+                id: *id,
+                recipient: entry.recipient.as_ref().expose_secret().to_owned(),
+                subject: entry.message.subject.clone(),
+                attempts: entry.attempts,
+                last_error: entry.last_error.clone().unwrap_or_default(),
+                created_at: entry.created_at,
+            })
+            .collect())
+    }
+
+    async fn requeue(&mut self, id: i64) -> Result<(), EmailOutboxError> {
+        let entry = self
+            .entries
+            .get_mut(&id)
+            .filter(|entry| entry.status == Status::Dead)
+            .ok_or(EmailOutboxError::NotFound)?;
+        if entry
+            .expires_at
+            .is_some_and(|expires_at| expires_at <= Utc::now())
+        {
+            return Err(EmailOutboxError::Expired);
+        }
+        entry.status = Status::Pending;
+        entry.attempts = 0;
+        entry.next_attempt_at = Utc::now();
+        Ok(())
+    }
+
+    async fn purge_sent(&mut self, before: DateTime<Utc>) -> Result<u64, EmailOutboxError> {
+        let count = self.entries.len();
+        self.entries
+            .retain(|_, entry| entry.sent_at.is_none_or(|sent_at| sent_at >= before));
+        Ok((count - self.entries.len()) as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEASE: Duration = Duration::from_secs(60);
+
+    fn message() -> EmailMessage {
+        EmailMessage {
+            subject: "Subject".to_owned(),
+            text_body: "Content".to_owned(),
+            html_body: "<p>Content</p>".to_owned(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_claimed_emails_are_leased() {
+        let mut outbox = HashmapEmailOutbox::default();
+        let recipient = Email::unwrap("herbert@email.com");
+        let id = outbox.enqueue(&recipient, &message(), None).await.unwrap();
+
+        let claimed = outbox.claim_due(10, LEASE).await.unwrap();
+        assert_eq!(
+            claimed,
+            vec![OutboxEmail {
+                id,
+                recipient,
+                message: message(),
+                attempts: 0,
+                expires_at: None,
+            }]
+        );
+        assert!(outbox.claim_due(10, LEASE).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_retry_later_counts_attempts() {
+        let mut outbox = HashmapEmailOutbox::default();
+        let id = outbox
+            .enqueue(&Email::unwrap("herbert@email.com"), &message(), None)
+            .await
+            .unwrap();
+        outbox.claim_due(10, LEASE).await.unwrap();
+        outbox.retry_later(id, "timeout", Utc::now()).await.unwrap();
+
+        let claimed = outbox.claim_due(10, LEASE).await.unwrap();
+        assert_eq!(claimed[0].attempts, 1);
+    }
+
+    #[tokio::test]
+    async fn test_dead_letters_can_be_requeued() {
+        let mut outbox = HashmapEmailOutbox::default();
+        let id = outbox
+            .enqueue(&Email::unwrap("herbert@email.com"), &message(), None)
+            .await
+            .unwrap();
+        outbox.dead_letter(id, "mailbox full").await.unwrap();
+        assert!(outbox
+            .claim_due(10, Duration::ZERO)
+            .await
+            .unwrap()
+            .is_empty());
+
+        let dead = outbox.dead_letters(10).await.unwrap();
+        assert_eq!(dead.len(), 1);
+        assert_eq!(dead[0].last_error, "mailbox full");
+        assert_eq!(dead[0].attempts, 1);
+
+        outbox.requeue(id).await.unwrap();
+        assert!(outbox.dead_letters(10).await.unwrap().is_empty());
+        assert_eq!(outbox.claim_due(10, LEASE).await.unwrap()[0].attempts, 0);
+        assert_eq!(outbox.requeue(id).await, Err(EmailOutboxError::NotFound));
+    }
+
+    #[tokio::test]
+    async fn test_sent_emails_are_emptied_and_purged() {
+        let mut outbox = HashmapEmailOutbox::default();
+        let recipient = Email::unwrap("herbert@email.com");
+        let sent = outbox.enqueue(&recipient, &message(), None).await.unwrap();
+        let pending = outbox.enqueue(&recipient, &message(), None).await.unwrap();
+        outbox.mark_sent(sent).await.unwrap();
+        assert!(outbox.entries[&sent].message.text_body.is_empty());
+        assert!(outbox.entries[&sent].message.html_body.is_empty());
+
+        let before = Utc::now() - chrono::Duration::minutes(1);
+        assert_eq!(outbox.purge_sent(before).await.unwrap(), 0);
+        assert_eq!(outbox.purge_sent(Utc::now()).await.unwrap(), 1);
+        assert!(!outbox.entries.contains_key(&sent));
+        assert!(outbox.entries.contains_key(&pending));
+    }
+
+    #[tokio::test]
+    async fn test_dead_letters_that_expire_lose_their_bodies() {
+        let mut outbox = HashmapEmailOutbox::default();
+        let expires_at = Utc::now() + chrono::Duration::minutes(10);
+        let id = outbox
+            .enqueue(
+                &Email::unwrap("herbert@email.com"),
+                &message(),
+                Some(expires_at),
+            )
+            .await
+            .unwrap();
+        outbox.dead_letter(id, "mailbox full").await.unwrap();
+        assert!(outbox.entries[&id].message.text_body.is_empty());
+        assert!(outbox.entries[&id].message.html_body.is_empty());
+
+        assert_eq!(outbox.requeue(id).await, Err(EmailOutboxError::Expired));
+        assert_eq!(outbox.dead_letters(10).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_expired_emails_are_not_requeued() {
+        let mut outbox = HashmapEmailOutbox::default();
+        let recipient = Email::unwrap("herbert@email.com");
+        let expired = Utc::now() - chrono::Duration::minutes(1);
+        let id = outbox
+            .enqueue(&recipient, &message(), Some(expired))
+            .await
+            .unwrap();
+        outbox.dead_letter(id, "mailbox full").await.unwrap();
+        assert_eq!(outbox.entries[&id].expires_at, Some(expired));
+        assert_eq!(outbox.requeue(id).await, Err(EmailOutboxError::Expired));
+    }
+
+    #[tokio::test]
+    async fn test_discarded_emails_are_gone() {
+        let mut outbox = HashmapEmailOutbox::default();
+        let id = outbox
+            .enqueue(&Email::unwrap("herbert@email.com"), &message(), None)
+            .await
+            .unwrap();
+        outbox.discard(id).await.unwrap();
+        assert!(outbox.claim_due(10, LEASE).await.unwrap().is_empty());
+        assert_eq!(outbox.discard(id).await, Err(EmailOutboxError::NotFound));
+    }
+}
@@ -1,12 +1,19 @@
-use crate::domain::data_stores::{UserStore, UserStoreError};
+use crate::domain::data_stores::{
+    LegacyPasswordHashCount, LegacyPasswordHashes, UserStore, UserStoreError,
+};
 use crate::domain::email::Email;
+use crate::domain::language::Language;
 use crate::domain::password::Password;
+use crate::domain::password_hash::PasswordHash;
 use crate::domain::user::User;
+use crate::services::password_hasher::{needs_rehash, verify_password_hash};
 use std::collections::HashMap;
 
 #[derive(Default)]
 pub struct HashmapUserStore {
     users: HashMap<Email, User>,
+    // previous password hashes per user, newest first
+    password_history: HashMap<Email, Vec<PasswordHash>>,
 }
 #[async_trait::async_trait]
 impl UserStore for HashmapUserStore {
@@ -33,11 +40,79 @@ impl UserStore for HashmapUserStore {
         password: &Password,
     ) -> Result<(), UserStoreError> {
         let user = self.get_user(email).await?;
-        if user.password_hash == *password {
-            Ok(())
-        } else {
-            Err(UserStoreError::InvalidPassword)
-        }
+        verify_password_hash(&user.password_hash, password)
+            .await
+            .map_err(|_| UserStoreError::InvalidPassword)
+    }
+
+    async fn update_password_hash(
+        &mut self,
+        email: &Email,
+        password_hash: PasswordHash,
+    ) -> Result<(), UserStoreError> {
+        let user = self
+            .users
+            .get_mut(email)
+            .ok_or(UserStoreError::UserNotFound)?;
+        user.password_hash = password_hash;
+        Ok(())
+    }
+
+    fn count_legacy_password_hashes(&self) -> LegacyPasswordHashCount {
+        let hashes: Vec<PasswordHash> = self
+            .users
+            .values()
+            .map(|user| user.password_hash.clone())
+            .collect();
+        Box::pin(async move {
+            let mut counts = LegacyPasswordHashes::default();
+            for hash in &hashes {
+                counts.count(needs_rehash(hash));
+            }
+            Ok(counts)
+        })
+    }
+
+    async fn get_password_history(
+        &self,
+        email: &Email,
+    ) -> Result<Vec<PasswordHash>, UserStoreError> {
+        self.get_user(email).await?;
+        Ok(self
+            .password_history
+            .get(email)
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    async fn update_password(
+        &mut self,
+        email: &Email,
+        password_hash: PasswordHash,
+        history_size: usize,
+    ) -> Result<(), UserStoreError> {
+        let user = self
+            .users
+            .get_mut(email)
+            .ok_or(UserStoreError::UserNotFound)?;
+        let previous = std::mem::replace(&mut user.password_hash, password_hash);
+        let history = self.password_history.entry(email.clone()).or_default();
+        history.insert(0, previous);
+        history.truncate(history_size);
+        Ok(())
+    }
+
+    async fn update_language(
+        &mut self,
+        email: &Email,
+        language: Language,
+    ) -> Result<(), UserStoreError> {
+        let user = self
+            .users
+            .get_mut(email)
+            .ok_or(UserStoreError::UserNotFound)?;
+        user.language = language;
+        Ok(())
     }
 }
 
@@ -48,23 +123,37 @@ pub mod tests {
 
     pub async fn test_data() -> HashmapUserStore {
         let mut store = HashmapUserStore::default();
-        let user = User::new("herbert@email.com", "password123§!!", true).unwrap();
+        let user = User::new("herbert@email.com", "password123§!!", true)
+            .await
+            .unwrap();
         store.add_user(user).await.unwrap();
-        let user = User::new("hubert@email.com", "password123§!!", true).unwrap();
+        let user = User::new("hubert@email.com", "password123§!!", true)
+            .await
+            .unwrap();
         store.add_user(user).await.unwrap();
-        let user = User::new("hermann@email.com", "password123§!!", true).unwrap();
+        let user = User::new("hermann@email.com", "password123§!!", true)
+            .await
+            .unwrap();
         store.add_user(user).await.unwrap();
         store
     }
     #[tokio::test]
     async fn test_add_user() {
         let mut hm = test_data().await;
-        hm.add_user(User::new("herbert222@email.com", "password123+", true).unwrap())
-            .await
-            .expect("Failed to add user");
+        hm.add_user(
+            User::new("herbert222@email.com", "password123+", true)
+                .await
+                .unwrap(),
+        )
+        .await
+        .expect("Failed to add user");
 
         let res = hm
-            .add_user(User::new("herbert@email.com", "password321$", true).unwrap())
+            .add_user(
+                User::new("herbert@email.com", "password321$", true)
+                    .await
+                    .unwrap(),
+            )
             .await;
         assert!(matches!(res, Err(UserStoreError::UserAlreadyExists)));
     }
@@ -103,4 +192,66 @@ pub mod tests {
             Err(UserStoreError::InvalidPassword)
         ));
     }
+
+    #[tokio::test]
+    async fn test_update_password_hash() {
+        let mut data = test_data().await;
+        let email = Email::unwrap("hermann@email.com");
+        assert_eq!(
+            data.count_legacy_password_hashes().await.unwrap(),
+            LegacyPasswordHashes::default()
+        );
+
+        let new_user = User::new("hermann@email.com", "password456§!!", true)
+            .await
+            .unwrap();
+        data.update_password_hash(&email, new_user.password_hash.clone())
+            .await
+            .expect("Failed to update password hash");
+        data.validate_user(
+            &email,
+            &Password::parse(Secret::new("password456§!!".to_string())).unwrap(),
+        )
+        .await
+        .expect("Failed to validate user with updated hash");
+
+        let legacy_hash = PasswordHash::parse(
+            "$argon2i$v=19$m=15000,t=2,p=1$c29tZXNhbHQ$wPB3NvGcZHhkoUdcKVDSYW1tiabnqsrIaeHx4Ctw0rQ"
+                .to_string(),
+        )
+        .unwrap();
+        data.update_password_hash(&email, legacy_hash)
+            .await
+            .unwrap();
+        assert_eq!(
+            data.count_legacy_password_hashes().await.unwrap(),
+            LegacyPasswordHashes {
+                legacy: 1,
+                unreadable: 0
+            }
+        );
+
+        // parses as Argon2, but its parameters don't
+        let broken_hash = PasswordHash::parse(
+            "$argon2id$v=19$m=1,t=1,p=1$c29tZXNhbHQ$wPB3NvGcZHhkoUdcKVDSYW1tiabnqsrIaeHx4Ctw0rQ"
+                .to_string(),
+        )
+        .unwrap();
+        data.update_password_hash(&email, broken_hash)
+            .await
+            .unwrap();
+        assert_eq!(
+            data.count_legacy_password_hashes().await.unwrap(),
+            LegacyPasswordHashes {
+                legacy: 0,
+                unreadable: 1
+            }
+        );
+
+        assert!(matches!(
+            data.update_password_hash(&Email::unwrap("notfound@email.com"), new_user.password_hash)
+                .await,
+            Err(UserStoreError::UserNotFound)
+        ));
+    }
 }
@@ -28,6 +28,6 @@ pub mod tests {
         let t1 = "token1".to_string();
         store.add_token(t1.clone()).await.unwrap();
         assert!(store.contains_token(&t1).await.unwrap());
-        assert!(!store.contains_token(&"token2".to_string()).await.unwrap());
+        assert!(!store.contains_token("token2").await.unwrap());
     }
 }
@@ -0,0 +1,334 @@
+use std::time::Duration;
+
+use color_eyre::eyre::{eyre, Context, Result};
+use reqwest::{Client, StatusCode, Url};
+use secrecy::{ExposeSecret, Secret};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::domain::{Email, EmailClient, EmailMessage};
+use crate::settings::EmailSettings;
+
+#[derive(Debug, Clone)]
+pub struct HttpEmailConfig {
+    // e.g. `https://api.postmarkapp.com`; messages are posted to `<base_url>/email`.
+    pub base_url: String,
+    pub api_token: Secret<String>,
+    pub from: String,
+    // per attempt
+    pub timeout: Duration,
+    // retries after the first attempt, each waiting twice as long as the one before
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+}
+
+pub const DEFAULT_EMAIL_API_TIMEOUT: Duration = Duration::from_secs(10);
+pub const DEFAULT_EMAIL_API_MAX_RETRIES: u32 = 3;
+pub const DEFAULT_EMAIL_API_INITIAL_BACKOFF: Duration = Duration::from_millis(200);
+
+impl HttpEmailConfig {
+    pub fn from_settings(email: &EmailSettings) -> Result<Self> {
+        let required = |value: Option<String>, key: &str| {
+            value.ok_or(eyre!("{} must be set to send emails via HTTP", key))
+        };
+        Ok(HttpEmailConfig {
+            base_url: required(email.api.base_url.clone(), "email.api.base_url")?,
+            api_token: Secret::new(required(
+                email
+                    .api
+                    .token
+                    .as_ref()
+                    .map(|t| t.expose_secret().to_owned()),
+                "email.api.token",
+            )?),
+            from: required(email.from.clone(), "email.from")?,
+            timeout: email.api.timeout,
+            max_retries: email.api.max_retries,
+            initial_backoff: DEFAULT_EMAIL_API_INITIAL_BACKOFF,
+        })
+    }
+}
+
+// Sends emails through a transactional email HTTP API in the style of Postmark.
+pub struct HttpEmailClient {
+    http_client: Client,
+    url: Url,
+    api_token: Secret<String>,
+    from: String,
+    max_retries: u32,
+    initial_backoff: Duration,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct SendEmailRequest<'a> {
+    from: &'a str,
+    to: &'a str,
+    subject: &'a str,
+    text_body: &'a str,
+    html_body: &'a str,
+}
+
+pub const API_TOKEN_HEADER: &str = "X-Postmark-Server-Token";
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
+impl HttpEmailClient {
+    pub fn new(config: HttpEmailConfig) -> Result<Self> {
+        // keep any path of the base URL, e.g. `https://mail.example.com/v1/`
+        let base_url = format!("{}/", config.base_url.trim_end_matches('/'));
+        let url = Url::parse(&base_url)
+            .and_then(|base| base.join("email"))
+            .wrap_err(format!("invalid email API base URL {}", config.base_url))?;
+        let http_client = Client::builder()
+            .timeout(config.timeout)
+            .build()
+            .wrap_err("failed to build HTTP client")?;
+        Ok(HttpEmailClient {
+            http_client,
+            url,
+            api_token: config.api_token,
+            from: config.from,
+            max_retries: config.max_retries,
+            initial_backoff: config.initial_backoff,
+        })
+    }
+
+    async fn attempt(&self, body: &SendEmailRequest<'_>, idempotency_key: &str) -> Attempt {
+        let response = self
+            .http_client
+            .post(self.url.clone())
+            .header(API_TOKEN_HEADER, self.api_token.expose_secret())
+            .header(IDEMPOTENCY_KEY_HEADER, idempotency_key)
+            .json(body)
+            .send()
+            .await;
+        match response {
+            Ok(response) if response.status().is_success() => Attempt::Sent,
+            Ok(response) => {
+                let status = response.status();
+                let error = eyre!("email API responded with {}", status);
+                if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS {
+                    Attempt::Retry(error)
+                } else {
+                    Attempt::Fail(error)
+                }
+            }
+            Err(e) if e.is_timeout() || e.is_connect() => {
+                Attempt::Retry(color_eyre::Report::new(e).wrap_err("email API unreachable"))
+            }
+            Err(e) => {
+                Attempt::Fail(color_eyre::Report::new(e).wrap_err("email API request failed"))
+            }
+        }
+    }
+}
+
+enum Attempt {
+    Sent,
+    Retry(color_eyre::Report),
+    Fail(color_eyre::Report),
+}
+
+#[async_trait::async_trait]
+impl EmailClient for HttpEmailClient {
+    // All attempts for one email share an idempotency key, so that the provider can
+    // drop duplicates when an attempt timed out after the email was already accepted.
+    #[tracing::instrument(name = "Sending email via HTTP API", skip_all)]
+    async fn send_email(&self, recipient: &Email, message: &EmailMessage) -> Result<()> {
+        let body = SendEmailRequest {
+            from: &self.from,
+            to: recipient.as_ref().expose_secret(),
+            subject: &message.subject,
+            text_body: &message.text_body,
+            html_body: &message.html_body,
+        };
+        let idempotency_key = Uuid::new_v4().to_string();
+        let mut backoff = self.initial_backoff;
+        let mut retries = 0;
+        loop {
+            match self.attempt(&body, &idempotency_key).await {
+                Attempt::Sent => return Ok(()),
+                Attempt::Retry(e) if retries < self.max_retries => {
+                    tracing::warn!("sending email failed, retrying in {:?}: {:?}", backoff, e);
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                    retries += 1;
+                }
+                Attempt::Retry(e) | Attempt::Fail(e) => return Err(e),
+            }
+        }
+    }
+
+    // Any answer short of a server error means the API is reachable; providers differ in
+    // how they respond to an unauthenticated HEAD, so 4xx counts as up.
+    async fn health_check(&self) -> Result<()> {
+        let response = self
+            .http_client
+            .head(self.url.clone())
+            .send()
+            .await
+            .wrap_err("email API unreachable")?;
+        if response.status().is_server_error() {
+            return Err(eyre!("email API responded with {}", response.status()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{header, header_exists, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn client(server: &MockServer, max_retries: u32) -> HttpEmailClient {
+        HttpEmailClient::new(HttpEmailConfig {
+            base_url: server.uri(),
+            api_token: Secret::new("token".to_owned()),
+            from: "no-reply@example.com".to_owned(),
+            timeout: Duration::from_millis(200),
+            max_retries,
+            initial_backoff: Duration::from_millis(1),
+        })
+        .unwrap()
+    }
+
+    async fn send(client: &HttpEmailClient) -> Result<()> {
+        let message = EmailMessage {
+            subject: "Subject".to_owned(),
+            text_body: "Content".to_owned(),
+            html_body: "<p>Content</p>".to_owned(),
+        };
+        client
+            .send_email(&Email::unwrap("herbert@email.com"), &message)
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_send_email() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/email"))
+            .and(header(API_TOKEN_HEADER, "token"))
+            .and(header_exists(IDEMPOTENCY_KEY_HEADER))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        send(&client(&server, 3)).await.unwrap();
+
+        let request = &server.received_requests().await.unwrap()[0];
+        let body: serde_json::Value = request.body_json().unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "From": "no-reply@example.com",
+                "To": "herbert@email.com",
+                "Subject": "Subject",
+                "TextBody": "Content",
+                "HtmlBody": "<p>Content</p>"
+            })
+        );
+    }
+
+    #[test]
+    fn test_base_url_path_is_kept() {
+        for base_url in [
+            "https://mail.example.com/v1",
+            "https://mail.example.com/v1/",
+        ] {
+            let client = HttpEmailClient::new(HttpEmailConfig {
+                base_url: base_url.to_owned(),
+                api_token: Secret::new("token".to_owned()),
+                from: "no-reply@example.com".to_owned(),
+                timeout: DEFAULT_EMAIL_API_TIMEOUT,
+                max_retries: DEFAULT_EMAIL_API_MAX_RETRIES,
+                initial_backoff: DEFAULT_EMAIL_API_INITIAL_BACKOFF,
+            })
+            .unwrap();
+            assert_eq!(client.url.as_str(), "https://mail.example.com/v1/email");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retries_server_errors_with_same_idempotency_key() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&server)
+            .await;
+
+        send(&client(&server, 3)).await.unwrap();
+
+        let requests = server.received_requests().await.unwrap();
+        assert_eq!(requests.len(), 3);
+        let keys: Vec<_> = requests
+            .iter()
+            .map(|r| r.headers.get(IDEMPOTENCY_KEY_HEADER).unwrap().clone())
+            .collect();
+        assert!(keys.iter().all(|key| *key == keys[0]));
+    }
+
+    #[tokio::test]
+    async fn test_retries_timeouts() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(1)))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&server)
+            .await;
+
+        send(&client(&server, 1)).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_after_max_retries() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(3)
+            .mount(&server)
+            .await;
+
+        assert!(send(&client(&server, 2)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_does_not_retry_client_errors() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(422))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        assert!(send(&client(&server, 3)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_health_check() {
+        let server = MockServer::start().await;
+        // wiremock answers 404 to unmatched requests
+        client(&server, 0).health_check().await.unwrap();
+
+        Mock::given(method("HEAD"))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&server)
+            .await;
+        assert!(client(&server, 0).health_check().await.is_err());
+
+        let client = client(&server, 0);
+        drop(server);
+        assert!(client.health_check().await.is_err());
+    }
+}
@@ -1,5 +1,16 @@
 pub mod data_stares;
+pub mod dns_mx_resolver;
+pub mod email_health_check;
+pub mod email_outbox_worker;
+pub mod email_templates;
+pub mod hashmap_email_outbox;
 pub mod hashmap_two_fa_code_store;
 pub mod hashmap_user_store;
 pub mod hashset_bannedtoken_store;
+pub mod http_email_client;
 pub mod mock_email_client;
+pub mod password_change;
+pub mod password_hasher;
+pub mod password_pepper;
+pub mod smtp_email_client;
+pub mod user_import;
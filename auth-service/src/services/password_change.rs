@@ -0,0 +1,262 @@
+use secrecy::ExposeSecret;
+
+use crate::app_state::UserStoreType;
+use crate::domain::data_stores::UserStoreError;
+use crate::domain::email::Email;
+use crate::domain::password::{Password, PasswordError};
+use crate::domain::user::User;
+use crate::services::password_hasher::{compute_password_hash, verify_password_hash};
+
+// Every change of a user's password goes through here, so that none of the last
+// `history_size` passwords, nor the current one, can be set again.
+// The new password is expected to have passed the password policy already.
+// Verifying and hashing take a while, so the store is only locked to read the user and, at the
+// end, to write the new hash. If the hash changed in between, `current_password` is checked
+// against the new one: a login may only have rehashed the same password, while after an actual
+// change the password no longer holds and the change is refused. Returns the user as it was
+// before the change.
+#[tracing::instrument(name = "Changing password", skip_all)]
+pub async fn change_password(
+    user_store: &UserStoreType,
+    email: &Email,
+    current_password: &Password,
+    new_password: &Password,
+    history_size: usize,
+) -> Result<User, UserStoreError> {
+    let (user, history) = {
+        let user_store = user_store.read().await;
+        let user = user_store.get_user(email).await?;
+        (user, user_store.get_password_history(email).await?)
+    };
+    verify_password_hash(&user.password_hash, current_password)
+        .await
+        .map_err(|_| UserStoreError::InvalidPassword)?;
+    let recent = std::iter::once(&user.password_hash).chain(history.iter().take(history_size));
+    for password_hash in recent {
+        if verify_password_hash(password_hash, new_password)
+            .await
+            .is_ok()
+        {
+            return Err(UserStoreError::InvalidNewPassword(
+                PasswordError::RecentlyUsed(history_size),
+            ));
+        }
+    }
+    let password_hash = compute_password_hash(new_password)
+        .await
+        .map_err(UserStoreError::UnexpectedError)?;
+
+    let mut user_store = user_store.write().await;
+    let current_hash = user_store.get_user(email).await?.password_hash;
+    if current_hash.as_ref().expose_secret() != user.password_hash.as_ref().expose_secret() {
+        verify_password_hash(&current_hash, current_password)
+            .await
+            .map_err(|_| UserStoreError::InvalidPassword)?;
+    }
+    user_store
+        .update_password(email, password_hash, history_size)
+        .await?;
+    Ok(user)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::data_stores::{LegacyPasswordHashCount, UserStore};
+    use crate::domain::language::Language;
+    use crate::domain::password_hash::PasswordHash;
+    use crate::services::hashmap_user_store::HashmapUserStore;
+    use secrecy::Secret;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+
+    fn password(p: &str) -> Password {
+        Password::parse(Secret::new(p.to_string())).unwrap()
+    }
+
+    async fn store_with_user() -> (UserStoreType, Email) {
+        let mut store = HashmapUserStore::default();
+        let user = User::new("herbert@email.com", "password0!", false)
+            .await
+            .unwrap();
+        let email = user.email.clone();
+        store.add_user(user).await.unwrap();
+        (Arc::new(RwLock::new(Box::new(store))), email)
+    }
+
+    async fn change(
+        store: &UserStoreType,
+        email: &Email,
+        from: &str,
+        to: &str,
+    ) -> Result<(), UserStoreError> {
+        change_password(store, email, &password(from), &password(to), 2)
+            .await
+            .map(|_| ())
+    }
+
+    #[tokio::test]
+    async fn test_recent_passwords_are_rejected() {
+        let (store, email) = store_with_user().await;
+        change(&store, &email, "password0!", "password1!")
+            .await
+            .unwrap();
+        change(&store, &email, "password1!", "password2!")
+            .await
+            .unwrap();
+
+        for reused in ["password0!", "password1!", "password2!"] {
+            let result = change(&store, &email, "password2!", reused).await;
+            assert_eq!(
+                result,
+                Err(UserStoreError::InvalidNewPassword(
+                    PasswordError::RecentlyUsed(2)
+                )),
+                "{} should count as reused",
+                reused
+            );
+        }
+        store
+            .read()
+            .await
+            .validate_user(&email, &password("password2!"))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_history_is_pruned() {
+        let (store, email) = store_with_user().await;
+        for [from, to] in [
+            ["password0!", "password1!"],
+            ["password1!", "password2!"],
+            ["password2!", "password3!"],
+        ] {
+            change(&store, &email, from, to).await.unwrap();
+        }
+        let history = store.read().await.get_password_history(&email).await;
+        assert_eq!(history.unwrap().len(), 2);
+
+        // "password0!" has dropped out of the history and may be used again
+        change(&store, &email, "password3!", "password0!")
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_current_password_must_match() {
+        let (store, email) = store_with_user().await;
+        let result = change(&store, &email, "password9!", "password1!").await;
+        assert_eq!(result, Err(UserStoreError::InvalidPassword));
+    }
+
+    // Hands out the user with a different hash of the same password once it was read before,
+    // as if a login had rehashed it in the meantime.
+    struct RehashingStore {
+        inner: HashmapUserStore,
+        rehashed: PasswordHash,
+        reads: AtomicU32,
+    }
+
+    #[async_trait::async_trait]
+    impl UserStore for RehashingStore {
+        async fn add_user(&mut self, user: User) -> Result<(), UserStoreError> {
+            self.inner.add_user(user).await
+        }
+        async fn get_user(&self, email: &Email) -> Result<User, UserStoreError> {
+            let mut user = self.inner.get_user(email).await?;
+            if self.reads.fetch_add(1, Ordering::SeqCst) > 0 {
+                user.password_hash = self.rehashed.clone();
+            }
+            Ok(user)
+        }
+        async fn validate_user(
+            &self,
+            email: &Email,
+            password: &Password,
+        ) -> Result<(), UserStoreError> {
+            self.inner.validate_user(email, password).await
+        }
+        async fn update_password_hash(
+            &mut self,
+            email: &Email,
+            password_hash: PasswordHash,
+        ) -> Result<(), UserStoreError> {
+            self.inner.update_password_hash(email, password_hash).await
+        }
+        fn count_legacy_password_hashes(&self) -> LegacyPasswordHashCount {
+            self.inner.count_legacy_password_hashes()
+        }
+        async fn get_password_history(
+            &self,
+            email: &Email,
+        ) -> Result<Vec<PasswordHash>, UserStoreError> {
+            self.inner.get_password_history(email).await
+        }
+        async fn update_password(
+            &mut self,
+            email: &Email,
+            password_hash: PasswordHash,
+            history_size: usize,
+        ) -> Result<(), UserStoreError> {
+            self.inner
+                .update_password(email, password_hash, history_size)
+                .await
+        }
+        async fn update_language(
+            &mut self,
+            email: &Email,
+            language: Language,
+        ) -> Result<(), UserStoreError> {
+            self.inner.update_language(email, language).await
+        }
+    }
+
+    // The stored hash turns into one of `rehashed` after the first read.
+    async fn rehashing_store(rehashed: &str) -> (UserStoreType, Email) {
+        let mut inner = HashmapUserStore::default();
+        let user = User::new("herbert@email.com", "password0!", false)
+            .await
+            .unwrap();
+        let email = user.email.clone();
+        inner.add_user(user).await.unwrap();
+        let store = RehashingStore {
+            inner,
+            rehashed: compute_password_hash(&password(rehashed)).await.unwrap(),
+            reads: AtomicU32::new(0),
+        };
+        (Arc::new(RwLock::new(Box::new(store))), email)
+    }
+
+    #[tokio::test]
+    async fn test_rehash_in_between_does_not_fail_the_change() {
+        let (store, email) = rehashing_store("password0!").await;
+
+        change(&store, &email, "password0!", "password1!")
+            .await
+            .unwrap();
+        store
+            .read()
+            .await
+            .validate_user(&email, &password("password1!"))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_change_in_between_fails_the_change() {
+        let (store, email) = rehashing_store("password9!").await;
+
+        let result = change(&store, &email, "password0!", "password1!").await;
+        assert_eq!(result, Err(UserStoreError::InvalidPassword));
+    }
+
+    #[tokio::test]
+    async fn test_unknown_user() {
+        let store: UserStoreType = Arc::new(RwLock::new(Box::new(HashmapUserStore::default())));
+        let email = Email::unwrap("nobody@email.com");
+        let result = change(&store, &email, "password0!", "password1!").await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+    }
+}
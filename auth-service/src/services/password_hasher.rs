@@ -0,0 +1,360 @@
+use argon2::{
+    password_hash::SaltString, Algorithm, Argon2, KeyId, Params, ParamsBuilder, PasswordHasher,
+    PasswordVerifier, Version,
+};
+use color_eyre::eyre::{eyre, Result};
+use secrecy::{ExposeSecret, SecretVec};
+use std::sync::OnceLock;
+
+use crate::domain::password::Password;
+use crate::domain::password_hash::{HashAlgorithm, PasswordHash};
+use crate::services::password_pepper::Peppers;
+use crate::settings::{Argon2Settings, PasswordSettings};
+use crate::util::metrics::metrics;
+
+struct HasherConfig {
+    params: Params,
+    peppers: Peppers,
+}
+
+// The user stores hash and verify passwords well below `AppState`, so the hashing settings
+// are installed once at startup instead of being passed down. Until then, e.g. in tests,
+// the default Argon2 parameters are used without a pepper.
+static HASHER_CONFIG: OnceLock<HasherConfig> = OnceLock::new();
+
+pub fn configure(settings: &PasswordSettings) -> Result<()> {
+    let config = HasherConfig {
+        params: settings.argon2.params()?,
+        peppers: settings.peppers()?,
+    };
+    HASHER_CONFIG
+        .set(config)
+        .map_err(|_| eyre!("the password hasher is already configured"))
+}
+
+fn hasher_config() -> &'static HasherConfig {
+    HASHER_CONFIG.get_or_init(|| HasherConfig {
+        params: Argon2Settings::default()
+            .params()
+            .expect("default Argon2 parameters are valid"),
+        peppers: Peppers::default(),
+    })
+}
+
+// Shared by all user stores, so that none of them ever keeps a plaintext password.
+// Hashes of imported users may use a legacy algorithm; each is verified with its own hasher.
+#[tracing::instrument(name = "Verify password hash", skip_all)]
+pub async fn verify_password_hash(
+    expected_password_hash: &PasswordHash,
+    password_candidate: &Password,
+) -> Result<()> {
+    verify_password_hash_with(
+        expected_password_hash,
+        password_candidate,
+        &hasher_config().peppers,
+    )
+    .await
+}
+
+async fn verify_password_hash_with(
+    expected_password_hash: &PasswordHash,
+    password_candidate: &Password,
+    peppers: &Peppers,
+) -> Result<()> {
+    let current_span: tracing::Span = tracing::Span::current();
+
+    let algorithm = expected_password_hash.algorithm();
+    let exp_hash = expected_password_hash.as_ref().to_owned();
+    // Only Argon2 hashes are ever peppered; imported legacy hashes are verified as they are.
+    let cand = match pepper_version(expected_password_hash)? {
+        Some(version) => peppers.apply(
+            version,
+            password_candidate.as_ref().expose_secret().as_bytes(),
+        )?,
+        None => unpeppered(password_candidate),
+    };
+    let _timer = metrics().time_password_hash("verify", algorithm.as_str());
+    tokio::task::spawn_blocking(move || {
+        current_span.in_scope(|| {
+            let cand = cand.expose_secret();
+            let exp_hash = exp_hash.expose_secret();
+            match algorithm {
+                HashAlgorithm::Argon2 => verify_phc_hash(&Argon2::default(), cand, exp_hash),
+                HashAlgorithm::Pbkdf2 => verify_phc_hash(&pbkdf2::Pbkdf2, cand, exp_hash),
+                HashAlgorithm::Scrypt => verify_phc_hash(&scrypt::Scrypt, cand, exp_hash),
+                HashAlgorithm::Bcrypt => match bcrypt::verify(cand, exp_hash)? {
+                    true => Ok(()),
+                    false => Err(eyre!("invalid password")),
+                },
+            }
+        })
+    })
+    .await?
+}
+
+fn verify_phc_hash(
+    verifier: &dyn PasswordVerifier,
+    password_candidate: &[u8],
+    expected_password_hash: &str,
+) -> Result<()> {
+    let expected_password_hash = argon2::PasswordHash::new(expected_password_hash)?;
+    verifier.verify_password(password_candidate, &expected_password_hash)?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Computing password hash", skip_all)]
+pub async fn compute_password_hash(password: &Password) -> Result<PasswordHash> {
+    compute_password_hash_with(password, current_params()?, &hasher_config().peppers).await
+}
+
+async fn compute_password_hash_with(
+    password: &Password,
+    params: Params,
+    peppers: &Peppers,
+) -> Result<PasswordHash> {
+    // The pepper version travels in the hash as the Argon2 `keyid` parameter.
+    let (params, pwd) = match peppers.current_version() {
+        Some(version) => {
+            let params = ParamsBuilder::new()
+                .m_cost(params.m_cost())
+                .t_cost(params.t_cost())
+                .p_cost(params.p_cost())
+                .keyid(KeyId::new(version.to_string().as_bytes())?)
+                .build()?;
+            let pwd = peppers.apply(version, password.as_ref().expose_secret().as_bytes())?;
+            (params, pwd)
+        }
+        None => (params, unpeppered(password)),
+    };
+    let current_span: tracing::Span = tracing::Span::current();
+
+    let _timer = metrics().time_password_hash("hash", HashAlgorithm::Argon2.as_str());
+    tokio::task::spawn_blocking(move || -> Result<PasswordHash> {
+        current_span.in_scope(|| {
+            let salt: SaltString = SaltString::generate(&mut rand::thread_rng());
+            let password_hash = Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+                .hash_password(pwd.expose_secret(), &salt)?
+                .to_string();
+
+            Ok(PasswordHash::parse(password_hash)?)
+        })
+    })
+    .await?
+}
+
+fn unpeppered(password: &Password) -> SecretVec<u8> {
+    SecretVec::new(password.as_ref().expose_secret().as_bytes().to_vec())
+}
+
+// The pepper version recorded in an Argon2 hash, if it was peppered at all.
+fn pepper_version(password_hash: &PasswordHash) -> Result<Option<u16>> {
+    if password_hash.algorithm() != HashAlgorithm::Argon2 {
+        return Ok(None);
+    }
+    let parsed = argon2::PasswordHash::new(password_hash.as_ref().expose_secret())?;
+    let keyid = Params::try_from(&parsed)?.keyid().to_vec();
+    if keyid.is_empty() {
+        return Ok(None);
+    }
+    let version = std::str::from_utf8(&keyid)?.parse()?;
+    Ok(Some(version))
+}
+
+// The Argon2id parameters new hashes are computed with.
+pub fn current_params() -> Result<Params> {
+    Ok(hasher_config().params.clone())
+}
+
+// A hash is outdated if it was not produced by Argon2id v0x13 with the current parameters
+// and the current pepper.
+pub fn needs_rehash(password_hash: &PasswordHash) -> Result<bool> {
+    needs_rehash_with(password_hash, &current_params()?, &hasher_config().peppers)
+}
+
+fn needs_rehash_with(
+    password_hash: &PasswordHash,
+    current: &Params,
+    peppers: &Peppers,
+) -> Result<bool> {
+    if password_hash.algorithm() != HashAlgorithm::Argon2 {
+        return Ok(true);
+    }
+    let parsed = argon2::PasswordHash::new(password_hash.as_ref().expose_secret())?;
+    if parsed.algorithm != Algorithm::Argon2id.ident()
+        || parsed.version != Some(Version::V0x13.into())
+    {
+        return Ok(true);
+    }
+    let params = Params::try_from(&parsed)?;
+    Ok(params.m_cost() != current.m_cost()
+        || params.t_cost() != current.t_cost()
+        || params.p_cost() != current.p_cost()
+        || pepper_version(password_hash)? != peppers.current_version())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use secrecy::Secret;
+
+    fn password(p: &str) -> Password {
+        Password::parse(Secret::new(p.to_string())).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_hash_does_not_contain_password() {
+        let hash = compute_password_hash(&password("password123!"))
+            .await
+            .unwrap();
+        assert!(hash.as_ref().expose_secret().starts_with("$argon2id$"));
+        assert!(!hash.as_ref().expose_secret().contains("password123!"));
+    }
+
+    #[tokio::test]
+    async fn test_verify_password_hash() {
+        let hash = compute_password_hash(&password("password123!"))
+            .await
+            .unwrap();
+        assert!(verify_password_hash(&hash, &password("password123!"))
+            .await
+            .is_ok());
+        assert!(verify_password_hash(&hash, &password("password124!"))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_current_hash_does_not_need_rehash() {
+        let hash = compute_password_hash(&password("password123!"))
+            .await
+            .unwrap();
+        assert!(!needs_rehash(&hash).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_outdated_params_need_rehash() {
+        let legacy_params = Params::new(8192, 1, 1, None).unwrap();
+        let hash = compute_password_hash_with(
+            &password("password123!"),
+            legacy_params,
+            &Peppers::default(),
+        )
+        .await
+        .unwrap();
+        assert!(needs_rehash(&hash).unwrap());
+        // outdated hashes still verify, so the login can go on and rehash them
+        assert!(verify_password_hash(&hash, &password("password123!"))
+            .await
+            .is_ok());
+    }
+
+    #[test]
+    fn test_other_algorithm_needs_rehash() {
+        let hash = PasswordHash::parse(
+            "$argon2i$v=19$m=15000,t=2,p=1$c29tZXNhbHQ$wPB3NvGcZHhkoUdcKVDSYW1tiabnqsrIaeHx4Ctw0rQ"
+                .to_string(),
+        )
+        .unwrap();
+        assert!(needs_rehash(&hash).unwrap());
+    }
+
+    fn legacy_hashes(p: &str) -> Vec<PasswordHash> {
+        let salt = SaltString::generate(&mut rand::thread_rng());
+        let pbkdf2_params = pbkdf2::Params {
+            rounds: 1000,
+            output_length: 32,
+        };
+        let pbkdf2_hash = pbkdf2::Pbkdf2
+            .hash_password_customized(
+                p.as_bytes(),
+                Some(pbkdf2::Algorithm::Pbkdf2Sha256.ident()),
+                None,
+                pbkdf2_params,
+                &salt,
+            )
+            .unwrap();
+        let scrypt_params = scrypt::Params::new(4, 8, 1, 32).unwrap();
+        let scrypt_hash = scrypt::Scrypt
+            .hash_password_customized(p.as_bytes(), None, None, scrypt_params, &salt)
+            .unwrap();
+        [
+            bcrypt::hash(p, 4).unwrap(),
+            pbkdf2_hash.to_string(),
+            scrypt_hash.to_string(),
+        ]
+        .into_iter()
+        .map(|hash| PasswordHash::parse(hash).unwrap())
+        .collect()
+    }
+
+    #[tokio::test]
+    async fn test_verify_legacy_password_hashes() {
+        for hash in legacy_hashes("password123!") {
+            assert!(verify_password_hash(&hash, &password("password123!"))
+                .await
+                .is_ok());
+            assert!(verify_password_hash(&hash, &password("password124!"))
+                .await
+                .is_err());
+            assert!(needs_rehash(&hash).unwrap());
+        }
+    }
+
+    fn peppers(spec: &str) -> Peppers {
+        Peppers::parse(&Secret::new(spec.to_string())).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_peppered_hash_records_version() {
+        let v1 = peppers("1:old-key");
+        let hash =
+            compute_password_hash_with(&password("password123!"), current_params().unwrap(), &v1)
+                .await
+                .unwrap();
+        assert_eq!(pepper_version(&hash).unwrap(), Some(1));
+        assert!(
+            verify_password_hash_with(&hash, &password("password123!"), &v1)
+                .await
+                .is_ok()
+        );
+        // without the pepper, a cracked database dump is not enough
+        assert!(
+            verify_password_hash_with(&hash, &password("password123!"), &Peppers::default())
+                .await
+                .is_err()
+        );
+        assert!(!needs_rehash_with(&hash, &current_params().unwrap(), &v1).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_rotated_pepper_needs_rehash() {
+        let v1 = peppers("1:old-key");
+        let v2 = peppers("1:old-key\n2:new-key");
+        let params = current_params().unwrap();
+        let unpeppered = compute_password_hash_with(
+            &password("password123!"),
+            params.clone(),
+            &Peppers::default(),
+        )
+        .await
+        .unwrap();
+        let old = compute_password_hash_with(&password("password123!"), params.clone(), &v1)
+            .await
+            .unwrap();
+
+        // the old pepper is still available to verify, but the hash should be re-peppered
+        assert!(
+            verify_password_hash_with(&old, &password("password123!"), &v2)
+                .await
+                .is_ok()
+        );
+        assert!(needs_rehash_with(&old, &params, &v2).unwrap());
+        assert!(needs_rehash_with(&unpeppered, &params, &v2).unwrap());
+
+        let new = compute_password_hash_with(&password("password123!"), params.clone(), &v2)
+            .await
+            .unwrap();
+        assert_eq!(pepper_version(&new).unwrap(), Some(2));
+        assert!(!needs_rehash_with(&new, &params, &v2).unwrap());
+    }
+}
@@ -0,0 +1,105 @@
+use std::collections::BTreeMap;
+
+use color_eyre::eyre::{eyre, Context, Result};
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret, SecretVec};
+use sha2::Sha256;
+
+// Server-side secrets mixed into every password with HMAC-SHA256 before it is hashed,
+// so that a database dump alone is not enough to crack the stored hashes.
+//
+// Every pepper has a version, which is recorded in the hashes it was used for.
+// The highest version is the current one; older versions are only kept around to verify
+// existing hashes until their users log in again and get re-peppered.
+#[derive(Default)]
+pub struct Peppers {
+    peppers: BTreeMap<u16, SecretVec<u8>>,
+}
+
+impl Peppers {
+    // One pepper per line as `<version>:<key>`. Empty lines and lines starting with `#` are ignored.
+    pub fn parse(spec: &Secret<String>) -> Result<Self> {
+        let mut peppers = BTreeMap::new();
+        for line in spec.expose_secret().lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (version, key) = line
+                .split_once(':')
+                .ok_or(eyre!("pepper must be given as <version>:<key>"))?;
+            let version: u16 = version
+                .trim()
+                .parse()
+                .wrap_err("pepper version must be a number between 0 and 65535")?;
+            if key.is_empty() {
+                return Err(eyre!("pepper {} has an empty key", version));
+            }
+            if peppers
+                .insert(version, SecretVec::new(key.as_bytes().to_vec()))
+                .is_some()
+            {
+                return Err(eyre!("pepper {} is defined twice", version));
+            }
+        }
+        Ok(Peppers { peppers })
+    }
+
+    // The version new hashes are peppered with, or `None` if peppering is disabled.
+    pub fn current_version(&self) -> Option<u16> {
+        self.peppers.keys().next_back().copied()
+    }
+
+    // HMAC-SHA256 of the password, keyed with the pepper of the given version.
+    pub fn apply(&self, version: u16, password: &[u8]) -> Result<SecretVec<u8>> {
+        let key = self
+            .peppers
+            .get(&version)
+            .ok_or(eyre!("pepper version {} is not configured", version))?;
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(key.expose_secret()).wrap_err("invalid pepper key")?;
+        mac.update(password);
+        Ok(SecretVec::new(mac.finalize().into_bytes().to_vec()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peppers(spec: &str) -> Result<Peppers> {
+        Peppers::parse(&Secret::new(spec.to_string()))
+    }
+
+    #[test]
+    fn test_parse_versions() {
+        let peppers = peppers("# rotated 2026-10\n1:old-key\n\n2:new:key-with-colon\n").unwrap();
+        assert_eq!(peppers.current_version(), Some(2));
+        assert!(peppers.apply(1, b"password123!").is_ok());
+        assert!(peppers.apply(3, b"password123!").is_err());
+    }
+
+    #[test]
+    fn test_no_pepper() {
+        assert_eq!(peppers("").unwrap().current_version(), None);
+    }
+
+    #[test]
+    fn test_invalid_spec_is_rejected() {
+        assert!(peppers("secret-without-version").is_err());
+        assert!(peppers("x:key").is_err());
+        assert!(peppers("1:").is_err());
+        assert!(peppers("1:a\n1:b").is_err());
+    }
+
+    #[test]
+    fn test_versions_give_different_outputs() {
+        let peppers = peppers("1:old-key\n2:new-key").unwrap();
+        let old = peppers.apply(1, b"password123!").unwrap();
+        let new = peppers.apply(2, b"password123!").unwrap();
+        assert_ne!(old.expose_secret(), new.expose_secret());
+        assert_eq!(
+            old.expose_secret(),
+            peppers.apply(1, b"password123!").unwrap().expose_secret()
+        );
+    }
+}
@@ -0,0 +1,211 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+use color_eyre::eyre::{eyre, Context, Result};
+use lettre::message::{Mailbox, MultiPart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::transport::smtp::client::{Tls, TlsParameters};
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use secrecy::{ExposeSecret, Secret};
+use serde::Deserialize;
+
+use crate::domain::{Email, EmailClient, EmailMessage};
+use crate::settings::EmailSettings;
+
+// How the connection to the SMTP server is secured.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum SmtpTls {
+    // Plain text, only meant for local relays and tests.
+    None,
+    // Connect in plain text and upgrade with STARTTLS, usually on port 587.
+    StartTls,
+    // TLS from the first byte ("SMTPS"), usually on port 465.
+    Implicit,
+}
+
+impl SmtpTls {
+    fn default_port(&self) -> u16 {
+        match self {
+            SmtpTls::None => 25,
+            SmtpTls::StartTls => 587,
+            SmtpTls::Implicit => 465,
+        }
+    }
+}
+
+impl TryFrom<String> for SmtpTls {
+    type Error = color_eyre::Report;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl FromStr for SmtpTls {
+    type Err = color_eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" => Ok(SmtpTls::None),
+            "starttls" => Ok(SmtpTls::StartTls),
+            "tls" | "implicit" => Ok(SmtpTls::Implicit),
+            _ => Err(eyre!("expected one of none, starttls or tls, got {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SmtpConfig {
+    pub host: String,
+    pub port: u16,
+    pub tls: SmtpTls,
+    pub username: Option<String>,
+    pub password: Option<Secret<String>>,
+    // e.g. `Auth Service <no-reply@example.com>`
+    pub from: String,
+    pub timeout: Duration,
+}
+
+impl SmtpConfig {
+    // `None` if `email.smtp.host` is not set, i.e. SMTP is not configured at all.
+    pub fn from_settings(email: &EmailSettings) -> Result<Option<Self>> {
+        let Some(host) = email.smtp.host.clone() else {
+            return Ok(None);
+        };
+        let from = email
+            .from
+            .clone()
+            .ok_or(eyre!("email.from must be set to send emails"))?;
+        Ok(Some(SmtpConfig {
+            host,
+            port: email.smtp.port.unwrap_or(email.smtp.tls.default_port()),
+            tls: email.smtp.tls,
+            username: email.smtp.username.clone(),
+            password: email.smtp.password.clone(),
+            from,
+            timeout: email.smtp.timeout,
+        }))
+    }
+}
+
+pub const DEFAULT_SMTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct SmtpEmailClient {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl SmtpEmailClient {
+    pub fn new(config: SmtpConfig) -> Result<Self> {
+        let from: Mailbox = config
+            .from
+            .parse()
+            .wrap_err(format!("invalid sender address {}", config.from))?;
+        let tls = match config.tls {
+            SmtpTls::None => Tls::None,
+            SmtpTls::StartTls => Tls::Required(TlsParameters::new(config.host.clone())?),
+            SmtpTls::Implicit => Tls::Wrapper(TlsParameters::new(config.host.clone())?),
+        };
+        let mut builder = AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.host)
+            .port(config.port)
+            .tls(tls)
+            .timeout(Some(config.timeout));
+        match (config.username, config.password) {
+            (Some(username), Some(password)) => {
+                builder = builder.credentials(Credentials::new(
+                    username,
+                    password.expose_secret().to_owned(),
+                ));
+            }
+            (None, None) => {}
+            _ => return Err(eyre!("SMTP username and password must be set together")),
+        }
+        Ok(SmtpEmailClient {
+            transport: builder.build(),
+            from,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailClient for SmtpEmailClient {
+    #[tracing::instrument(name = "Sending email via SMTP", skip_all)]
+    async fn send_email(&self, recipient: &Email, message: &EmailMessage) -> Result<()> {
+        let to: Mailbox = recipient
+            .as_ref()
+            .expose_secret()
+            .parse()
+            .wrap_err("invalid recipient address")?;
+        let email = Message::builder()
+            .from(self.from.clone())
+            .to(to)
+            .subject(&message.subject)
+            .multipart(MultiPart::alternative_plain_html(
+                message.text_body.clone(),
+                message.html_body.clone(),
+            ))
+            .wrap_err("failed to build email")?;
+        self.transport
+            .send(email)
+            .await
+            .wrap_err("failed to send email")?;
+        Ok(())
+    }
+
+    // Connects (or reuses a pooled connection) and sends NOOP.
+    async fn health_check(&self) -> Result<()> {
+        let connected = self
+            .transport
+            .test_connection()
+            .await
+            .wrap_err("failed to connect to SMTP server")?;
+        if !connected {
+            return Err(eyre!("SMTP server did not answer NOOP"));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(tls: SmtpTls) -> SmtpConfig {
+        SmtpConfig {
+            host: "smtp.example.com".to_owned(),
+            port: tls.default_port(),
+            tls,
+            username: None,
+            password: None,
+            from: "Auth Service <no-reply@example.com>".to_owned(),
+            timeout: DEFAULT_SMTP_TIMEOUT,
+        }
+    }
+
+    #[test]
+    fn test_parse_tls_mode() {
+        assert_eq!("none".parse::<SmtpTls>().unwrap(), SmtpTls::None);
+        assert_eq!("STARTTLS".parse::<SmtpTls>().unwrap(), SmtpTls::StartTls);
+        assert_eq!("tls".parse::<SmtpTls>().unwrap(), SmtpTls::Implicit);
+        assert!("ssl3".parse::<SmtpTls>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_build_client() {
+        for tls in [SmtpTls::None, SmtpTls::StartTls, SmtpTls::Implicit] {
+            assert!(SmtpEmailClient::new(config(tls)).is_ok());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_invalid_config_is_rejected() {
+        let mut invalid_from = config(SmtpTls::StartTls);
+        invalid_from.from = "not an address".to_owned();
+        assert!(SmtpEmailClient::new(invalid_from).is_err());
+
+        let mut missing_password = config(SmtpTls::StartTls);
+        missing_password.username = Some("user".to_owned());
+        assert!(SmtpEmailClient::new(missing_password).is_err());
+    }
+}
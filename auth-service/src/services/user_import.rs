@@ -0,0 +1,188 @@
+use std::io::BufRead;
+
+use color_eyre::eyre::{eyre, Result};
+use serde::Deserialize;
+
+use crate::domain::data_stores::{UserStore, UserStoreError};
+use crate::domain::email::Email;
+use crate::domain::password_hash::PasswordHash;
+use crate::domain::user::User;
+
+// One user exported from the old system. The hash is kept as-is and upgraded
+// to Argon2id on the user's next successful login.
+#[derive(Deserialize)]
+pub struct ImportRow {
+    pub email: String,
+    pub hash: String,
+    pub requires_2fa: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImportFormat {
+    Csv,
+    Jsonl,
+}
+
+impl ImportFormat {
+    pub fn from_path(path: &str) -> Result<Self> {
+        match path.rsplit_once('.').map(|(_, ext)| ext) {
+            Some("csv") => Ok(ImportFormat::Csv),
+            Some("jsonl") => Ok(ImportFormat::Jsonl),
+            _ => Err(eyre!("expected a .csv or .jsonl file, got {}", path)),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct RejectedRow {
+    pub line: u64,
+    pub reason: String,
+}
+
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub imported: usize,
+    pub rejected: Vec<RejectedRow>,
+}
+
+// Imports every valid row into `user_store`. Invalid rows do not abort the import;
+// they are collected in the report together with their line number.
+#[tracing::instrument(name = "Importing users", skip_all)]
+pub async fn import_users<R: BufRead>(
+    reader: R,
+    format: ImportFormat,
+    user_store: &mut dyn UserStore,
+) -> Result<ImportReport> {
+    let mut report = ImportReport::default();
+    for (line, row) in read_rows(reader, format)? {
+        let result = match row {
+            Ok(row) => import_row(row, user_store).await,
+            Err(reason) => Err(reason),
+        };
+        match result {
+            Ok(()) => report.imported += 1,
+            Err(reason) => report.rejected.push(RejectedRow { line, reason }),
+        }
+    }
+    Ok(report)
+}
+
+type ParsedRow = (u64, Result<ImportRow, String>);
+
+fn read_rows<R: BufRead>(reader: R, format: ImportFormat) -> Result<Vec<ParsedRow>> {
+    let rows = match format {
+        ImportFormat::Csv => csv::Reader::from_reader(reader)
+            .deserialize::<ImportRow>()
+            .enumerate()
+            .map(|(i, row)| {
+                let line = match &row {
+                    Ok(_) => i as u64 + 2,
+                    Err(e) => e.position().map_or(i as u64 + 2, |p| p.line()),
+                };
+                (line, row.map_err(|e| format!("malformed row: {}", e)))
+            })
+            .collect(),
+        ImportFormat::Jsonl => {
+            let mut rows = Vec::new();
+            for (i, line) in reader.lines().enumerate() {
+                let line_content = line?;
+                if line_content.trim().is_empty() {
+                    continue;
+                }
+                let row = serde_json::from_str::<ImportRow>(&line_content)
+                    .map_err(|e| format!("malformed row: {}", e));
+                rows.push((i as u64 + 1, row));
+            }
+            rows
+        }
+    };
+    Ok(rows)
+}
+
+async fn import_row(row: ImportRow, user_store: &mut dyn UserStore) -> Result<(), String> {
+    let email = Email::parse(row.email).map_err(|e| format!("invalid email: {}", e))?;
+    let password_hash =
+        PasswordHash::parse(row.hash).map_err(|e| format!("invalid hash: {}", e))?;
+    let user = User::new2(email, password_hash, row.requires_2fa);
+    user_store.add_user(user).await.map_err(|e| match e {
+        UserStoreError::UserAlreadyExists => "user already exists".to_string(),
+        e => format!("could not store user: {:?}", e),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::password::Password;
+    use crate::services::hashmap_user_store::HashmapUserStore;
+    use secrecy::Secret;
+
+    const BCRYPT_HASH: &str = "$2b$04$SU6FTRoLIY.i0hQraKudM..2OSoN.5OhSUBeIH9K4ogTHVQAXUJDa";
+    const PBKDF2_HASH: &str = "$pbkdf2-sha256$i=1000,l=32$YGmqm6LXbGZvFpdIjzHFUg$OzJs5ERvlG6WXYAnJLYxyEotp9AkJoV3MV7iGocnnqE";
+
+    #[tokio::test]
+    async fn test_import_csv() {
+        // PHC parameters contain commas, so those hashes have to be quoted
+        let input = format!(
+            "email,hash,requires_2fa\n\
+             bob@example.com,{},false\n\
+             not-an-email,{},false\n\
+             alice@example.com,\"{}\",true\n\
+             bob@example.com,\"{}\",false\n\
+             carol@example.com,password123!,false\n",
+            BCRYPT_HASH, BCRYPT_HASH, PBKDF2_HASH, PBKDF2_HASH
+        );
+        let mut store = HashmapUserStore::default();
+        let report = import_users(input.as_bytes(), ImportFormat::Csv, &mut store)
+            .await
+            .unwrap();
+
+        assert_eq!(report.imported, 2);
+        let lines: Vec<u64> = report.rejected.iter().map(|r| r.line).collect();
+        assert_eq!(lines, vec![3, 5, 6]);
+
+        let password = Password::parse(Secret::new("password123!".to_string())).unwrap();
+        store
+            .validate_user(&Email::unwrap("alice@example.com"), &password)
+            .await
+            .expect("imported PBKDF2 hash should verify");
+    }
+
+    #[tokio::test]
+    async fn test_import_jsonl() {
+        let input = format!(
+            "{{\"email\": \"bob@example.com\", \"hash\": \"{}\", \"requires_2fa\": true}}\n\
+             \n\
+             {{\"email\": \"alice@example.com\"}}\n",
+            BCRYPT_HASH
+        );
+        let mut store = HashmapUserStore::default();
+        let report = import_users(input.as_bytes(), ImportFormat::Jsonl, &mut store)
+            .await
+            .unwrap();
+
+        assert_eq!(report.imported, 1);
+        assert_eq!(report.rejected.len(), 1);
+        assert_eq!(report.rejected[0].line, 3);
+        assert!(
+            store
+                .get_user(&Email::unwrap("bob@example.com"))
+                .await
+                .unwrap()
+                .requires_2fa
+        );
+    }
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(
+            ImportFormat::from_path("users.csv").unwrap(),
+            ImportFormat::Csv
+        );
+        assert_eq!(
+            ImportFormat::from_path("export/users.jsonl").unwrap(),
+            ImportFormat::Jsonl
+        );
+        assert!(ImportFormat::from_path("users.txt").is_err());
+    }
+}
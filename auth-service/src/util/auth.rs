@@ -1,44 +1,72 @@
-use super::constants::{JWT_COOKIE_NAME, JWT_SECRET};
+use crate::app_state::AppState;
 use crate::domain::email::Email;
+use crate::domain::error::AuthAPIError;
+use crate::settings::{CookieSameSite, JwtSettings, Settings};
+use axum::http::{header, HeaderMap};
 use axum_extra::extract::cookie::{Cookie, SameSite};
+use axum_extra::extract::CookieJar;
 use chrono::Utc;
-use color_eyre::eyre::{eyre, Context, ContextCompat, Result};
+use color_eyre::eyre::{eyre, Context, Result};
 use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Validation};
 use secrecy::ExposeSecret;
 use serde::{Deserialize, Serialize};
 
 // Create cookie with a new JWT auth token
 #[tracing::instrument(name = "generate_auth_cookie", skip_all)]
-pub fn generate_auth_cookie(email: &Email) -> Result<Cookie<'static>> {
-    let token = generate_auth_token(email)?;
-    Ok(create_auth_cookie(token))
+pub fn generate_auth_cookie(email: &Email, settings: &Settings) -> Result<Cookie<'static>> {
+    let token = generate_auth_token(email, &settings.jwt)?;
+    create_auth_cookie(token, settings)
 }
 
-// Create cookie and set the value to the passed-in token string
+// Create cookie and set the value to the passed-in token string. It expires together with the
+// token.
 #[tracing::instrument(name = "create_auth_cookie", skip_all)]
-fn create_auth_cookie(token: String) -> Cookie<'static> {
-    let cookie = Cookie::build((JWT_COOKIE_NAME, token))
+fn create_auth_cookie(token: String, settings: &Settings) -> Result<Cookie<'static>> {
+    let max_age = time::Duration::try_from(settings.jwt.token_ttl)
+        .wrap_err("failed to convert token TTL to a cookie max age")?;
+    let mut cookie = auth_cookie(token, settings);
+    cookie.set_max_age(max_age);
+    Ok(cookie)
+}
+
+// A cookie that makes browsers drop the auth cookie. Its path and domain have to match the ones
+// it was set with, and a `__Host-` cookie is only replaced by another Secure one.
+pub fn removal_cookie(settings: &Settings) -> Cookie<'static> {
+    let mut cookie = auth_cookie(String::new(), settings);
+    cookie.make_removal();
+    cookie
+}
+
+// The attributes from `auth_cookie`.
+fn auth_cookie(value: String, settings: &Settings) -> Cookie<'static> {
+    let cookie_settings = &settings.auth_cookie;
+    let same_site = match cookie_settings.same_site {
+        CookieSameSite::Strict => SameSite::Strict,
+        CookieSameSite::Lax => SameSite::Lax,
+        CookieSameSite::None => SameSite::None,
+    };
+    let mut cookie = Cookie::build((cookie_settings.full_name(), value))
         .path("/") // apply cookie to all URLs on the server
         .http_only(true) // prevent JavaScript from accessing the cookie
-        .same_site(SameSite::Lax) // send cookie with "same-site" requests, and with "cross-site" top-level navigations.
+        .same_site(same_site)
+        .secure(cookie_settings.is_secure(&settings.tls)) // only send the cookie over HTTPS
         .build();
-
+    if let Some(domain) = &cookie_settings.domain {
+        cookie.set_domain(domain.clone());
+    }
     cookie
 }
 
-// This value determines how long the JWT auth token is valid for
-pub const TOKEN_TTL_SECONDS: i64 = 600; // 10 minutes
-
-// Create JWT auth token
+// Create JWT auth token, valid for `jwt.token_ttl`
 #[tracing::instrument(name = "generate_auth_token", skip_all)]
-fn generate_auth_token(email: &Email) -> Result<String> {
-    let delta = chrono::Duration::try_seconds(TOKEN_TTL_SECONDS)
-        .wrap_err("failed to create 10 minute time delta")?;
+fn generate_auth_token(email: &Email, jwt: &JwtSettings) -> Result<String> {
+    let delta = chrono::Duration::from_std(jwt.token_ttl)
+        .wrap_err("failed to create token TTL time delta")?;
 
     // Create JWT expiration time
     let exp = Utc::now()
         .checked_add_signed(delta)
-        .ok_or(eyre!("failed to add 10 minutes to current time"))?
+        .ok_or(eyre!("failed to add token TTL to current time"))?
         .timestamp();
 
     // Cast exp to a usize, which is what Claims expects
@@ -51,28 +79,78 @@ fn generate_auth_token(email: &Email) -> Result<String> {
 
     let claims = Claims { sub, exp };
 
-    create_token(&claims)
+    create_token(&claims, jwt)
 }
 
 // Check if JWT auth token is valid by decoding it using the JWT secret
 #[tracing::instrument(name = "validate_token", skip_all)]
-pub async fn validate_token(token: &str) -> Result<Claims> {
+pub async fn validate_token(token: &str, jwt: &JwtSettings) -> Result<Claims> {
     decode::<Claims>(
         token,
-        &DecodingKey::from_secret(JWT_SECRET.as_bytes()),
+        &DecodingKey::from_secret(jwt.secret.expose_secret().as_bytes()),
         &Validation::default(),
     )
     .map(|data| data.claims)
     .wrap_err("failed to decode token")
 }
 
+// The JWT a request carries: in `Authorization: Bearer`, as non-browser clients send it, or
+// else in the auth cookie.
+pub fn request_token(
+    settings: &Settings,
+    headers: &HeaderMap,
+    jar: &CookieJar,
+) -> std::result::Result<String, AuthAPIError> {
+    if let Some(token) = bearer_token(headers) {
+        return Ok(token.to_owned());
+    }
+    jar.get(&settings.auth_cookie.full_name())
+        .map(|cookie| cookie.value().to_owned())
+        .ok_or(AuthAPIError::MissingToken)
+}
+
+pub fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+}
+
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+// The user a request was made by, from its JWT. Tokens banned on logout are rejected.
+#[tracing::instrument(name = "authenticate", skip_all)]
+pub async fn authenticated_email(
+    state: &AppState,
+    headers: &HeaderMap,
+    jar: &CookieJar,
+) -> std::result::Result<Email, AuthAPIError> {
+    let token = request_token(&state.settings, headers, jar)?;
+    let claims = validate_token(&token, &state.settings.jwt)
+        .await
+        .map_err(|_| AuthAPIError::InvalidToken)?;
+    let banned = state
+        .ban_store
+        .read()
+        .await
+        .contains_token(&token)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+    if banned {
+        return Err(AuthAPIError::InvalidToken);
+    }
+    Email::parse(claims.sub).map_err(|_| AuthAPIError::InvalidToken)
+}
+
 // Create JWT auth token by encoding claims using the JWT secret
 #[tracing::instrument(name = "create_token", skip_all)]
-fn create_token(claims: &Claims) -> Result<String> {
+fn create_token(claims: &Claims, jwt: &JwtSettings) -> Result<String> {
     encode(
         &jsonwebtoken::Header::default(),
         &claims,
-        &EncodingKey::from_secret(JWT_SECRET.as_bytes()),
+        &EncodingKey::from_secret(jwt.secret.expose_secret().as_bytes()),
     )
     .wrap_err("failed to create token")
 }
@@ -86,11 +164,28 @@ pub struct Claims {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::settings::AuthCookieSettings;
+    use crate::util::constants::JWT_COOKIE_NAME;
+    use secrecy::Secret;
+
+    fn jwt() -> JwtSettings {
+        JwtSettings {
+            secret: Secret::new("test-secret".to_owned()),
+            ..JwtSettings::default()
+        }
+    }
+
+    fn settings() -> Settings {
+        Settings {
+            jwt: jwt(),
+            ..Settings::default()
+        }
+    }
 
     #[tokio::test]
     async fn test_generate_auth_cookie() {
         let email = Email::parse("test@example.com".to_owned()).unwrap();
-        let cookie = generate_auth_cookie(&email).unwrap();
+        let cookie = generate_auth_cookie(&email, &settings()).unwrap();
         assert_eq!(cookie.name(), JWT_COOKIE_NAME);
         assert_eq!(cookie.value().split('.').count(), 3);
         assert_eq!(cookie.path(), Some("/"));
@@ -101,26 +196,78 @@ mod tests {
     #[tokio::test]
     async fn test_create_auth_cookie() {
         let token = "test_token".to_owned();
-        let cookie = create_auth_cookie(token.clone());
+        let mut settings = settings();
+        let cookie = create_auth_cookie(token.clone(), &settings).unwrap();
         assert_eq!(cookie.name(), JWT_COOKIE_NAME);
         assert_eq!(cookie.value(), token);
         assert_eq!(cookie.path(), Some("/"));
         assert_eq!(cookie.http_only(), Some(true));
         assert_eq!(cookie.same_site(), Some(SameSite::Lax));
+        assert_eq!(cookie.secure(), Some(false));
+        assert_eq!(cookie.domain(), None);
+        assert_eq!(cookie.max_age(), Some(time::Duration::minutes(10)));
+
+        // Secure follows TLS unless set
+        settings.tls.enabled = true;
+        let cookie = create_auth_cookie(token.clone(), &settings).unwrap();
+        assert_eq!(cookie.secure(), Some(true));
+        settings.auth_cookie.secure = Some(false);
+        let cookie = create_auth_cookie(token, &settings).unwrap();
+        assert_eq!(cookie.secure(), Some(false));
+    }
+
+    #[tokio::test]
+    async fn test_auth_cookie_attributes_are_configurable() {
+        let mut settings = settings();
+        settings.jwt.token_ttl = std::time::Duration::from_secs(3600);
+        settings.auth_cookie = AuthCookieSettings {
+            name: "session".to_owned(),
+            host_prefix: false,
+            secure: Some(true),
+            same_site: CookieSameSite::Strict,
+            domain: Some("example.com".to_owned()),
+        };
+        let cookie = create_auth_cookie("token".to_owned(), &settings).unwrap();
+        assert_eq!(
+            cookie.to_string(),
+            "session=token; HttpOnly; SameSite=Strict; Secure; Path=/; Domain=example.com; Max-Age=3600"
+        );
+
+        settings.auth_cookie.host_prefix = true;
+        settings.auth_cookie.domain = None;
+        settings.auth_cookie.same_site = CookieSameSite::None;
+        let cookie = create_auth_cookie("token".to_owned(), &settings).unwrap();
+        assert_eq!(cookie.name(), "__Host-session");
+        assert_eq!(cookie.same_site(), Some(SameSite::None));
+    }
+
+    #[test]
+    fn test_removal_cookie_matches_the_auth_cookie() {
+        let mut settings = settings();
+        settings.auth_cookie.secure = Some(true);
+        settings.auth_cookie.domain = Some("example.com".to_owned());
+        let cookie = removal_cookie(&settings);
+        assert_eq!(cookie.name(), JWT_COOKIE_NAME);
+        assert_eq!(cookie.value(), "");
+        assert_eq!(cookie.path(), Some("/"));
+        assert_eq!(cookie.domain(), Some("example.com"));
+        assert_eq!(cookie.secure(), Some(true));
+        assert_eq!(cookie.max_age(), Some(time::Duration::ZERO));
+        assert!(cookie.expires_datetime().is_some());
     }
 
     #[tokio::test]
     async fn test_generate_auth_token() {
         let email = Email::parse("test@example.com".to_owned()).unwrap();
-        let result = generate_auth_token(&email).unwrap();
+        let result = generate_auth_token(&email, &jwt()).unwrap();
         assert_eq!(result.split('.').count(), 3);
     }
 
     #[tokio::test]
     async fn test_validate_token_with_valid_token() {
         let email = Email::unwrap("test@example.com");
-        let token = generate_auth_token(&email).unwrap();
-        let result = validate_token(&token).await.unwrap();
+        let token = generate_auth_token(&email, &jwt()).unwrap();
+        let result = validate_token(&token, &jwt()).await.unwrap();
         assert_eq!(result.sub, "test@example.com");
 
         let exp = Utc::now()
@@ -134,7 +281,31 @@ mod tests {
     #[tokio::test]
     async fn test_validate_token_with_invalid_token() {
         let token = "invalid_token".to_owned();
-        let result = validate_token(&token).await;
+        let result = validate_token(&token, &jwt()).await;
         assert!(result.is_err());
     }
+
+    #[tokio::test]
+    async fn test_validate_token_signed_with_other_secret() {
+        let email = Email::unwrap("test@example.com");
+        let token = generate_auth_token(&email, &jwt()).unwrap();
+        let other = JwtSettings {
+            secret: Secret::new("other-secret".to_owned()),
+            ..JwtSettings::default()
+        };
+        assert!(validate_token(&token, &other).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_token_expiry_follows_ttl() {
+        let email = Email::unwrap("test@example.com");
+        let jwt = JwtSettings {
+            token_ttl: std::time::Duration::from_secs(3600),
+            ..jwt()
+        };
+        let token = generate_auth_token(&email, &jwt).unwrap();
+        let claims = validate_token(&token, &jwt).await.unwrap();
+        let in_59_minutes = (Utc::now() + chrono::Duration::minutes(59)).timestamp() as usize;
+        assert!(claims.exp > in_59_minutes);
+    }
 }
@@ -0,0 +1,140 @@
+use axum::http::{HeaderName, HeaderValue, Method};
+use color_eyre::eyre::{Context, Result};
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+use super::request_id::REQUEST_ID_HEADER;
+use crate::settings::CorsSettings;
+
+// The CORS policy from `cors`; the settings have been validated.
+pub fn cors_layer(settings: &CorsSettings) -> Result<CorsLayer> {
+    let methods = settings
+        .allowed_methods
+        .iter()
+        .map(|method| method.parse())
+        .collect::<Result<Vec<Method>, _>>()
+        .wrap_err("invalid cors.allowed_methods")?;
+    let headers = settings
+        .allowed_headers
+        .iter()
+        .map(|header| header.parse())
+        .collect::<Result<Vec<HeaderName>, _>>()
+        .wrap_err("invalid cors.allowed_headers")?;
+    let allowed_origins = settings.allowed_origins.clone();
+    let allow_origin = match allowed_origins.iter().any(|origin| origin == "*") {
+        true => AllowOrigin::any(),
+        false => AllowOrigin::predicate(move |origin: &HeaderValue, _| {
+            origin
+                .to_str()
+                .is_ok_and(|origin| is_allowed_origin(&allowed_origins, origin))
+        }),
+    };
+
+    Ok(CorsLayer::new()
+        .allow_origin(allow_origin)
+        .allow_methods(methods)
+        .allow_headers(headers)
+        .allow_credentials(settings.allow_credentials)
+        .max_age(settings.max_age)
+        .expose_headers([REQUEST_ID_HEADER]))
+}
+
+// Whether `origin` is one of `cors.allowed_origins`, or matches one of its patterns.
+pub fn is_allowed_origin(allowed_origins: &[String], origin: &str) -> bool {
+    allowed_origins
+        .iter()
+        .any(|pattern| origin_matches(pattern, origin))
+}
+
+// Like `is_allowed_origin`, but without `*`: for deciding who may send requests with the user's
+// cookies, rather than who may read responses.
+pub fn is_trusted_origin(allowed_origins: &[String], origin: &str) -> bool {
+    allowed_origins
+        .iter()
+        .filter(|pattern| *pattern != "*")
+        .any(|pattern| origin_matches(pattern, origin))
+}
+
+// `https://*.example.com` matches `https://app.example.com` and `https://a.b.example.com`, but
+// neither `https://example.com` itself nor `http://app.example.com` or
+// `https://app.example.com:8443`.
+fn origin_matches(pattern: &str, origin: &str) -> bool {
+    let (pattern, origin) = (pattern.to_ascii_lowercase(), origin.to_ascii_lowercase());
+    if pattern == "*" {
+        return true;
+    }
+    let Some((scheme, domain)) = pattern.split_once("://*.") else {
+        return pattern == origin;
+    };
+    let subdomain = origin
+        .strip_prefix(scheme)
+        .and_then(|rest| rest.strip_prefix("://"))
+        .and_then(|host| host.strip_suffix(domain))
+        .and_then(|rest| rest.strip_suffix('.'));
+    subdomain.is_some_and(|subdomain| {
+        subdomain.split('.').all(|label| {
+            !label.is_empty()
+                && label
+                    .bytes()
+                    .all(|byte| byte.is_ascii_alphanumeric() || byte == b'-')
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exact_origins() {
+        let allowed = ["http://localhost:8000".to_owned()];
+        assert!(is_allowed_origin(&allowed, "http://localhost:8000"));
+        assert!(is_allowed_origin(&allowed, "HTTP://LOCALHOST:8000"));
+        assert!(!is_allowed_origin(&allowed, "http://localhost:3000"));
+        assert!(!is_allowed_origin(&allowed, "https://localhost:8000"));
+        assert!(!is_allowed_origin(&[], "http://localhost:8000"));
+    }
+
+    #[test]
+    fn test_wildcard_subdomains() {
+        let allowed = ["https://*.example.com".to_owned()];
+        for origin in [
+            "https://app.example.com",
+            "https://a.b.example.com",
+            "https://App.Example.com",
+        ] {
+            assert!(is_allowed_origin(&allowed, origin), "{}", origin);
+        }
+        for origin in [
+            "https://example.com",
+            "https://.example.com",
+            "http://app.example.com",
+            "https://app.example.com:8443",
+            "https://appexample.com",
+            "https://app.example.com.evil.example",
+            "https://evil.example/.example.com",
+            "https://a..example.com",
+            "null",
+        ] {
+            assert!(!is_allowed_origin(&allowed, origin), "{}", origin);
+        }
+
+        let allowed = ["http://*.localhost:8000".to_owned()];
+        assert!(is_allowed_origin(&allowed, "http://app.localhost:8000"));
+        assert!(!is_allowed_origin(&allowed, "http://app.localhost:3000"));
+    }
+
+    #[test]
+    fn test_any_origin() {
+        assert!(is_allowed_origin(
+            &["*".to_owned()],
+            "https://anything.example"
+        ));
+    }
+
+    #[test]
+    fn test_any_origin_is_not_trusted() {
+        let allowed = ["*".to_owned(), "https://app.example.com".to_owned()];
+        assert!(is_trusted_origin(&allowed, "https://app.example.com"));
+        assert!(!is_trusted_origin(&allowed, "https://anything.example"));
+    }
+}
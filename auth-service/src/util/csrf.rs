@@ -0,0 +1,389 @@
+use std::sync::Arc;
+
+use axum::extract::{Request, State};
+use axum::http::{header, HeaderName, HeaderValue, Method, Uri};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum_extra::extract::cookie::{Cookie, SameSite};
+use axum_extra::extract::CookieJar;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use secrecy::ExposeSecret;
+use sha2::Sha256;
+
+use super::auth::{bearer_token, constant_time_eq};
+use super::cors::is_trusted_origin;
+use crate::app_state::AppState;
+use crate::domain::error::AuthAPIError;
+use crate::settings::Settings;
+
+pub const CSRF_HEADER: HeaderName = HeaderName::from_static("x-csrf-token");
+
+const NONCE_LENGTH: usize = 32;
+
+// The token a request's response hands out, for `GET /csrf-token`.
+#[derive(Debug, Clone)]
+pub struct CsrfToken(pub String);
+
+// Cross-site request forgery protection with a signed double-submit cookie.
+//
+// Every response to a request without a valid token sets one in the `csrf.cookie_name` cookie,
+// which pages can read (or get from `GET /csrf-token`). A POST that carries the auth cookie has
+// to send the same token in the `X-CSRF-Token` header, which pages on other sites can't. The
+// token is signed with `jwt.secret` together with the auth cookie it was issued for, so it is
+// only valid along with that cookie: one planted by a sibling subdomain, which can only have
+// been issued to some other session, is refused. A response that sets or removes the auth
+// cookie, i.e. a login or logout, comes with a new token.
+//
+// Independently, POSTs from browsers have to come from the service's own pages or one of
+// `cors.allowed_origins` other than `*`, going by `Origin`, or `Referer` where that is missing.
+// Requests with `Authorization: Bearer` don't come from a browser's ambient credentials and are
+// left alone.
+pub async fn protect_from_csrf(
+    State(state): State<Arc<AppState>>,
+    jar: CookieJar,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let settings = &state.settings;
+    let secret = settings.jwt.secret.expose_secret().as_bytes();
+    let auth_cookie_name = settings.auth_cookie.full_name();
+    let session = jar
+        .get(&auth_cookie_name)
+        .map_or("", |cookie| cookie.value());
+    let cookie_token = jar
+        .get(&settings.csrf.full_cookie_name(&settings.auth_cookie))
+        .map(|cookie| cookie.value().to_owned())
+        .filter(|token| is_valid_token(token, secret, session));
+    if let Err(e) = check_request(&request, &jar, cookie_token.as_deref(), settings) {
+        return e.into_response();
+    }
+
+    let token = cookie_token
+        .clone()
+        .unwrap_or_else(|| new_token(secret, session));
+    request.extensions_mut().insert(CsrfToken(token.clone()));
+    let mut response = next.run(request).await;
+    let token = match new_session(&response, &auth_cookie_name) {
+        Some(session) => Some(new_token(secret, &session)),
+        None => cookie_token.is_none().then_some(token),
+    };
+    if let Some(token) = token {
+        let cookie = csrf_cookie(token, settings).to_string();
+        let cookie = HeaderValue::from_str(&cookie).expect("CSRF cookies are valid headers");
+        response.headers_mut().append(header::SET_COOKIE, cookie);
+    }
+    response
+}
+
+// The auth cookie the response sets, empty if it removes it.
+fn new_session(response: &Response, auth_cookie_name: &str) -> Option<String> {
+    response
+        .headers()
+        .get_all(header::SET_COOKIE)
+        .iter()
+        .filter_map(|value| Cookie::parse(value.to_str().ok()?).ok())
+        .filter(|cookie| cookie.name() == auth_cookie_name)
+        .map(|cookie| match cookie.max_age() {
+            Some(max_age) if max_age.is_zero() => String::new(),
+            _ => cookie.value().to_owned(),
+        })
+        .next_back()
+}
+
+fn check_request(
+    request: &Request,
+    jar: &CookieJar,
+    cookie_token: Option<&str>,
+    settings: &Settings,
+) -> Result<(), AuthAPIError> {
+    let safe = matches!(
+        *request.method(),
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE
+    );
+    if safe || bearer_token(request.headers()).is_some() {
+        return Ok(());
+    }
+    if !is_allowed_source(request, settings) {
+        return Err(AuthAPIError::CrossSiteRequest);
+    }
+    if jar.get(&settings.auth_cookie.full_name()).is_none() {
+        return Ok(());
+    }
+    let header_token = request
+        .headers()
+        .get(&CSRF_HEADER)
+        .and_then(|value| value.to_str().ok());
+    match (cookie_token, header_token) {
+        (Some(cookie_token), Some(header_token))
+            if constant_time_eq(cookie_token.as_bytes(), header_token.as_bytes()) =>
+        {
+            Ok(())
+        }
+        _ => Err(AuthAPIError::InvalidCsrfToken),
+    }
+}
+
+// Browsers send `Origin` with every cross-origin POST, old ones at least `Referer`; a request
+// with neither doesn't come from a page. The service's own pages are recognised by the host,
+// and by the scheme if it is known: with a Secure auth cookie, they are served over HTTPS, even
+// where a proxy terminates TLS in front of the service.
+fn is_allowed_source(request: &Request, settings: &Settings) -> bool {
+    let headers = request.headers();
+    let source = match (headers.get(header::ORIGIN), headers.get(header::REFERER)) {
+        (Some(origin), _) => origin.to_str().ok().and_then(|origin| origin.parse().ok()),
+        (None, Some(referer)) => referer
+            .to_str()
+            .ok()
+            .and_then(|referer| referer.parse().ok()),
+        (None, None) => return true,
+    };
+    let Some(source) = source.as_ref().and_then(origin_of) else {
+        return false;
+    };
+    if is_trusted_origin(&settings.cors.allowed_origins, &source.0) {
+        return true;
+    }
+    if settings.auth_cookie.is_secure(&settings.tls) && !source.0.starts_with("https://") {
+        return false;
+    }
+    let host = headers
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok())
+        .or(request
+            .uri()
+            .authority()
+            .map(|authority| authority.as_str()));
+    host.is_some_and(|host| host.eq_ignore_ascii_case(&source.1))
+}
+
+// `scheme://authority`, and the authority on its own
+fn origin_of(uri: &Uri) -> Option<(String, String)> {
+    let authority = uri.authority()?.as_str();
+    Some((
+        format!("{}://{}", uri.scheme_str()?, authority),
+        authority.to_owned(),
+    ))
+}
+
+// A random nonce and its signature for `session`, the value of the auth cookie or empty without
+// one, both hex encoded.
+fn new_token(secret: &[u8], session: &str) -> String {
+    let mut nonce = [0u8; NONCE_LENGTH];
+    rand::thread_rng().fill_bytes(&mut nonce);
+    let nonce = hex::encode(nonce);
+    let signature = hex::encode(sign(secret, &nonce, session).finalize().into_bytes());
+    format!("{}.{}", nonce, signature)
+}
+
+fn is_valid_token(token: &str, secret: &[u8], session: &str) -> bool {
+    let Some((nonce, signature)) = token.split_once('.') else {
+        return false;
+    };
+    hex::decode(signature).is_ok_and(|signature| {
+        sign(secret, nonce, session)
+            .verify_slice(&signature)
+            .is_ok()
+    })
+}
+
+fn sign(secret: &[u8], nonce: &str, session: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC takes keys of any length");
+    // keeps the signatures apart from anything else signed with the same secret
+    mac.update(b"csrf:");
+    // hex, so it can't run into the session
+    mac.update(nonce.as_bytes());
+    mac.update(b":");
+    mac.update(session.as_bytes());
+    mac
+}
+
+// Readable by the service's pages, and only sent along with requests from the same site.
+fn csrf_cookie(token: String, settings: &Settings) -> Cookie<'static> {
+    let auth_cookie = &settings.auth_cookie;
+    let mut cookie = Cookie::build((settings.csrf.full_cookie_name(auth_cookie), token))
+        .path("/")
+        .same_site(SameSite::Strict)
+        .secure(auth_cookie.is_secure(&settings.tls))
+        .build();
+    if let Some(domain) = &auth_cookie.domain {
+        cookie.set_domain(domain.clone());
+    }
+    cookie
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+
+    const SECRET: &[u8] = b"test-secret";
+
+    fn post(headers: &[(HeaderName, &str)]) -> Request {
+        let mut request = Request::builder()
+            .method(Method::POST)
+            .uri("/logout")
+            .header(header::HOST, "auth.example.com");
+        for (name, value) in headers {
+            request = request.header(name, *value);
+        }
+        request.body(Body::empty()).unwrap()
+    }
+
+    fn settings() -> Settings {
+        let mut settings = Settings::default();
+        settings.cors.allowed_origins = vec!["https://*.example.com".to_owned()];
+        settings
+    }
+
+    #[test]
+    fn test_tokens_are_signed() {
+        let token = new_token(SECRET, "");
+        assert!(is_valid_token(&token, SECRET, ""));
+        assert!(!is_valid_token(&token, b"other-secret", ""));
+        assert_ne!(token, new_token(SECRET, ""));
+
+        let (nonce, signature) = token.split_once('.').unwrap();
+        let forged = format!("{}.{}", "0".repeat(nonce.len()), signature);
+        assert!(!is_valid_token(&forged, SECRET, ""));
+        assert!(!is_valid_token(nonce, SECRET, ""));
+        assert!(!is_valid_token("", SECRET, ""));
+    }
+
+    #[test]
+    fn test_tokens_are_bound_to_the_session() {
+        let token = new_token(SECRET, "jwt-of-herbert");
+        assert!(is_valid_token(&token, SECRET, "jwt-of-herbert"));
+        assert!(!is_valid_token(&token, SECRET, "jwt-of-mallory"));
+        assert!(!is_valid_token(&token, SECRET, ""));
+        assert!(!is_valid_token(
+            &new_token(SECRET, ""),
+            SECRET,
+            "jwt-of-herbert"
+        ));
+    }
+
+    #[test]
+    fn test_logins_and_logouts_change_the_session() {
+        let response = |cookies: &[&str]| {
+            let mut response = Response::new(Body::empty());
+            for cookie in cookies {
+                response
+                    .headers_mut()
+                    .append(header::SET_COOKIE, HeaderValue::from_str(cookie).unwrap());
+            }
+            response
+        };
+        assert_eq!(new_session(&response(&[]), "jwt"), None);
+        assert_eq!(
+            new_session(
+                &response(&["other=1", "jwt=token; HttpOnly; Path=/"]),
+                "jwt"
+            ),
+            Some("token".to_owned())
+        );
+        assert_eq!(
+            new_session(&response(&["jwt=; Max-Age=0; Path=/"]), "jwt"),
+            Some(String::new())
+        );
+    }
+
+    #[test]
+    fn test_only_own_and_allowed_origins_may_post() {
+        let settings = &settings();
+        for origin in [
+            "https://auth.example.com",
+            "http://auth.example.com",
+            "https://app.example.com",
+        ] {
+            let request = post(&[(header::ORIGIN, origin)]);
+            assert!(is_allowed_source(&request, settings), "{}", origin);
+        }
+        for origin in [
+            "https://evil.example",
+            "https://auth.example.com.evil.example",
+            "http://app.example.com",
+            "null",
+        ] {
+            let request = post(&[(header::ORIGIN, origin)]);
+            assert!(!is_allowed_source(&request, settings), "{}", origin);
+        }
+
+        let request = post(&[(header::REFERER, "https://auth.example.com/account?tab=1")]);
+        assert!(is_allowed_source(&request, settings));
+        let request = post(&[(header::REFERER, "https://evil.example/page")]);
+        assert!(!is_allowed_source(&request, settings));
+        // not from a browser
+        assert!(is_allowed_source(&post(&[]), settings));
+    }
+
+    #[test]
+    fn test_any_origin_may_not_post() {
+        let mut settings = settings();
+        settings.cors.allowed_origins = vec!["*".to_owned()];
+        let request = post(&[(header::ORIGIN, "https://evil.example")]);
+        assert!(!is_allowed_source(&request, &settings));
+        let request = post(&[(header::ORIGIN, "https://auth.example.com")]);
+        assert!(is_allowed_source(&request, &settings));
+    }
+
+    #[test]
+    fn test_own_pages_are_https_with_secure_cookies() {
+        let mut settings = settings();
+        settings.auth_cookie.secure = Some(true);
+        let request = post(&[(header::ORIGIN, "https://auth.example.com")]);
+        assert!(is_allowed_source(&request, &settings));
+        let request = post(&[(header::ORIGIN, "http://auth.example.com")]);
+        assert!(!is_allowed_source(&request, &settings));
+        let request = post(&[(header::REFERER, "http://auth.example.com/account")]);
+        assert!(!is_allowed_source(&request, &settings));
+    }
+
+    #[test]
+    fn test_cookie_authenticated_posts_need_the_token() {
+        let settings = settings();
+        let token = new_token(SECRET, "token");
+        let jar = |cookies: &[(&str, &str)]| {
+            cookies.iter().fold(CookieJar::new(), |jar, (name, value)| {
+                jar.add(Cookie::new(name.to_string(), value.to_string()))
+            })
+        };
+        let authenticated = jar(&[("jwt", "token"), ("csrf_token", &token)]);
+
+        let request = post(&[(CSRF_HEADER, &token)]);
+        assert!(check_request(&request, &authenticated, Some(&token), &settings).is_ok());
+
+        let request = post(&[]);
+        let result = check_request(&request, &authenticated, Some(&token), &settings);
+        assert!(matches!(result, Err(AuthAPIError::InvalidCsrfToken)));
+        let request = post(&[(CSRF_HEADER, &new_token(SECRET, "token"))]);
+        let result = check_request(&request, &authenticated, Some(&token), &settings);
+        assert!(matches!(result, Err(AuthAPIError::InvalidCsrfToken)));
+        let request = post(&[(CSRF_HEADER, &token)]);
+        let result = check_request(&request, &authenticated, None, &settings);
+        assert!(matches!(result, Err(AuthAPIError::InvalidCsrfToken)));
+
+        // nothing to forge without the auth cookie, or with a bearer token instead
+        let request = post(&[]);
+        assert!(check_request(&request, &jar(&[]), None, &settings).is_ok());
+        let request = post(&[(header::AUTHORIZATION, "Bearer token")]);
+        assert!(check_request(&request, &authenticated, None, &settings).is_ok());
+    }
+
+    #[test]
+    fn test_csrf_cookie_follows_the_auth_cookie() {
+        let mut settings = settings();
+        let cookie = csrf_cookie("token".to_owned(), &settings);
+        assert_eq!(
+            cookie.to_string(),
+            "csrf_token=token; SameSite=Strict; Path=/"
+        );
+
+        settings.auth_cookie.host_prefix = true;
+        settings.auth_cookie.secure = Some(true);
+        let cookie = csrf_cookie("token".to_owned(), &settings);
+        assert_eq!(cookie.name(), "__Host-csrf_token");
+        assert_eq!(cookie.secure(), Some(true));
+        assert_eq!(cookie.http_only(), None);
+    }
+}
@@ -0,0 +1,131 @@
+use std::fmt;
+
+use chrono::{SecondsFormat, Utc};
+use serde_json::{Map, Value};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::field::RecordFields;
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields, FormattedFields};
+use tracing_subscriber::registry::LookupSpan;
+
+use super::redaction::{is_sensitive_field, REDACTED};
+
+// One JSON object per line: timestamp, level, target and message, then the fields of every span
+// the event is in, outermost first, then the event's own fields. Inner fields win, so a request's
+// `request_id`, `method` and `uri` sit next to the `status` and `latency` of its last event.
+pub struct FlatJson;
+
+impl<S> FormatEvent<S, JsonFields> for FlatJson
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, JsonFields>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let metadata = event.metadata();
+        let mut object = Map::new();
+        object.insert(
+            "timestamp".to_owned(),
+            Utc::now()
+                .to_rfc3339_opts(SecondsFormat::Micros, true)
+                .into(),
+        );
+        object.insert("level".to_owned(), metadata.level().as_str().into());
+        object.insert("target".to_owned(), metadata.target().into());
+        if let Some(scope) = ctx.event_scope() {
+            for span in scope.from_root() {
+                let extensions = span.extensions();
+                let Some(fields) = extensions.get::<FormattedFields<JsonFields>>() else {
+                    continue;
+                };
+                if let Ok(Value::Object(fields)) = serde_json::from_str(&fields.fields) {
+                    object.extend(fields);
+                }
+            }
+        }
+        let mut visitor = JsonVisitor(object);
+        event.record(&mut visitor);
+        writeln!(writer, "{}", Value::Object(visitor.0))
+    }
+}
+
+// Keeps span fields as a JSON object for `FlatJson` to merge into each event.
+pub struct JsonFields;
+
+impl<'writer> FormatFields<'writer> for JsonFields {
+    fn format_fields<R: RecordFields>(
+        &self,
+        mut writer: Writer<'writer>,
+        fields: R,
+    ) -> fmt::Result {
+        let mut visitor = JsonVisitor(Map::new());
+        fields.record(&mut visitor);
+        write!(writer, "{}", Value::Object(visitor.0))
+    }
+
+    // Fields recorded later, e.g. with `Span::record`, are added to the object.
+    fn add_fields(
+        &self,
+        current: &'writer mut FormattedFields<Self>,
+        fields: &tracing::span::Record<'_>,
+    ) -> fmt::Result {
+        let object = match serde_json::from_str(&current.fields) {
+            Ok(Value::Object(object)) => object,
+            _ => Map::new(),
+        };
+        let mut visitor = JsonVisitor(object);
+        fields.record(&mut visitor);
+        current.fields = Value::Object(visitor.0).to_string();
+        Ok(())
+    }
+}
+
+struct JsonVisitor(Map<String, Value>);
+
+impl JsonVisitor {
+    fn insert(&mut self, field: &Field, value: Value) {
+        // `otel.*` fields configure the exported span, they aren't part of the log
+        if field.name().starts_with("otel.") {
+            return;
+        }
+        let value = match is_sensitive_field(field.name()) {
+            true => REDACTED.into(),
+            false => value,
+        };
+        self.0.insert(field.name().to_owned(), value);
+    }
+}
+
+impl Visit for JsonVisitor {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.insert(field, value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.insert(field, value.into());
+    }
+
+    fn record_error(&mut self, field: &Field, value: &(dyn std::error::Error + 'static)) {
+        self.insert(field, value.to_string().into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.insert(field, format!("{:?}", value).into());
+    }
+}
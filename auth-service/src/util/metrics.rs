@@ -0,0 +1,370 @@
+use std::sync::LazyLock;
+use std::time::{Duration, Instant};
+
+use axum::extract::{MatchedPath, Request};
+use axum::http::Method;
+use axum::middleware::Next;
+use axum::response::Response;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramTimer, HistogramVec, IntCounter, IntCounterVec, IntGauge,
+    Opts, Registry, TextEncoder,
+};
+use tokio::sync::watch;
+use tokio::time::MissedTickBehavior;
+
+use crate::app_state::UserStoreType;
+use crate::domain::data_stores::{LegacyPasswordHashes, UserStoreError};
+
+// Every series the service exports. They are recorded from wherever the event happens, down to
+// the password hasher and the stores, so the collection is global like the Prometheus default
+// registry; it only gets its own registry to keep the output free of anything else.
+pub struct Metrics {
+    registry: Registry,
+    pub http_requests: IntCounterVec,
+    pub http_request_duration: HistogramVec,
+    pub logins: IntCounterVec,
+    pub two_fa_codes_issued: IntCounter,
+    pub two_fa_codes_verified: IntCounter,
+    pub tokens_banned: IntCounter,
+    pub password_hash_duration: HistogramVec,
+    pub db_query_duration: HistogramVec,
+    pub redis_command_duration: HistogramVec,
+    pub legacy_password_hashes: IntGauge,
+    pub unreadable_password_hashes: IntGauge,
+}
+
+// Latencies of calls to Postgres and Redis, from a fraction of a millisecond up.
+const STORE_BUCKETS: &[f64] = &[
+    0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0,
+];
+// Argon2 is made to be slow; the interesting range is tens to hundreds of milliseconds.
+// Imported legacy hashes are timed as well, since they are verified until the next login.
+const PASSWORD_HASH_BUCKETS: &[f64] = &[0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0];
+
+static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+pub fn metrics() -> &'static Metrics {
+    &METRICS
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry =
+            Registry::new_custom(Some("auth".to_owned()), None).expect("metric namespace is valid");
+        let metrics = Metrics {
+            http_requests: IntCounterVec::new(
+                Opts::new("http_requests_total", "HTTP requests by route and status"),
+                &["method", "route", "status"],
+            )
+            .unwrap(),
+            http_request_duration: HistogramVec::new(
+                HistogramOpts::new(
+                    "http_request_duration_seconds",
+                    "HTTP request latency by route and status",
+                ),
+                &["method", "route", "status"],
+            )
+            .unwrap(),
+            logins: IntCounterVec::new(
+                Opts::new(
+                    "logins_total",
+                    "Login attempts by outcome, and by reason for failures",
+                ),
+                &["outcome", "reason"],
+            )
+            .unwrap(),
+            two_fa_codes_issued: IntCounter::new(
+                "two_fa_codes_issued_total",
+                "2FA codes sent to users logging in",
+            )
+            .unwrap(),
+            two_fa_codes_verified: IntCounter::new(
+                "two_fa_codes_verified_total",
+                "2FA codes entered correctly",
+            )
+            .unwrap(),
+            tokens_banned: IntCounter::new("tokens_banned_total", "Auth tokens banned on logout")
+                .unwrap(),
+            password_hash_duration: HistogramVec::new(
+                HistogramOpts::new(
+                    "password_hash_duration_seconds",
+                    "Time spent hashing and verifying passwords, by algorithm",
+                )
+                .buckets(PASSWORD_HASH_BUCKETS.to_vec()),
+                &["operation", "algorithm"],
+            )
+            .unwrap(),
+            db_query_duration: HistogramVec::new(
+                HistogramOpts::new(
+                    "db_query_duration_seconds",
+                    "Postgres calls by store operation, including waiting for a pooled connection",
+                )
+                .buckets(STORE_BUCKETS.to_vec()),
+                &["operation"],
+            )
+            .unwrap(),
+            redis_command_duration: HistogramVec::new(
+                HistogramOpts::new(
+                    "redis_command_duration_seconds",
+                    "Redis calls by store operation",
+                )
+                .buckets(STORE_BUCKETS.to_vec()),
+                &["operation"],
+            )
+            .unwrap(),
+            legacy_password_hashes: IntGauge::new(
+                "legacy_password_hashes",
+                "Users whose password hash has outdated parameters, upgraded on their next login",
+            )
+            .unwrap(),
+            unreadable_password_hashes: IntGauge::new(
+                "unreadable_password_hashes",
+                "Users whose password hash can't be parsed, so they can't log in",
+            )
+            .unwrap(),
+            registry,
+        };
+        metrics.register();
+        metrics
+    }
+
+    fn register(&self) {
+        let collectors: [Box<dyn prometheus::core::Collector>; 11] = [
+            Box::new(self.http_requests.clone()),
+            Box::new(self.http_request_duration.clone()),
+            Box::new(self.logins.clone()),
+            Box::new(self.two_fa_codes_issued.clone()),
+            Box::new(self.two_fa_codes_verified.clone()),
+            Box::new(self.tokens_banned.clone()),
+            Box::new(self.password_hash_duration.clone()),
+            Box::new(self.db_query_duration.clone()),
+            Box::new(self.redis_command_duration.clone()),
+            Box::new(self.legacy_password_hashes.clone()),
+            Box::new(self.unreadable_password_hashes.clone()),
+        ];
+        for collector in collectors {
+            self.registry
+                .register(collector)
+                .expect("metric names are unique");
+        }
+    }
+
+    // Everything in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .expect("metrics encode as text");
+        String::from_utf8(buffer).expect("metrics text is UTF-8")
+    }
+
+    pub fn login_succeeded(&self) {
+        self.logins.with_label_values(&["success", ""]).inc();
+    }
+
+    pub fn login_failed(&self, reason: &str) {
+        self.logins.with_label_values(&["failure", reason]).inc();
+    }
+
+    // Observes the time until the returned timer is dropped.
+    pub fn time_password_hash(&self, operation: &str, algorithm: &str) -> HistogramTimer {
+        self.password_hash_duration
+            .with_label_values(&[operation, algorithm])
+            .start_timer()
+    }
+
+    pub fn time_db_query(&self, operation: &str) -> HistogramTimer {
+        self.db_query_duration
+            .with_label_values(&[operation])
+            .start_timer()
+    }
+
+    pub fn time_redis_command(&self, operation: &str) -> HistogramTimer {
+        self.redis_command_duration
+            .with_label_values(&[operation])
+            .start_timer()
+    }
+}
+
+// Counts and times every request under its route pattern, e.g. `/admin/email-outbox/dead/:id/retry`,
+// so that path parameters don't turn into series of their own. Everything served by the UI
+// fallback shares the route `static`.
+pub async fn track_http_metrics(request: Request, next: Next) -> Response {
+    let started = Instant::now();
+    let method = method_label(request.method());
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_owned())
+        .unwrap_or_else(|| "static".to_owned());
+    let response = next.run(request).await;
+    let status = response.status().as_u16().to_string();
+    let labels = [method, route.as_str(), status.as_str()];
+    let metrics = metrics();
+    metrics.http_requests.with_label_values(&labels).inc();
+    metrics
+        .http_request_duration
+        .with_label_values(&labels)
+        .observe(started.elapsed().as_secs_f64());
+    response
+}
+
+// Clients can send any method name, so only the standard ones get series of their own.
+fn method_label(method: &Method) -> &'static str {
+    match *method {
+        Method::GET => "GET",
+        Method::HEAD => "HEAD",
+        Method::POST => "POST",
+        Method::PUT => "PUT",
+        Method::PATCH => "PATCH",
+        Method::DELETE => "DELETE",
+        Method::OPTIONS => "OPTIONS",
+        Method::CONNECT => "CONNECT",
+        Method::TRACE => "TRACE",
+        _ => "other",
+    }
+}
+
+// Keeps the password hash gauges current, so changing the Argon2 settings can be followed by
+// how many users are left on the old ones. Counting reads every user's hash, so it runs every
+// `interval` rather than on each scrape. Changes are logged as well. Returns once `stopped` is
+// set, dropping a count that is still running.
+pub async fn audit_password_hashes(
+    user_store: UserStoreType,
+    interval: Duration,
+    mut stopped: watch::Receiver<bool>,
+) {
+    let mut ticks = tokio::time::interval(interval);
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut last = None;
+    loop {
+        let tick = async {
+            ticks.tick().await;
+            update_password_hash_gauges(&user_store).await
+        };
+        let counts = tokio::select! {
+            counts = tick => counts,
+            _ = stopped.wait_for(|stopped| *stopped) => break,
+        };
+        match counts {
+            Ok(counts) if last != Some(counts) => {
+                tracing::info!(
+                    legacy_password_hashes = counts.legacy,
+                    unreadable_password_hashes = counts.unreadable,
+                    "password hash parameter audit"
+                );
+                last = Some(counts);
+            }
+            Ok(_) => {}
+            Err(e) => tracing::warn!("could not count legacy password hashes: {:?}", e),
+        }
+    }
+}
+
+async fn update_password_hash_gauges(
+    user_store: &UserStoreType,
+) -> Result<LegacyPasswordHashes, UserStoreError> {
+    // the lock is only held to start the count
+    let count = user_store.read().await.count_legacy_password_hashes();
+    let counts = count.await?;
+    let metrics = metrics();
+    metrics
+        .legacy_password_hashes
+        .set(counts.legacy.try_into().unwrap_or(i64::MAX));
+    metrics
+        .unreadable_password_hashes
+        .set(counts.unreadable.try_into().unwrap_or(i64::MAX));
+    Ok(counts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::data_stores::UserStore;
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+
+    #[test]
+    fn test_render_includes_every_series() {
+        let metrics = metrics();
+        metrics.login_failed("incorrect_credentials");
+        metrics.tokens_banned.inc();
+        drop(metrics.time_db_query("get_user"));
+
+        let text = metrics.render();
+        assert!(
+            text.contains(r#"auth_logins_total{outcome="failure",reason="incorrect_credentials"}"#)
+        );
+        assert!(text.contains("auth_tokens_banned_total"));
+        assert!(text.contains(r#"auth_db_query_duration_seconds_count{operation="get_user"}"#));
+        // unlabelled series show up before anything is recorded
+        assert!(text.contains("# TYPE auth_two_fa_codes_issued_total counter"));
+    }
+
+    #[test]
+    fn test_unknown_methods_share_a_label() {
+        assert_eq!(method_label(&Method::POST), "POST");
+        assert_eq!(
+            method_label(&Method::from_bytes(b"PROPFIND").unwrap()),
+            "other"
+        );
+        assert_eq!(
+            method_label(&Method::from_bytes(b"X-12345").unwrap()),
+            "other"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_password_hash_gauges_follow_the_store() {
+        use crate::domain::password_hash::PasswordHash;
+        use crate::domain::user::User;
+        use crate::services::hashmap_user_store::HashmapUserStore;
+
+        let mut store = HashmapUserStore::default();
+        let mut user = User::new("herbert@email.com", "password0!", false)
+            .await
+            .unwrap();
+        user.password_hash = PasswordHash::parse(
+            "$argon2i$v=19$m=15000,t=2,p=1$c29tZXNhbHQ$wPB3NvGcZHhkoUdcKVDSYW1tiabnqsrIaeHx4Ctw0rQ"
+                .to_string(),
+        )
+        .unwrap();
+        store.add_user(user).await.unwrap();
+        let store: UserStoreType = Arc::new(RwLock::new(Box::new(store)));
+
+        let counts = update_password_hash_gauges(&store).await.unwrap();
+        assert_eq!(counts.legacy, 1);
+        assert!(metrics()
+            .render()
+            .contains("auth_legacy_password_hashes 1\n"));
+    }
+
+    #[tokio::test]
+    async fn test_counting_password_hashes_does_not_hold_the_store() {
+        use crate::services::hashmap_user_store::HashmapUserStore;
+
+        let store: UserStoreType = Arc::new(RwLock::new(Box::new(HashmapUserStore::default())));
+        let count = store.read().await.count_legacy_password_hashes();
+        // a writer gets in while the count is still pending
+        let _writer = store.try_write().expect("the count holds the store");
+        assert_eq!(count.await.unwrap(), LegacyPasswordHashes::default());
+    }
+
+    #[tokio::test]
+    async fn test_password_hash_audit_stops_when_told() {
+        use crate::services::hashmap_user_store::HashmapUserStore;
+
+        let store: UserStoreType = Arc::new(RwLock::new(Box::new(HashmapUserStore::default())));
+        let (stop, stopped) = watch::channel(false);
+        let audit = tokio::spawn(audit_password_hashes(
+            store,
+            Duration::from_secs(3600),
+            stopped,
+        ));
+        stop.send(true).unwrap();
+        tokio::time::timeout(Duration::from_secs(5), audit)
+            .await
+            .expect("audit still running")
+            .unwrap();
+    }
+}
@@ -1,3 +1,11 @@
 pub(crate) mod auth;
 pub mod constants;
+pub mod cors;
+pub mod csrf;
+pub mod json_log;
+pub mod metrics;
+pub mod redaction;
+pub mod request_id;
+pub mod security_headers;
+pub mod tls;
 pub mod tracing;
@@ -0,0 +1,76 @@
+use axum::extract::Request;
+use axum::http::{HeaderName, HeaderValue};
+use axum::middleware::Next;
+use axum::response::Response;
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+// Longer ids, or ones with other characters, are replaced rather than logged and echoed.
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+// Gives every request an id: the caller's `X-Request-Id` if it is well-formed, e.g. one set by
+// a proxy or by app-service, otherwise a new UUID. The id is written back into the request
+// headers so the request span logs it, and returned in the `X-Request-Id` response header.
+pub async fn propagate_request_id(mut request: Request, next: Next) -> Response {
+    let request_id = request
+        .headers()
+        .get(&REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| is_valid_request_id(value))
+        .map(str::to_owned)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let header_value = HeaderValue::from_str(&request_id).expect("request ids are valid headers");
+    request
+        .headers_mut()
+        .insert(REQUEST_ID_HEADER, header_value.clone());
+
+    let mut response = REQUEST_ID.scope(request_id, next.run(request)).await;
+    response
+        .headers_mut()
+        .insert(REQUEST_ID_HEADER, header_value);
+    response
+}
+
+// The id of the request being handled, for error responses. `None` outside of a request.
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(|request_id| request_id.clone()).ok()
+}
+
+// Only ids that are safe to put into logs and headers as they are.
+fn is_valid_request_id(value: &str) -> bool {
+    (1..=MAX_REQUEST_ID_LENGTH).contains(&value.len())
+        && value
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_' | b'.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_valid_request_id() {
+        assert!(is_valid_request_id("6f1c2a1e-0b7e-4c1b-9f1e-2f7d0c3b5a11"));
+        assert!(is_valid_request_id("app-service_42.1"));
+        assert!(is_valid_request_id(&"a".repeat(MAX_REQUEST_ID_LENGTH)));
+
+        assert!(!is_valid_request_id(""));
+        assert!(!is_valid_request_id(&"a".repeat(MAX_REQUEST_ID_LENGTH + 1)));
+        assert!(!is_valid_request_id("id with spaces"));
+        assert!(!is_valid_request_id("id\nforged log line"));
+        assert!(!is_valid_request_id("ünïcode"));
+    }
+
+    #[tokio::test]
+    async fn test_current_request_id_is_scoped_to_the_request() {
+        assert_eq!(current_request_id(), None);
+        let inside = REQUEST_ID
+            .scope("abc".to_owned(), async { current_request_id() })
+            .await;
+        assert_eq!(inside, Some("abc".to_owned()));
+    }
+}
@@ -0,0 +1,207 @@
+use std::sync::Arc;
+
+use axum::body::Body;
+use axum::extract::{Request, State};
+use axum::http::{header, HeaderMap, HeaderName, HeaderValue, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use rand::RngCore;
+
+use crate::settings::SecurityHeadersSettings;
+
+// In `security_headers.*.content_security_policy`.
+const POLICY_NONCE: &str = "{nonce}";
+// In HTML pages, e.g. `<script nonce="{{csp_nonce}}" src="app.js">`.
+const PAGE_NONCE: &str = "{{csp_nonce}}";
+// The pages in `assets/` are a few kilobytes.
+const MAX_PAGE_SIZE: usize = 1024 * 1024;
+
+// The headers for one group of routes. Values have been validated with the settings.
+#[derive(Debug)]
+pub struct SecurityHeaders {
+    content_security_policy: String,
+    referrer_policy: String,
+    permissions_policy: String,
+    strict_transport_security: String,
+}
+
+impl SecurityHeaders {
+    // For the JSON routes.
+    pub fn api(settings: &SecurityHeadersSettings) -> Arc<Self> {
+        Arc::new(SecurityHeaders {
+            content_security_policy: settings.api.content_security_policy.clone(),
+            referrer_policy: settings.api.referrer_policy.clone(),
+            permissions_policy: settings.permissions_policy.clone(),
+            strict_transport_security: settings.strict_transport_security.clone(),
+        })
+    }
+
+    // For the login UI.
+    pub fn ui(settings: &SecurityHeadersSettings) -> Arc<Self> {
+        Arc::new(SecurityHeaders {
+            content_security_policy: settings.ui.content_security_policy.clone(),
+            referrer_policy: settings.ui.referrer_policy.clone(),
+            permissions_policy: settings.permissions_policy.clone(),
+            strict_transport_security: settings.strict_transport_security.clone(),
+        })
+    }
+}
+
+// Adds the headers to responses that don't set them themselves, and `X-Content-Type-Options:
+// nosniff` to all. If the policy uses a nonce, every response gets a new one, and HTML pages get
+// it in place of `{{csp_nonce}}`. Such pages differ on every request, so they mustn't be cached.
+pub async fn set_security_headers(
+    State(headers): State<Arc<SecurityHeaders>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let nonce = headers
+        .content_security_policy
+        .contains(POLICY_NONCE)
+        .then(new_nonce);
+    let mut response = next.run(request).await;
+    if let Some(nonce) = &nonce {
+        if is_html(response.headers()) {
+            response = insert_nonce(response, nonce).await;
+        }
+    }
+
+    set_headers(&headers, nonce.as_deref(), response.headers_mut());
+    response
+}
+
+fn set_headers(headers: &SecurityHeaders, nonce: Option<&str>, response_headers: &mut HeaderMap) {
+    let policy = match nonce {
+        Some(nonce) => headers.content_security_policy.replace(POLICY_NONCE, nonce),
+        None => headers.content_security_policy.clone(),
+    };
+    for (name, value) in [
+        (header::CONTENT_SECURITY_POLICY, policy.as_str()),
+        (header::REFERRER_POLICY, &headers.referrer_policy),
+        (
+            HeaderName::from_static("permissions-policy"),
+            &headers.permissions_policy,
+        ),
+        (
+            header::STRICT_TRANSPORT_SECURITY,
+            &headers.strict_transport_security,
+        ),
+        (header::X_CONTENT_TYPE_OPTIONS, "nosniff"),
+    ] {
+        if value.is_empty() {
+            continue;
+        }
+        let value = HeaderValue::from_str(value).expect("security headers are validated");
+        response_headers.entry(name).or_insert(value);
+    }
+}
+
+fn new_nonce() -> String {
+    let mut nonce = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut nonce);
+    hex::encode(nonce)
+}
+
+fn is_html(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/html"))
+}
+
+async fn insert_nonce(response: Response, nonce: &str) -> Response {
+    let (mut parts, body) = response.into_parts();
+    let page = match axum::body::to_bytes(body, MAX_PAGE_SIZE).await {
+        Ok(page) => page,
+        Err(e) => {
+            tracing::error!("failed to read page: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    let page = String::from_utf8_lossy(&page).replace(PAGE_NONCE, nonce);
+    for name in [header::CONTENT_LENGTH, header::ETAG, header::LAST_MODIFIED] {
+        parts.headers.remove(name);
+    }
+    parts
+        .headers
+        .insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    Response::from_parts(parts, Body::from(page))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+        headers.get(name).map(|value| value.to_str().unwrap())
+    }
+
+    #[test]
+    fn test_sets_the_headers_of_the_group() {
+        let settings = SecurityHeadersSettings::default();
+        let mut headers = HeaderMap::new();
+        set_headers(&SecurityHeaders::api(&settings), None, &mut headers);
+        assert_eq!(
+            header(&headers, "content-security-policy"),
+            Some("default-src 'none'; frame-ancestors 'none'")
+        );
+        assert_eq!(header(&headers, "referrer-policy"), Some("no-referrer"));
+        assert_eq!(header(&headers, "x-content-type-options"), Some("nosniff"));
+        assert_eq!(
+            header(&headers, "strict-transport-security"),
+            Some("max-age=31536000")
+        );
+        assert!(header(&headers, "permissions-policy").is_some());
+
+        let mut headers = HeaderMap::new();
+        set_headers(&SecurityHeaders::ui(&settings), Some("abc"), &mut headers);
+        let policy = header(&headers, "content-security-policy").unwrap();
+        assert!(policy.contains("script-src 'nonce-abc'"), "{}", policy);
+        assert!(!policy.contains(POLICY_NONCE), "{}", policy);
+        assert_eq!(header(&headers, "referrer-policy"), Some("same-origin"));
+    }
+
+    #[test]
+    fn test_skips_empty_settings_and_headers_already_set() {
+        let settings = SecurityHeadersSettings {
+            strict_transport_security: String::new(),
+            ..SecurityHeadersSettings::default()
+        };
+        let mut headers = HeaderMap::new();
+        headers.insert(header::REFERRER_POLICY, HeaderValue::from_static("origin"));
+        set_headers(&SecurityHeaders::api(&settings), None, &mut headers);
+        assert_eq!(header(&headers, "strict-transport-security"), None);
+        assert_eq!(header(&headers, "referrer-policy"), Some("origin"));
+    }
+
+    #[tokio::test]
+    async fn test_pages_get_the_nonce_and_are_not_cached() {
+        let page = Response::builder()
+            .header(header::CONTENT_TYPE, "text/html; charset=utf-8")
+            .header(header::CONTENT_LENGTH, "52")
+            .header(header::LAST_MODIFIED, "Wed, 21 Oct 2015 07:28:00 GMT")
+            .body(Body::from(
+                r#"<script nonce="{{csp_nonce}}" src="app.js"></script>"#,
+            ))
+            .unwrap();
+        assert!(is_html(page.headers()));
+        let response = insert_nonce(page, "abc").await;
+        assert_eq!(
+            header(response.headers(), "cache-control"),
+            Some("no-store")
+        );
+        assert_eq!(header(response.headers(), "content-length"), None);
+        assert_eq!(header(response.headers(), "last-modified"), None);
+        let body = axum::body::to_bytes(response.into_body(), MAX_PAGE_SIZE)
+            .await
+            .unwrap();
+        assert_eq!(body, r#"<script nonce="abc" src="app.js"></script>"#);
+    }
+
+    #[test]
+    fn test_nonces_are_random() {
+        let nonce = new_nonce();
+        assert_eq!(nonce.len(), 32);
+        assert_ne!(nonce, new_nonce());
+    }
+}
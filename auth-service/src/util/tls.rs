@@ -0,0 +1,178 @@
+use std::path::Path;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use axum::extract::Request;
+use axum::http::uri::Authority;
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Redirect, Response};
+use axum::Router;
+use axum_server::tls_rustls::RustlsConfig;
+use color_eyre::eyre::{eyre, Context, Result};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::ServerConfig;
+use tokio::time::MissedTickBehavior;
+
+use crate::settings::TlsSettings;
+
+// The certificate chain and key from `tls.cert_file` and `tls.key_file`.
+pub fn load_server_config(settings: &TlsSettings) -> Result<Arc<ServerConfig>> {
+    let (cert_file, key_file) = files(settings)?;
+    let certs = CertificateDer::pem_file_iter(cert_file)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .wrap_err_with(|| format!("failed to read certificates from {}", cert_file.display()))?;
+    if certs.is_empty() {
+        return Err(eyre!("no certificate in {}", cert_file.display()));
+    }
+    let key = PrivateKeyDer::from_pem_file(key_file)
+        .wrap_err_with(|| format!("failed to read private key from {}", key_file.display()))?;
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let mut config = ServerConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .wrap_err("failed to configure TLS")?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .wrap_err("the private key doesn't match the certificate")?;
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(Arc::new(config))
+}
+
+fn files(settings: &TlsSettings) -> Result<(&Path, &Path)> {
+    match (&settings.cert_file, &settings.key_file) {
+        (Some(cert_file), Some(key_file)) => Ok((cert_file, key_file)),
+        _ => Err(eyre!("tls.cert_file and tls.key_file must be set")),
+    }
+}
+
+// Swaps the certificate served by `config` for the one in the files whenever they change, and on
+// SIGHUP. Connections already open keep the certificate they started with; if the new files
+// can't be loaded, e.g. because only one of them has been replaced yet, the old certificate
+// stays until the next attempt.
+pub async fn reload_on_change(config: RustlsConfig, settings: TlsSettings) {
+    let mut modified = modification_times(&settings);
+    let mut interval = tokio::time::interval(settings.reload_interval);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    interval.tick().await;
+    #[cfg(unix)]
+    let mut hangups = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()).ok();
+
+    loop {
+        let hangup = async {
+            #[cfg(unix)]
+            if let Some(hangups) = hangups.as_mut() {
+                hangups.recv().await;
+                return;
+            }
+            std::future::pending::<()>().await
+        };
+        let forced = tokio::select! {
+            _ = interval.tick() => false,
+            _ = hangup => true,
+        };
+        let current = modification_times(&settings);
+        if !forced && current == modified {
+            continue;
+        }
+        match load_server_config(&settings) {
+            Ok(server_config) => {
+                config.reload_from_config(server_config);
+                modified = current;
+                tracing::info!("reloaded TLS certificate");
+            }
+            Err(e) => tracing::warn!("could not reload TLS certificate: {:?}", e),
+        }
+    }
+}
+
+fn modification_times(settings: &TlsSettings) -> Option<(SystemTime, SystemTime)> {
+    let (cert_file, key_file) = files(settings).ok()?;
+    let modified = |path: &Path| std::fs::metadata(path).and_then(|metadata| metadata.modified());
+    Some((modified(cert_file).ok()?, modified(key_file).ok()?))
+}
+
+// Answers everything with a permanent redirect to the same host and path over HTTPS.
+pub fn redirect_router(https_port: u16) -> Router {
+    Router::new()
+        .fallback(move |request: Request| async move { redirect_to_https(&request, https_port) })
+}
+
+fn redirect_to_https(request: &Request, https_port: u16) -> Response {
+    let host = request
+        .headers()
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok())
+        .and_then(|host| host.parse::<Authority>().ok());
+    let Some(host) = host else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+    let authority = match https_port {
+        443 => host.host().to_owned(),
+        port => format!("{}:{}", host.host(), port),
+    };
+    let path = request
+        .uri()
+        .path_and_query()
+        .map_or("/", |path| path.as_str());
+    Redirect::permanent(&format!("https://{}{}", authority, path)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+
+    fn redirect(host: &str, uri: &str, https_port: u16) -> Response {
+        let request = Request::builder()
+            .uri(uri)
+            .header(header::HOST, host)
+            .body(Body::empty())
+            .unwrap();
+        redirect_to_https(&request, https_port)
+    }
+
+    #[test]
+    fn test_redirects_to_the_same_host_and_path() {
+        let response = redirect("auth.example.com:80", "/login?next=%2F", 443);
+        assert_eq!(response.status(), StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(
+            response.headers()[header::LOCATION],
+            "https://auth.example.com/login?next=%2F"
+        );
+
+        let response = redirect("localhost:8080", "/", 3000);
+        assert_eq!(
+            response.headers()[header::LOCATION],
+            "https://localhost:3000/"
+        );
+    }
+
+    #[test]
+    fn test_rejects_requests_without_a_host() {
+        let response = redirect("not a host", "/", 443);
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn test_missing_or_broken_files_are_errors() {
+        let settings = TlsSettings {
+            enabled: true,
+            ..TlsSettings::default()
+        };
+        assert!(load_server_config(&settings).is_err());
+
+        let dir = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        std::fs::create_dir(&dir).unwrap();
+        let cert_file = dir.join("cert.pem");
+        std::fs::write(&cert_file, "not a certificate").unwrap();
+        let settings = TlsSettings {
+            cert_file: Some(cert_file),
+            key_file: Some(dir.join("key.pem")),
+            ..settings
+        };
+        let message = format!("{:?}", load_server_config(&settings).unwrap_err());
+        assert!(message.contains("cert.pem"), "{}", message);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,24 +1,52 @@
-use color_eyre::eyre::Result;
+use color_eyre::eyre::{Context, Result};
+use opentelemetry::propagation::TextMapPropagator;
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_http::HeaderExtractor;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{Sampler, SdkTracerProvider};
+use opentelemetry_sdk::Resource;
 use std::time::Duration;
+use tracing::Subscriber;
 use tracing_error::ErrorLayer;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::field::MakeExt;
+use tracing_subscriber::fmt::{FormatFields, MakeWriter};
 use tracing_subscriber::prelude::*;
-use tracing_subscriber::{fmt, EnvFilter};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::{fmt, EnvFilter, Layer};
 
+use crate::settings::{LogFormat, OtlpSettings, TracingSettings};
+use crate::util::json_log::{FlatJson, JsonFields};
+use crate::util::redaction::{is_sensitive_field, Redacting, REDACTED};
+use crate::util::request_id::REQUEST_ID_HEADER;
 use axum::{body::Body, extract::Request, response::Response};
 use tracing::{Level, Span};
 
-// Creates a new tracing span with a unique request ID for each incoming request.
-// This helps in tracking and correlating logs for individual requests.
+// Creates a new tracing span with the request ID set by `propagate_request_id` for each incoming
+// request. This helps in tracking and correlating logs for individual requests.
+// A `traceparent` (and `tracestate`) sent by the caller, e.g. app-service, makes the span part
+// of the caller's trace when spans are exported.
 pub fn make_span_with_request_id(request: &Request<Body>) -> Span {
-    let request_id = uuid::Uuid::new_v4();
-    tracing::span!(
+    let request_id = request
+        .headers()
+        .get(&REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_owned)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let span = tracing::span!(
         Level::INFO,
         "[REQUEST]",
         method = tracing::field::display(request.method()),
         uri = tracing::field::display(request.uri()),
         version = tracing::field::debug(request.version()),
         request_id = tracing::field::display(request_id),
-    )
+        otel.name = format!("{} {}", request.method(), request.uri().path()),
+        otel.kind = "server",
+    );
+    let parent = TraceContextPropagator::new().extract(&HeaderExtractor(request.headers()));
+    // fails only if no OpenTelemetry layer is installed, in which case there is nothing to join
+    let _ = span.set_parent(parent);
+    span
 }
 
 // Logs an event indicating the start of a request.
@@ -53,22 +81,214 @@ pub fn on_response(response: &Response, latency: Duration, _span: &Span) {
     };
 }
 
-pub fn init_tracing() -> Result<()> {
-    // Create a formatting layer for tracing output with a compact format
-    let fmt_layer = fmt::layer().compact();
+// Keeps the OpenTelemetry pipeline, if one is configured, so that spans still buffered can be
+// exported before the process exits.
+pub struct Telemetry {
+    tracer_provider: Option<SdkTracerProvider>,
+}
+
+impl Telemetry {
+    pub fn force_flush(&self) -> Result<()> {
+        if let Some(tracer_provider) = &self.tracer_provider {
+            tracer_provider
+                .force_flush()
+                .wrap_err("failed to export spans")?;
+        }
+        Ok(())
+    }
+
+    pub fn shutdown(self) -> Result<()> {
+        if let Some(tracer_provider) = self.tracer_provider {
+            tracer_provider
+                .shutdown()
+                .wrap_err("failed to shut down span export")?;
+        }
+        Ok(())
+    }
+}
+
+pub fn init_tracing(settings: &TracingSettings) -> Result<Telemetry> {
+    let (subscriber, telemetry) = build_subscriber(settings)?;
+    tracing::subscriber::set_global_default(subscriber)
+        .wrap_err("failed to install tracing subscriber")?;
+    Ok(telemetry)
+}
+
+// The subscriber `init_tracing` installs, for tests to install for themselves.
+pub fn build_subscriber(
+    settings: &TracingSettings,
+) -> Result<(impl Subscriber + Send + Sync + 'static, Telemetry)> {
+    // Create a formatting layer for tracing output in the configured format
+    let fmt_layer = fmt_layer(settings.format, std::io::stdout);
 
     // Create a filter layer to control the verbosity of logs
     // Try to get the filter configuration from the environment variables
     // If it fails, default to the "info" log level
     let filter_layer = EnvFilter::try_from_default_env().or_else(|_| EnvFilter::try_new("info"))?;
 
+    let tracer_provider = match settings.otlp.enabled {
+        true => Some(tracer_provider(&settings.otlp)?),
+        false => None,
+    };
+    // a `None` layer does nothing
+    let otel_layer = tracer_provider.as_ref().map(|tracer_provider| {
+        tracing_opentelemetry::layer()
+            .with_tracer(tracer_provider.tracer(settings.otlp.service_name.clone()))
+    });
+
     // Build the tracing subscriber registry with the formatting layer,
     // the filter layer, and the error layer for enhanced error reporting
-    tracing_subscriber::registry()
+    let subscriber = tracing_subscriber::registry()
         .with(filter_layer) // Add the filter layer to control log verbosity
-        .with(fmt_layer) // Add the formatting layer for compact log output
-        .with(ErrorLayer::default()) // Add the error layer to capture error contexts
-        .init(); // Initialize the tracing subscriber
+        .with(fmt_layer) // Add the formatting layer for log output, redacted
+        .with(otel_layer) // Add the OpenTelemetry layer to export spans, if enabled
+        .with(ErrorLayer::default()); // Add the error layer to capture error contexts
+
+    Ok((subscriber, Telemetry { tracer_provider }))
+}
+
+// Whatever the format, values of sensitive fields (see `is_sensitive_field`) are replaced, and
+// emails and JWTs are scrubbed from each line before it is written.
+fn fmt_layer<S, W>(format: LogFormat, make_writer: W) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'writer> MakeWriter<'writer> + Send + Sync + 'static,
+{
+    let make_writer = Redacting(make_writer);
+    match format {
+        LogFormat::Compact => fmt::layer()
+            .compact()
+            .fmt_fields(compact_fields())
+            .with_writer(make_writer)
+            .boxed(),
+        LogFormat::Json => fmt::layer()
+            .fmt_fields(JsonFields)
+            .event_format(FlatJson)
+            .with_writer(make_writer)
+            .boxed(),
+    }
+}
+
+// `name=value` like the default, except for sensitive fields.
+fn compact_fields() -> impl for<'writer> FormatFields<'writer> + Send + Sync + 'static {
+    fmt::format::debug_fn(|writer, field, value| match field.name() {
+        "message" => write!(writer, "{:?}", value),
+        name if is_sensitive_field(name) => write!(writer, "{}={}", name, REDACTED),
+        name => write!(writer, "{}={:?}", name, value),
+    })
+    .delimited(" ")
+}
 
-    Ok(())
+// Spans are exported in batches from a background thread.
+fn tracer_provider(settings: &OtlpSettings) -> Result<SdkTracerProvider> {
+    use opentelemetry_otlp::WithExportConfig;
+
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .with_endpoint(settings.endpoint.clone())
+        .with_timeout(settings.timeout)
+        .build()
+        .wrap_err("failed to create OTLP span exporter")?;
+    Ok(SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(
+            settings.sample_ratio,
+        ))))
+        .with_resource(
+            Resource::builder()
+                .with_service_name(settings.service_name.clone())
+                .build(),
+        )
+        .build())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+    use std::io;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Buffer {
+        fn contents(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    #[test]
+    fn test_json_lines_flatten_span_fields() {
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let subscriber =
+            tracing_subscriber::registry().with(fmt_layer(LogFormat::Json, move || writer.clone()));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!(
+                "[REQUEST]",
+                request_id = "abc",
+                method = "POST",
+                uri = "/login",
+                otel.kind = "server",
+                status = tracing::field::Empty,
+            );
+            let _entered = span.enter();
+            span.record("status", 200);
+            tracing::info!(latency = 1.5, password = "hunter2", "[REQUEST END]");
+        });
+
+        let output = buffer.contents();
+        let lines: Vec<_> = output.lines().collect();
+        assert_eq!(lines.len(), 1);
+        let line: Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(line["level"], "INFO");
+        assert_eq!(line["message"], "[REQUEST END]");
+        assert_eq!(line["request_id"], "abc");
+        assert_eq!(line["method"], "POST");
+        assert_eq!(line["uri"], "/login");
+        assert_eq!(line["status"], 200);
+        assert_eq!(line["latency"], 1.5);
+        assert_eq!(line["password"], REDACTED);
+        assert!(line.get("otel.kind").is_none());
+        assert!(line["timestamp"].is_string());
+    }
+
+    #[test]
+    fn test_logs_are_redacted_in_every_format() {
+        for format in [LogFormat::Compact, LogFormat::Json] {
+            let buffer = Buffer::default();
+            let writer = buffer.clone();
+            let subscriber =
+                tracing_subscriber::registry().with(fmt_layer(format, move || writer.clone()));
+
+            tracing::subscriber::with_default(subscriber, || {
+                let span = tracing::info_span!("signup", email = "bob@example.com");
+                let _entered = span.enter();
+                tracing::info!(
+                    auth_token = "eyJhbGciOiJIUzI1NiJ9.eyJzdWIiOiJib2IifQ.c2ln",
+                    "sending a code to alice@example.org"
+                );
+            });
+
+            let output = buffer.contents();
+            assert!(output.contains("sending a code to [email]"), "{}", output);
+            assert!(output.contains(REDACTED), "{}", output);
+            for secret in ["bob@example.com", "alice@example.org", "eyJ"] {
+                assert!(!output.contains(secret), "{:?}: {}", format, output);
+            }
+        }
+    }
 }
@@ -0,0 +1,82 @@
+use crate::helpers::{get_random_email, TestApp};
+use auth_service::ErrorResponse;
+
+async fn signup_and_login(app: &TestApp, email: &str) {
+    let user = serde_json::json!({
+        "email": email,
+        "password": "password123!",
+        "requires2FA": false
+    });
+    let response = app.post_signup(&user).await;
+    assert_eq!(response.status().as_u16(), 201);
+    let response = app.post_login(&user).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_return_400_if_jwt_cookie_missing() {
+    let app = TestApp::new().await;
+    let body = serde_json::json!({
+        "currentPassword": "password123!",
+        "newPassword": "password456!"
+    });
+    let response = app.post("change-password", &body).await;
+    assert_eq!(response.status().as_u16(), 400);
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_current_password_is_wrong() {
+    let app = TestApp::new().await;
+    signup_and_login(&app, &get_random_email()).await;
+
+    let body = serde_json::json!({
+        "currentPassword": "wrongpassword123!",
+        "newPassword": "password456!"
+    });
+    let response = app.post("change-password", &body).await;
+    assert_eq!(response.status().as_u16(), 401);
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_reject_recently_used_passwords() {
+    let app = TestApp::new().await;
+    let email = get_random_email();
+    signup_and_login(&app, &email).await;
+
+    let body = serde_json::json!({
+        "currentPassword": "password123!",
+        "newPassword": "password456!"
+    });
+    let response = app.post("change-password", &body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // the new password is in effect
+    let login = serde_json::json!({
+        "email": email,
+        "password": "password456!",
+        "requires2FA": false
+    });
+    let response = app.post_login(&login).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // both the current and the previous password are off limits
+    for reused in ["password456!", "password123!"] {
+        let body = serde_json::json!({
+            "currentPassword": "password456!",
+            "newPassword": reused
+        });
+        let response = app.post("change-password", &body).await;
+        assert_eq!(response.status().as_u16(), 400);
+        assert_eq!(
+            response
+                .json::<ErrorResponse>()
+                .await
+                .expect("Could not deserialize response body to ErrorResponse")
+                .error,
+            "Password must not be your current password or one of your 5 previous ones"
+        );
+    }
+    app.clean_up().await;
+}
@@ -0,0 +1,190 @@
+use crate::helpers::{get_random_email, TestApp};
+use auth_service::routes::CsrfTokenResponse;
+use auth_service::util::constants::JWT_COOKIE_NAME;
+
+async fn logged_in_app() -> (TestApp, String) {
+    let app = TestApp::new().await;
+    let user = serde_json::json!({
+        "email": get_random_email(),
+        "password": "password123!",
+        "requires2FA": false
+    });
+    assert_eq!(app.post_signup(&user).await.status().as_u16(), 201);
+    let response = app.post_login(&user).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let token = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found")
+        .value()
+        .to_owned();
+    (app, token)
+}
+
+#[tokio::test]
+async fn should_hand_out_a_stable_token_in_a_cookie() {
+    let app = TestApp::new().await;
+    assert!(!app.csrf_token().is_empty());
+
+    let response = app.get("csrf-token").await;
+    assert_eq!(response.status().as_u16(), 200);
+    // the cookie from `TestApp::new` is still valid, so no new one is set
+    assert!(response.cookies().next().is_none());
+    let response: CsrfTokenResponse = response.json().await.unwrap();
+    assert_eq!(response.csrf_token, app.csrf_token());
+
+    // pages get the cookie too
+    let response = reqwest::get(format!("{}/", app.address)).await.unwrap();
+    let cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == "csrf_token")
+        .expect("No CSRF cookie found");
+    assert!(cookie.same_site_strict());
+    assert!(!cookie.http_only());
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_reject_cookie_authenticated_posts_without_the_token() {
+    let (app, token) = logged_in_app().await;
+
+    for csrf_token in [None, Some("forged"), Some(token.as_str())] {
+        let mut request = app.http_client.post(format!("{}/logout", app.address));
+        if let Some(csrf_token) = csrf_token {
+            request = request.header("x-csrf-token", csrf_token);
+        }
+        let response = request.send().await.unwrap();
+        assert_eq!(response.status().as_u16(), 403);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["error"], "Missing or invalid CSRF token");
+    }
+    let banned = app
+        .banned_token
+        .read()
+        .await
+        .contains_token(&token)
+        .await
+        .unwrap();
+    assert!(!banned);
+
+    assert_eq!(app.post_logout().await.status().as_u16(), 200);
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_bind_tokens_to_the_session() {
+    let app = TestApp::new().await;
+    let before_login = app.csrf_token();
+    let user = serde_json::json!({
+        "email": get_random_email(),
+        "password": "password123!",
+        "requires2FA": false
+    });
+    assert_eq!(app.post_signup(&user).await.status().as_u16(), 201);
+    let response = app.post_login(&user).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .cookies()
+        .any(|cookie| cookie.name() == "csrf_token"));
+    assert_ne!(app.csrf_token(), before_login);
+
+    // neither the token from before the login nor another client's works with this session
+    let (other, _) = logged_in_app().await;
+    for csrf_token in [before_login, other.csrf_token()] {
+        let response = app
+            .http_client
+            .post(format!("{}/logout", app.address))
+            .header("x-csrf-token", csrf_token)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 403);
+    }
+    assert_eq!(app.post_logout().await.status().as_u16(), 200);
+    assert_eq!(other.post_logout().await.status().as_u16(), 200);
+    app.clean_up().await;
+    other.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_reject_posts_from_other_origins() {
+    let app = TestApp::new().await;
+    let user = serde_json::json!({
+        "email": get_random_email(),
+        "password": "password123!",
+        "requires2FA": false
+    });
+
+    let response = app
+        .http_client
+        .post(format!("{}/signup", app.address))
+        .header("origin", "https://evil.example")
+        .json(&user)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 403);
+    let response = app
+        .http_client
+        .post(format!("{}/login", app.address))
+        .header("referer", "https://evil.example/login")
+        .json(&user)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 403);
+
+    // the UI's own origin, and the allowed ones, may
+    let response = app
+        .http_client
+        .post(format!("{}/signup", app.address))
+        .header("origin", &app.address)
+        .json(&user)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 201);
+    let response = app
+        .http_client
+        .post(format!("{}/login", app.address))
+        .header("origin", "http://localhost:8000")
+        .json(&user)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_exempt_bearer_tokens() {
+    let (app, token) = logged_in_app().await;
+
+    let client = reqwest::Client::new();
+    let body = serde_json::json!({ "language": "de" });
+    let response = client
+        .post(format!("{}/language", app.address))
+        .bearer_auth(&token)
+        .json(&body)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = client
+        .post(format!("{}/logout", app.address))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let banned = app
+        .banned_token
+        .read()
+        .await
+        .contains_token(&token)
+        .await
+        .unwrap();
+    assert!(banned);
+    app.clean_up().await;
+}
@@ -0,0 +1,73 @@
+use crate::helpers::TestDatabase;
+use auth_service::domain::data_stores::UserStore;
+use auth_service::domain::user::User;
+use auth_service::domain::Email;
+use auth_service::services::data_stares::postgres_user_store::{
+    EmailNormalization, PostgresUserStore,
+};
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+
+// Stores `email` as is, the way addresses were stored before they were normalised.
+async fn insert_legacy_user(pool: &PgPool, email: &str) {
+    let user = User::new("placeholder@example.com", "password123!", false)
+        .await
+        .unwrap();
+    sqlx::query("INSERT INTO users (email, password_hash, requires_2fa) VALUES ($1, $2, false)")
+        .bind(email)
+        .bind(user.password_hash.as_ref().expose_secret())
+        .execute(pool)
+        .await
+        .unwrap();
+}
+
+async fn has_index(pool: &PgPool) -> bool {
+    sqlx::query_scalar("SELECT to_regclass('users_email_lower_idx') IS NOT NULL")
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn stored_emails_should_be_normalised_like_lookups() {
+    let db = TestDatabase::new().await;
+    insert_legacy_user(&db.pool, "Jörg@BÜCHER.de").await;
+    insert_legacy_user(&db.pool, "not an address").await;
+    insert_legacy_user(&db.pool, "Herbert@Example.com").await;
+    insert_legacy_user(&db.pool, "herbert@example.com").await;
+    let store = PostgresUserStore::new(db.pool.clone());
+
+    let report = store.normalize_emails().await.unwrap();
+    assert_eq!(
+        report,
+        EmailNormalization {
+            normalized: 1,
+            unparseable: 1,
+            conflicting: 1,
+        }
+    );
+    let user = store
+        .get_user(&Email::unwrap("jörg@bücher.de"))
+        .await
+        .unwrap();
+    assert_eq!(user.email.as_ref().expose_secret(), "jörg@xn--bcher-kva.de");
+    // retried until the conflict is resolved
+    assert!(!has_index(&db.pool).await);
+
+    sqlx::query("DELETE FROM users WHERE email = 'herbert@example.com'")
+        .execute(&db.pool)
+        .await
+        .unwrap();
+    let report = store.normalize_emails().await.unwrap();
+    assert_eq!(report.normalized, 1);
+    assert!(has_index(&db.pool).await);
+    store
+        .get_user(&Email::unwrap("herbert@example.com"))
+        .await
+        .unwrap();
+    assert_eq!(
+        store.normalize_emails().await.unwrap(),
+        EmailNormalization::default()
+    );
+    db.clean_up().await;
+}
@@ -0,0 +1,144 @@
+use crate::helpers::{get_random_email, TestApp, ADMIN_TOKEN, EMAIL_OUTBOX_MAX_ATTEMPTS};
+use auth_service::ErrorResponse;
+use std::time::{Duration, Instant};
+
+const DEAD_LETTERS: &str = "admin/email-outbox/dead";
+
+async fn signup_with_2fa(app: &TestApp) -> serde_json::Value {
+    let user = serde_json::json!({
+        "email": get_random_email(),
+        "password": "password123!",
+        "requires2FA": true
+    });
+    let response = app.post_signup(&user).await;
+    assert_eq!(response.status().as_u16(), 201);
+    user
+}
+
+async fn wait_for_dead_letters(app: &TestApp) -> Vec<serde_json::Value> {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    loop {
+        let response = app.get_admin(DEAD_LETTERS, Some(ADMIN_TOKEN)).await;
+        assert_eq!(response.status().as_u16(), 200);
+        let dead_letters: Vec<serde_json::Value> = response.json().await.unwrap();
+        if !dead_letters.is_empty() {
+            return dead_letters;
+        }
+        assert!(Instant::now() < deadline, "no email was dead-lettered");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+}
+
+#[tokio::test]
+async fn login_should_not_wait_for_slow_email_provider() {
+    let app = TestApp::new().await;
+    let user = signup_with_2fa(&app).await;
+    app.smtp.set_delay(Duration::from_secs(2));
+
+    let started = Instant::now();
+    let response = app.post_login(&user).await;
+    assert_eq!(response.status().as_u16(), 206);
+    assert!(started.elapsed() < Duration::from_secs(1));
+
+    let messages = app.smtp.wait_for_messages(1).await;
+    assert_eq!(messages[0].header("Subject"), Some("Your login code"));
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn undeliverable_email_should_be_dead_lettered_and_retried_by_admin() {
+    let app = TestApp::new().await;
+    let user = serde_json::json!({
+        "email": get_random_email(),
+        "password": "password123!",
+        "requires2FA": false
+    });
+    let response = app.post_signup(&user).await;
+    assert_eq!(response.status().as_u16(), 201);
+    let response = app.post_login(&user).await;
+    assert_eq!(response.status().as_u16(), 200);
+    app.smtp.reject_recipients(true);
+
+    // the change itself succeeds, delivering the alert fails in the background
+    let body = serde_json::json!({
+        "currentPassword": "password123!",
+        "newPassword": "password456!"
+    });
+    let response = app.post("change-password", &body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let dead_letters = wait_for_dead_letters(&app).await;
+    assert_eq!(dead_letters.len(), 1);
+    let dead_letter = &dead_letters[0];
+    assert_eq!(dead_letter["recipient"], user["email"]);
+    assert_eq!(dead_letter["subject"], "Security alert");
+    assert_eq!(dead_letter["attempts"], EMAIL_OUTBOX_MAX_ATTEMPTS);
+    assert!(dead_letter["lastError"].as_str().unwrap().contains("550"));
+    assert!(app.smtp.messages().is_empty());
+
+    app.smtp.reject_recipients(false);
+    let id = dead_letter["id"].as_i64().unwrap();
+    let retry = format!("{}/{}/retry", DEAD_LETTERS, id);
+    let response = app.post_admin(&retry, Some(ADMIN_TOKEN)).await;
+    assert_eq!(response.status().as_u16(), 202);
+
+    let messages = app.smtp.wait_for_messages(1).await;
+    assert_eq!(messages[0].to, vec![user["email"].as_str().unwrap()]);
+    let response = app.get_admin(DEAD_LETTERS, Some(ADMIN_TOKEN)).await;
+    let dead_letters: Vec<serde_json::Value> = response.json().await.unwrap();
+    assert!(dead_letters.is_empty());
+
+    // only dead letters can be retried
+    let response = app.post_admin(&retry, Some(ADMIN_TOKEN)).await;
+    assert_eq!(response.status().as_u16(), 404);
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn dead_lettered_login_codes_should_not_be_retried() {
+    let app = TestApp::new().await;
+    let user = signup_with_2fa(&app).await;
+    app.smtp.reject_recipients(true);
+
+    // the login itself succeeds, delivery fails in the background
+    let response = app.post_login(&user).await;
+    assert_eq!(response.status().as_u16(), 206);
+
+    let dead_letters = wait_for_dead_letters(&app).await;
+    assert_eq!(dead_letters[0]["subject"], "Your login code");
+    app.smtp.reject_recipients(false);
+    let id = dead_letters[0]["id"].as_i64().unwrap();
+    let retry = format!("{}/{}/retry", DEAD_LETTERS, id);
+    let response = app.post_admin(&retry, Some(ADMIN_TOKEN)).await;
+    assert_eq!(response.status().as_u16(), 410);
+
+    let response = app.get_admin(DEAD_LETTERS, Some(ADMIN_TOKEN)).await;
+    let dead_letters: Vec<serde_json::Value> = response.json().await.unwrap();
+    assert_eq!(dead_letters.len(), 1);
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(app.smtp.messages().is_empty());
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn admin_routes_should_require_admin_token() {
+    let app = TestApp::new().await;
+    for token in [None, Some("wrong-token")] {
+        let response = app.get_admin(DEAD_LETTERS, token).await;
+        assert_eq!(response.status().as_u16(), 401);
+        assert_eq!(
+            response
+                .json::<ErrorResponse>()
+                .await
+                .expect("Could not deserialize response body to ErrorResponse")
+                .error,
+            "Unauthorized"
+        );
+
+        let response = app
+            .post_admin(&format!("{}/1/retry", DEAD_LETTERS), token)
+            .await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+    app.clean_up().await;
+}
@@ -0,0 +1,70 @@
+use crate::helpers::{test_settings, TestApp};
+use auth_service::domain::email_domain_policy::EmailDomainPolicy;
+use auth_service::routes::{LivenessResponse, ReadinessResponse};
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+#[tokio::test]
+async fn should_return_200_while_running() {
+    let app = TestApp::new().await;
+
+    let response = app.get("health/live").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let body = response.json::<LivenessResponse>().await.unwrap();
+    assert_eq!(body.status, "ok");
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_report_every_dependency_when_ready() {
+    let app = TestApp::new().await;
+
+    let response = app.get("health/ready").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let body = response.json::<ReadinessResponse>().await.unwrap();
+    assert_eq!(body.status, "ready");
+    // the test setup keeps banned tokens in memory, but 2FA codes in Redis
+    let dependencies: Vec<_> = body.checks.keys().map(String::as_str).collect();
+    assert_eq!(dependencies, ["email", "postgres", "redis"]);
+    for (dependency, check) in &body.checks {
+        assert_eq!(check.status, "up", "{} is down", dependency);
+        assert!(check.latency_ms >= 0.0);
+    }
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_503_if_a_dependency_is_down() {
+    let mut settings = test_settings();
+    settings.health.check_timeout = Duration::from_millis(200);
+    let app = TestApp::with_settings(settings, EmailDomainPolicy::default()).await;
+    app.smtp.set_unresponsive(true);
+
+    let response = app.get("health/ready").await;
+
+    assert_eq!(response.status().as_u16(), 503);
+    let body = response.json::<ReadinessResponse>().await.unwrap();
+    assert_eq!(body.status, "not_ready");
+    assert_eq!(body.checks["email"].status, "down");
+    assert!(body.checks["email"].latency_ms >= 200.0);
+    assert_eq!(body.checks["postgres"].status, "up");
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_503_while_draining() {
+    let app = TestApp::new().await;
+    app.draining.store(true, Ordering::SeqCst);
+
+    let response = app.get("health/ready").await;
+
+    assert_eq!(response.status().as_u16(), 503);
+    let body = response.json::<ReadinessResponse>().await.unwrap();
+    assert_eq!(body.status, "draining");
+
+    let response = app.get("health/live").await;
+    assert_eq!(response.status().as_u16(), 200);
+    app.clean_up().await;
+}
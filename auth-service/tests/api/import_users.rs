@@ -0,0 +1,29 @@
+use crate::helpers::TestDatabase;
+use auth_service::services::data_stares::postgres_user_store::PostgresUserStore;
+use auth_service::services::user_import::{import_users, ImportFormat};
+
+const BCRYPT_HASH: &str = "$2b$04$SU6FTRoLIY.i0hQraKudM..2OSoN.5OhSUBeIH9K4ogTHVQAXUJDa";
+
+#[tokio::test]
+async fn duplicate_rows_should_be_rejected_as_existing_users() {
+    let db = TestDatabase::new().await;
+    let mut store = PostgresUserStore::new(db.pool.clone());
+    // the second row is the first one's address in another case
+    let input = format!(
+        "email,hash,requires_2fa\n\
+         bob@example.com,{},false\n\
+         Bob@Example.com,{},true\n\
+         alice@example.com,{},false\n",
+        BCRYPT_HASH, BCRYPT_HASH, BCRYPT_HASH
+    );
+
+    let report = import_users(input.as_bytes(), ImportFormat::Csv, &mut store)
+        .await
+        .unwrap();
+
+    assert_eq!(report.imported, 2);
+    assert_eq!(report.rejected.len(), 1);
+    assert_eq!(report.rejected[0].line, 3);
+    assert_eq!(report.rejected[0].reason, "user already exists");
+    db.clean_up().await;
+}
@@ -0,0 +1,84 @@
+use crate::helpers::{get_random_email, TestApp};
+use auth_service::ErrorResponse;
+
+#[tokio::test]
+async fn should_send_2fa_email_in_language_chosen_on_signup() {
+    let app = TestApp::new().await;
+    let user = serde_json::json!({
+        "email": get_random_email(),
+        "password": "password123!",
+        "requires2FA": true,
+        "language": "de-AT"
+    });
+    let response = app.post_signup(&user).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app.post_login(&user).await;
+    assert_eq!(response.status().as_u16(), 206);
+    let messages = app.smtp.wait_for_messages(1).await;
+    assert_eq!(messages[0].header("Subject"), Some("Dein Anmeldecode"));
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_send_security_alert_in_updated_language() {
+    let app = TestApp::new().await;
+    let user = serde_json::json!({
+        "email": get_random_email(),
+        "password": "password123!",
+        "requires2FA": false
+    });
+    let response = app.post_signup(&user).await;
+    assert_eq!(response.status().as_u16(), 201);
+    let response = app.post_login(&user).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app
+        .post("language", &serde_json::json!({ "language": "de" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let body = serde_json::json!({
+        "currentPassword": "password123!",
+        "newPassword": "password456!"
+    });
+    let response = app.post("change-password", &body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let messages = app.smtp.wait_for_messages(1).await;
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].header("Subject"), Some("Sicherheitshinweis"));
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_400_for_unsupported_language() {
+    let app = TestApp::new().await;
+    let user = serde_json::json!({
+        "email": get_random_email(),
+        "password": "password123!",
+        "requires2FA": false,
+        "language": "tlh"
+    });
+    let response = app.post_signup(&user).await;
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        "Unsupported language"
+    );
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_400_if_jwt_cookie_missing() {
+    let app = TestApp::new().await;
+    let response = app
+        .post("language", &serde_json::json!({ "language": "de" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+    app.clean_up().await;
+}
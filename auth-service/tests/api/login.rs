@@ -2,6 +2,7 @@ use crate::helpers::{get_random_email, TestApp};
 use auth_service::domain::email::Email;
 use auth_service::routes::TwoFactorAuthResponse;
 use auth_service::util::constants::JWT_COOKIE_NAME;
+use secrecy::ExposeSecret;
 #[tokio::test]
 async fn should_return_206_if_valid_credentials_and_2fa_enabled() {
     let app = TestApp::new().await;
@@ -33,11 +34,59 @@ async fn should_return_206_if_valid_credentials_and_2fa_enabled() {
         let login_code = o.as_ref().get_code(&email_of_user).await.unwrap();
         let should_id = login_code.0.as_ref();
 
-        assert!(login_id == should_id);
+        assert!(login_id == should_id.expose_secret());
     }
     app.clean_up().await;
 }
 
+#[tokio::test]
+async fn should_email_2fa_code_that_completes_login() {
+    let app = TestApp::new().await;
+    let useremail = get_random_email();
+
+    let user = serde_json::json!({
+        "email": &useremail,
+        "password": "password123!",
+        "requires2FA": true
+    });
+    let signup_response = app.post_signup(&user).await;
+    assert_eq!(signup_response.status().as_u16(), 201);
+
+    let login_response = app.post_login(&user).await;
+    assert_eq!(login_response.status().as_u16(), 206);
+    let json_body = login_response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse");
+
+    let messages = app.smtp.wait_for_messages(1).await;
+    assert_eq!(messages.len(), 1);
+    let email = &messages[0];
+    assert_eq!(email.from, "no-reply@example.com");
+    assert_eq!(email.to, vec![useremail.clone()]);
+    assert_eq!(email.header("Subject"), Some("Your login code"));
+    assert!(email
+        .header("Content-Type")
+        .unwrap()
+        .starts_with("multipart/alternative"));
+    // the plain text part has the code on a line of its own
+    let code = email
+        .body()
+        .lines()
+        .find(|line| line.len() == 6 && line.chars().all(|c| c.is_ascii_digit()))
+        .expect("no 2FA code in email");
+
+    let verify_response = app
+        .post_verify_2fa(&serde_json::json!({
+            "email": &useremail,
+            "loginAttemptId": json_body.login_attempt_id,
+            "2FACode": code
+        }))
+        .await;
+    assert_eq!(verify_response.status().as_u16(), 200);
+    app.clean_up().await;
+}
+
 #[tokio::test]
 async fn should_return_422_if_malformed_credentials() {
     let app = TestApp::new().await;
@@ -66,9 +115,11 @@ async fn should_return_400_if_invalid_input() {
             "email": "invalidemail",
             "password": "password123!"
         }),
+        // the password policy only applies when a password is set, so only an empty
+        // password is invalid input here
         serde_json::json!({
             "email": "test@example.com",
-            "password": "short"
+            "password": ""
         }),
     ];
 
@@ -103,14 +154,14 @@ async fn should_return_401_if_incorrect_credentials() {
     let response = app.post_login(&invalid_credentials).await;
     assert_eq!(response.status().as_u16(), 401);
 
-    // Try to login with malformed credentials
+    // A password the policy would reject on signup is still just a wrong password on login
     let invalid_credentials = serde_json::json!({
         "email": email,
         "password": "321!"
     });
 
     let response = app.post_login(&invalid_credentials).await;
-    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(response.status().as_u16(), 401);
     app.clean_up().await;
 }
 #[tokio::test]
@@ -182,3 +233,25 @@ async fn should_return_200_if_valid_credentials_and_2fa_disabled() {
     assert!(!auth_cookie.value().is_empty());
     app.clean_up().await;
 }
+
+#[tokio::test]
+async fn should_login_regardless_of_email_case() {
+    let app = TestApp::new().await;
+    let random_email = get_random_email();
+
+    let signup_body = serde_json::json!({
+        "email": random_email.to_uppercase(),
+        "password": "password123!",
+        "requires2FA": false
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let login_body = serde_json::json!({
+        "email": format!(" {} ", random_email),
+        "password": "password123!",
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+    app.clean_up().await;
+}
@@ -1,6 +1,9 @@
-use crate::helpers::{get_random_email, TestApp};
+use crate::helpers::{get_random_email, test_settings, TestApp};
+use auth_service::domain::email_domain_policy::EmailDomainPolicy;
+use auth_service::settings::CookieSameSite;
 use auth_service::util::constants::JWT_COOKIE_NAME;
 use reqwest::Url;
+use std::time::Duration;
 
 #[tokio::test]
 async fn should_return_400_if_jwt_cookie_missing() {
@@ -25,6 +28,8 @@ async fn should_return_401_if_invalid_token() {
         ),
         &Url::parse("http://127.0.0.1").expect("Failed to parse URL"),
     );
+    // CSRF tokens are bound to the auth cookie, so it needs one for this one
+    app.get("csrf-token").await;
     // no body
     let no_body = serde_json::json!({});
     let response = app.post("logout", &no_body).await;
@@ -66,24 +71,63 @@ async fn should_return_200_if_valid_jwt_cookie() {
     let response = app.post_logout().await;
     assert_eq!(response.status().as_u16(), 200);
     // cookie shall be deleted
-    assert!(response
+    let removal = response
         .cookies()
         .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
-        .is_none());
+        .expect("logout should send a removal cookie");
+    assert_eq!(removal.value(), "");
+    assert_eq!(removal.max_age(), Some(std::time::Duration::ZERO));
+    assert_eq!(removal.path(), Some("/"));
 
     println!("token: {}", jwt_cookie.value());
     let token_banned = app
         .banned_token
         .read()
         .await
-        .contains_token(&jwt_cookie.value().to_string())
+        .contains_token(jwt_cookie.value())
         .await
         .unwrap();
     assert!(token_banned);
 
     // are we really logged out?
-    // let no_body = serde_json::json!({});
-    // let response = app.post("logout", &no_body).await;
-    // assert_eq!(response.status().as_u16(), 400); // cookie should be missing
+    let response = app.post_logout().await;
+    assert_eq!(response.status().as_u16(), 400); // cookie should be missing
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_use_the_configured_cookie_attributes() {
+    let mut settings = test_settings();
+    settings.jwt.token_ttl = Duration::from_secs(3600);
+    settings.auth_cookie.name = "session".to_owned();
+    settings.auth_cookie.same_site = CookieSameSite::Strict;
+    settings.auth_cookie.domain = Some("127.0.0.1".to_owned());
+    let app = TestApp::with_settings(settings, EmailDomainPolicy::default()).await;
+
+    let user = serde_json::json!({
+        "email": get_random_email(),
+        "password": "password123!",
+        "requires2FA": false
+    });
+    assert_eq!(app.post_signup(&user).await.status().as_u16(), 201);
+    let response = app.post_login(&user).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == "session")
+        .expect("No auth cookie found");
+    assert!(cookie.same_site_strict());
+    assert_eq!(cookie.domain(), Some("127.0.0.1"));
+    assert_eq!(cookie.max_age(), Some(Duration::from_secs(3600)));
+    assert!(!cookie.secure());
+
+    let response = app.post_logout().await;
+    assert_eq!(response.status().as_u16(), 200);
+    let removal = response
+        .cookies()
+        .find(|cookie| cookie.name() == "session")
+        .expect("logout should send a removal cookie");
+    assert_eq!(removal.domain(), Some("127.0.0.1"));
+    assert_eq!(removal.max_age(), Some(Duration::ZERO));
     app.clean_up().await;
 }
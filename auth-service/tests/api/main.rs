@@ -1,7 +1,23 @@
+mod change_password;
+mod csrf;
+mod email_normalization;
+mod email_outbox;
+mod health;
 mod helpers;
-// mod login;
+mod import_users;
+mod language;
+mod login;
 mod logout;
+mod metrics;
+mod otlp_collector;
+mod request_id;
 mod root;
+mod security_headers;
+mod settings;
+mod shutdown;
 mod signup;
+mod smtp_server;
+mod tls;
+mod tracing;
 // mod verify_2fa;
 mod verify_token;
@@ -0,0 +1,139 @@
+use crate::helpers::{get_random_email, test_settings, TestApp};
+use auth_service::domain::email_domain_policy::EmailDomainPolicy;
+
+// The value of one series, e.g. `auth_logins_total{outcome="success",reason=""}`, or 0 if it
+// hasn't been recorded yet. Tests run in parallel and share the counters, so they can only
+// ever check that a value went up.
+fn value_of(metrics: &str, series: &str) -> f64 {
+    metrics
+        .lines()
+        .find_map(|line| line.strip_prefix(series)?.strip_prefix(' '))
+        .map(|value| value.parse().unwrap())
+        .unwrap_or(0.0)
+}
+
+async fn scrape(address: &str) -> String {
+    let response = reqwest::get(format!("{}/metrics", address))
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers().get("content-type").unwrap(),
+        "text/plain; version=0.0.4"
+    );
+    response.text().await.unwrap()
+}
+
+async fn app_with_metrics() -> TestApp {
+    let mut settings = test_settings();
+    settings.metrics.enabled = true;
+    TestApp::with_settings(settings, EmailDomainPolicy::default()).await
+}
+
+#[tokio::test]
+async fn should_not_serve_metrics_unless_enabled() {
+    let app = TestApp::new().await;
+    let response = app.get("metrics").await;
+    assert!(!response
+        .text()
+        .await
+        .unwrap()
+        .contains("auth_tokens_banned_total"));
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_count_requests_per_route_and_status() {
+    let app = app_with_metrics().await;
+    let series = r#"auth_http_requests_total{method="POST",route="/signup",status="201"}"#;
+    let before = value_of(&scrape(&app.address).await, series);
+
+    let user = serde_json::json!({
+        "email": get_random_email(),
+        "password": "password123!",
+        "requires2FA": false
+    });
+    assert_eq!(app.post_signup(&user).await.status().as_u16(), 201);
+
+    let metrics = scrape(&app.address).await;
+    assert!(value_of(&metrics, series) >= before + 1.0);
+    assert!(metrics.contains(
+        r#"auth_http_request_duration_seconds_count{method="POST",route="/signup",status="201"}"#
+    ));
+    // Argon2 ran for the signup
+    assert!(metrics.contains(
+        r#"auth_password_hash_duration_seconds_count{algorithm="argon2",operation="hash"}"#
+    ));
+    assert!(metrics.contains(r#"auth_db_query_duration_seconds_count{operation="add_user"}"#));
+
+    // made-up methods don't get series of their own
+    let method = reqwest::Method::from_bytes(b"BREW").unwrap();
+    let response = app
+        .http_client
+        .request(method, format!("{}/signup", app.address))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 405);
+    let metrics = scrape(&app.address).await;
+    assert!(!metrics.contains("BREW"));
+    assert!(metrics
+        .contains(r#"auth_http_requests_total{method="other",route="/signup",status="405"}"#));
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_count_logins_by_outcome_and_reason() {
+    let app = app_with_metrics().await;
+    let success = r#"auth_logins_total{outcome="success",reason=""}"#;
+    let wrong_password = r#"auth_logins_total{outcome="failure",reason="incorrect_credentials"}"#;
+    let issued = "auth_two_fa_codes_issued_total";
+    let before = scrape(&app.address).await;
+
+    let email = get_random_email();
+    let user = serde_json::json!({
+        "email": &email,
+        "password": "password123!",
+        "requires2FA": true
+    });
+    assert_eq!(app.post_signup(&user).await.status().as_u16(), 201);
+    assert_eq!(app.post_login(&user).await.status().as_u16(), 206);
+    let wrong = serde_json::json!({
+        "email": &email,
+        "password": "password321!",
+    });
+    assert_eq!(app.post_login(&wrong).await.status().as_u16(), 401);
+
+    let after = scrape(&app.address).await;
+    for series in [success, wrong_password, issued] {
+        assert!(
+            value_of(&after, series) >= value_of(&before, series) + 1.0,
+            "{} did not go up",
+            series
+        );
+    }
+    assert!(after.contains(r#"auth_redis_command_duration_seconds_count{operation="add_code"}"#));
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_serve_metrics_on_their_own_port_if_configured() {
+    let mut settings = test_settings();
+    settings.metrics.enabled = true;
+    settings.metrics.port = Some(0);
+    let app = TestApp::with_settings(settings, EmailDomainPolicy::default()).await;
+
+    let metrics_address = app.metrics_address.clone().expect("no metrics listener");
+    assert!(scrape(&metrics_address)
+        .await
+        .contains("auth_tokens_banned_total"));
+
+    // the API port falls through to the UI
+    let response = app.get("metrics").await;
+    assert!(!response
+        .text()
+        .await
+        .unwrap()
+        .contains("auth_tokens_banned_total"));
+    app.clean_up().await;
+}
@@ -0,0 +1,110 @@
+use opentelemetry_proto::tonic::collector::trace::v1::ExportTraceServiceRequest;
+use opentelemetry_proto::tonic::common::v1::any_value::Value;
+use prost::Message;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone, Debug)]
+pub struct ReceivedSpan {
+    pub service_name: Option<String>,
+    pub name: String,
+    // hex encoded, empty for root spans
+    pub trace_id: String,
+    pub parent_span_id: String,
+}
+
+// Just enough of an OTLP/HTTP collector (protobuf, no compression) to receive the spans the
+// service exports. It runs on threads of its own rather than on the test's runtime, because
+// flushing the exporter blocks the thread that asks for it.
+pub struct OtlpCollector {
+    // the traces URL to export to
+    pub endpoint: String,
+    received: Arc<Mutex<Vec<ReceivedSpan>>>,
+}
+
+impl OtlpCollector {
+    pub fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind OTLP stand-in");
+        let endpoint = format!("http://{}/v1/traces", listener.local_addr().unwrap());
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let store = Arc::clone(&received);
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let store = Arc::clone(&store);
+                std::thread::spawn(move || handle_connection(stream, store));
+            }
+        });
+        OtlpCollector { endpoint, received }
+    }
+
+    pub fn spans(&self) -> Vec<ReceivedSpan> {
+        self.received.lock().unwrap().clone()
+    }
+}
+
+fn handle_connection(stream: TcpStream, received: Arc<Mutex<Vec<ReceivedSpan>>>) {
+    let mut writer = stream.try_clone().unwrap();
+    let mut reader = BufReader::new(stream);
+    // one request after the other on a kept-alive connection
+    loop {
+        let mut content_length = 0;
+        let mut line = String::new();
+        loop {
+            line.clear();
+            match reader.read_line(&mut line) {
+                Ok(0) | Err(_) => return,
+                Ok(_) => {}
+            }
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                if name.eq_ignore_ascii_case("content-length") {
+                    content_length = value.trim().parse().unwrap();
+                }
+            }
+        }
+        let mut body = vec![0; content_length];
+        if reader.read_exact(&mut body).is_err() {
+            return;
+        }
+        let request = ExportTraceServiceRequest::decode(body.as_slice())
+            .expect("OTLP stand-in received an invalid export request");
+        received.lock().unwrap().extend(spans(request));
+        let reply =
+            b"HTTP/1.1 200 OK\r\ncontent-type: application/x-protobuf\r\ncontent-length: 0\r\n\r\n";
+        if writer.write_all(reply).is_err() {
+            return;
+        }
+    }
+}
+
+fn spans(request: ExportTraceServiceRequest) -> Vec<ReceivedSpan> {
+    let mut spans = Vec::new();
+    for resource_spans in request.resource_spans {
+        let service_name = resource_spans.resource.and_then(|resource| {
+            resource
+                .attributes
+                .into_iter()
+                .find(|attribute| attribute.key == "service.name")
+                .and_then(|attribute| attribute.value?.value)
+                .and_then(|value| match value {
+                    Value::StringValue(name) => Some(name),
+                    _ => None,
+                })
+        });
+        for scope_spans in resource_spans.scope_spans {
+            for span in scope_spans.spans {
+                spans.push(ReceivedSpan {
+                    service_name: service_name.clone(),
+                    name: span.name,
+                    trace_id: hex::encode(span.trace_id),
+                    parent_span_id: hex::encode(span.parent_span_id),
+                });
+            }
+        }
+    }
+    spans
+}
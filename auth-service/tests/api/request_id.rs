@@ -0,0 +1,70 @@
+use crate::helpers::{get_random_email, TestApp};
+use auth_service::ErrorResponse;
+
+#[tokio::test]
+async fn should_echo_a_well_formed_request_id() {
+    let app = TestApp::new().await;
+
+    let response = app
+        .http_client
+        .get(format!("{}/health/live", &app.address))
+        .header("X-Request-Id", "app-service-42")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()["x-request-id"], "app-service-42");
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_replace_a_missing_or_malformed_request_id() {
+    let app = TestApp::new().await;
+
+    let response = app.get("health/live").await;
+    let generated = response.headers()["x-request-id"].to_str().unwrap();
+    assert!(uuid::Uuid::parse_str(generated).is_ok());
+
+    let malformed = "a".repeat(129);
+    let response = app
+        .http_client
+        .get(format!("{}/health/live", &app.address))
+        .header("X-Request-Id", &malformed)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    let replaced = response.headers()["x-request-id"].to_str().unwrap();
+    assert_ne!(replaced, malformed);
+    assert!(uuid::Uuid::parse_str(replaced).is_ok());
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_include_the_request_id_in_error_responses() {
+    let app = TestApp::new().await;
+    let user = serde_json::json!({
+        "email": get_random_email(),
+        "password": "password123!",
+        "requires2FA": false
+    });
+    assert_eq!(app.post_signup(&user).await.status().as_u16(), 201);
+
+    let response = app
+        .http_client
+        .post(format!("{}/signup", &app.address))
+        .header("X-Request-Id", "duplicate-signup")
+        .json(&user)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 409);
+    assert_eq!(response.headers()["x-request-id"], "duplicate-signup");
+    let body = response
+        .json::<ErrorResponse>()
+        .await
+        .expect("Could not deserialize response body to ErrorResponse");
+    assert_eq!(body.request_id.as_deref(), Some("duplicate-signup"));
+    app.clean_up().await;
+}
@@ -0,0 +1,99 @@
+use crate::helpers::{test_settings, TestApp};
+use auth_service::domain::email_domain_policy::EmailDomainPolicy;
+
+fn header<'a>(response: &'a reqwest::Response, name: &str) -> Option<&'a str> {
+    response
+        .headers()
+        .get(name)
+        .map(|value| value.to_str().unwrap())
+}
+
+#[tokio::test]
+async fn should_give_pages_a_csp_nonce_for_their_scripts() {
+    let app = TestApp::new().await;
+
+    let response = app.get_root().await;
+    assert_eq!(response.status().as_u16(), 200);
+    let policy = header(&response, "content-security-policy")
+        .unwrap()
+        .to_owned();
+    let nonce = policy
+        .split("'nonce-")
+        .nth(1)
+        .and_then(|rest| rest.split('\'').next())
+        .expect("No nonce in the policy");
+    assert!(policy.contains("frame-ancestors 'none'"), "{}", policy);
+    assert_eq!(header(&response, "referrer-policy"), Some("same-origin"));
+    assert_eq!(header(&response, "x-content-type-options"), Some("nosniff"));
+    assert_eq!(header(&response, "cache-control"), Some("no-store"));
+    let page = response.text().await.unwrap();
+    assert_eq!(
+        page.matches(&format!("<script nonce=\"{}\"", nonce))
+            .count(),
+        2,
+        "{}",
+        page
+    );
+    assert!(!page.contains("{{csp_nonce}}"));
+
+    // every page gets a new one
+    let response = app.get_root().await;
+    let next_policy = header(&response, "content-security-policy").unwrap();
+    assert_ne!(next_policy, policy);
+
+    // other assets keep the UI headers, but aren't rewritten
+    let response = app.get("app.js").await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(header(&response, "referrer-policy"), Some("same-origin"));
+    assert_eq!(header(&response, "cache-control"), None);
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_lock_down_api_responses() {
+    let app = TestApp::new().await;
+
+    let response = app.get("health/live").await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        header(&response, "content-security-policy"),
+        Some("default-src 'none'; frame-ancestors 'none'")
+    );
+    assert_eq!(header(&response, "referrer-policy"), Some("no-referrer"));
+    assert_eq!(header(&response, "x-content-type-options"), Some("nosniff"));
+    assert_eq!(
+        header(&response, "strict-transport-security"),
+        Some("max-age=31536000")
+    );
+    assert!(header(&response, "permissions-policy").is_some());
+
+    // errors too
+    let response = app.post_verify_token(&serde_json::json!({})).await;
+    assert_eq!(response.status().as_u16(), 422);
+    assert_eq!(header(&response, "referrer-policy"), Some("no-referrer"));
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_use_the_configured_headers() {
+    let mut settings = test_settings();
+    settings.security_headers.strict_transport_security = String::new();
+    settings.security_headers.api.referrer_policy = "strict-origin".to_owned();
+    let app = TestApp::with_settings(settings, EmailDomainPolicy::default()).await;
+
+    let response = app.get("health/live").await;
+    assert_eq!(header(&response, "strict-transport-security"), None);
+    assert_eq!(header(&response, "referrer-policy"), Some("strict-origin"));
+    app.clean_up().await;
+
+    let mut settings = test_settings();
+    settings.security_headers.enabled = false;
+    let app = TestApp::with_settings(settings, EmailDomainPolicy::default()).await;
+
+    for uri in ["", "health/live"] {
+        let response = app.get(uri).await;
+        assert_eq!(header(&response, "content-security-policy"), None);
+        assert_eq!(header(&response, "x-content-type-options"), None);
+    }
+    app.clean_up().await;
+}
@@ -0,0 +1,82 @@
+use crate::helpers::{test_settings, TestApp};
+use auth_service::domain::email_domain_policy::EmailDomainPolicy;
+use reqwest::Method;
+use std::time::Duration;
+
+#[tokio::test]
+async fn should_allow_configured_origins_only() {
+    let mut settings = test_settings();
+    settings.cors.allowed_origins = vec!["https://*.example.com".to_owned()];
+    let app = TestApp::with_settings(settings, EmailDomainPolicy::default()).await;
+
+    for (origin, allowed) in [
+        ("https://app.example.com", true),
+        ("https://a.b.example.com", true),
+        ("https://example.com", false),
+        ("http://localhost:8000", false),
+    ] {
+        let response = app
+            .http_client
+            .get(format!("{}/", &app.address))
+            .header("Origin", origin)
+            .send()
+            .await
+            .expect("Failed to execute request.");
+        assert_eq!(
+            response
+                .headers()
+                .get("access-control-allow-origin")
+                .map(|value| value.to_str().unwrap()),
+            allowed.then_some(origin),
+            "for {}",
+            origin
+        );
+    }
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_answer_preflights_with_the_configured_policy() {
+    let mut settings = test_settings();
+    settings.cors.allowed_methods = vec!["GET".to_owned(), "POST".to_owned(), "DELETE".to_owned()];
+    settings.cors.max_age = Duration::from_secs(600);
+    let app = TestApp::with_settings(settings, EmailDomainPolicy::default()).await;
+
+    let response = app
+        .http_client
+        .request(Method::OPTIONS, format!("{}/logout", &app.address))
+        .header("Origin", "http://localhost:8000")
+        .header("Access-Control-Request-Method", "POST")
+        .header(
+            "Access-Control-Request-Headers",
+            "content-type, x-csrf-token",
+        )
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert!(response.status().is_success());
+    let header = |name: &str| {
+        response
+            .headers()
+            .get(name)
+            .map(|value| value.to_str().unwrap().to_owned())
+    };
+    assert_eq!(
+        header("access-control-allow-origin").as_deref(),
+        Some("http://localhost:8000")
+    );
+    assert_eq!(
+        header("access-control-allow-methods").as_deref(),
+        Some("GET,POST,DELETE")
+    );
+    assert_eq!(
+        header("access-control-allow-headers").as_deref(),
+        Some("content-type,x-csrf-token")
+    );
+    assert_eq!(
+        header("access-control-allow-credentials").as_deref(),
+        Some("true")
+    );
+    assert_eq!(header("access-control-max-age").as_deref(), Some("600"));
+    app.clean_up().await;
+}
@@ -0,0 +1,86 @@
+use crate::helpers::{get_random_email, test_settings, TestApp};
+use auth_service::domain::email_domain_policy::EmailDomainPolicy;
+use auth_service::domain::Email;
+use auth_service::routes::ReadinessResponse;
+use std::time::{Duration, Instant};
+
+#[tokio::test]
+async fn should_finish_in_flight_requests_before_exiting() {
+    let mut settings = test_settings();
+    settings.health.check_timeout = Duration::from_millis(500);
+    let mut app = TestApp::with_settings(settings, EmailDomainPolicy::default()).await;
+    // makes `/health/ready` wait for the email check to time out
+    app.smtp.set_unresponsive(true);
+
+    let in_flight = tokio::spawn(
+        app.http_client
+            .get(format!("{}/health/ready", &app.address))
+            .send(),
+    );
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    app.begin_shutdown();
+
+    let response = in_flight
+        .await
+        .unwrap()
+        .expect("in-flight request was dropped");
+    assert_eq!(response.status().as_u16(), 503);
+    let body = response.json::<ReadinessResponse>().await.unwrap();
+    assert_eq!(body.status, "not_ready");
+    app.wait_for_exit().await.unwrap();
+
+    // the listener is closed
+    let request = app.http_client.get(format!("{}/health/live", &app.address));
+    assert!(request.send().await.is_err());
+    // the Postgres pool is closed
+    let email = Email::parse(get_random_email()).unwrap();
+    assert!(app.user_store.read().await.get_user(&email).await.is_err());
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_fail_readiness_during_the_readiness_delay() {
+    let mut settings = test_settings();
+    settings.shutdown.readiness_delay = Duration::from_millis(500);
+    let mut app = TestApp::with_settings(settings, EmailDomainPolicy::default()).await;
+
+    let started = Instant::now();
+    app.begin_shutdown();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let response = app.get("health/ready").await;
+    assert_eq!(response.status().as_u16(), 503);
+    let body = response.json::<ReadinessResponse>().await.unwrap();
+    assert_eq!(body.status, "draining");
+    // still serving everything else
+    assert_eq!(app.get("health/live").await.status().as_u16(), 200);
+
+    app.wait_for_exit().await.unwrap();
+    assert!(started.elapsed() >= Duration::from_millis(500));
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_stop_waiting_for_requests_after_the_drain_timeout() {
+    let mut settings = test_settings();
+    settings.health.check_timeout = Duration::from_secs(5);
+    settings.shutdown.drain_timeout = Duration::from_millis(200);
+    let mut app = TestApp::with_settings(settings, EmailDomainPolicy::default()).await;
+    app.smtp.set_unresponsive(true);
+
+    #[allow(clippy::let_underscore_future)]
+    let _ = tokio::spawn(
+        app.http_client
+            .get(format!("{}/health/ready", &app.address))
+            .send(),
+    );
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let started = Instant::now();
+    app.begin_shutdown();
+
+    app.wait_for_exit().await.unwrap();
+    let elapsed = started.elapsed();
+    assert!(elapsed >= Duration::from_millis(200), "{:?}", elapsed);
+    assert!(elapsed < Duration::from_secs(2), "{:?}", elapsed);
+    app.clean_up().await;
+}
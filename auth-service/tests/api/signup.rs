@@ -1,4 +1,5 @@
 use crate::helpers::{get_random_email, TestApp};
+use auth_service::domain::email_domain_policy::{EmailDomainPolicy, MxResolver};
 use auth_service::routes::SignupResponse;
 use auth_service::ErrorResponse;
 
@@ -81,27 +82,51 @@ async fn should_return_201_if_valid_input() {
 async fn should_return_400_if_invalid_input() {
     // The signup route should return a 400 HTTP status code if an invalid input is sent.
     // The input is considered invalid if:
-    // - The email is empty or does not contain '@'
+    // - The email is not a valid RFC 5321 address
     // - The password is less than 8 characters
 
     // Create an array of invalid inputs. Then, iterate through the array and
     // make HTTP calls to the signup route. Assert a 400 HTTP status code is returned.
     let app = TestApp::new().await;
 
-    let input = [
-        serde_json::json!({
-            "email": "asdsjfh-at-someone.com",
-            "password": "password123",
-            "requires2FA": true
-        }),
-        serde_json::json!({
-            "email": get_random_email(),
-            "password": "pass",
-            "requires2FA": false
-        }),
+    let mut input = vec![
+        (
+            serde_json::json!({
+                "email": "asdsjfh-at-someone.com",
+                "password": "password123",
+                "requires2FA": true
+            }),
+            "Invalid credentials",
+        ),
+        (
+            serde_json::json!({
+                "email": get_random_email(),
+                "password": "pass",
+                "requires2FA": false
+            }),
+            "Password must be at least 8 characters long",
+        ),
+        (
+            serde_json::json!({
+                "email": get_random_email(),
+                "password": "password123",
+                "requires2FA": false
+            }),
+            "Password must contain at least one special character",
+        ),
     ];
+    for email in ["@", "a@", "a b@example.com", "bob@localhost"] {
+        input.push((
+            serde_json::json!({
+                "email": email,
+                "password": "password123!",
+                "requires2FA": false
+            }),
+            "Invalid credentials",
+        ));
+    }
 
-    for i in input.iter() {
+    for (i, expected_error) in input.iter() {
         let response = app.post_signup(i).await;
         assert_eq!(response.status().as_u16(), 400, "Failed for input: {:?}", i);
 
@@ -111,7 +136,7 @@ async fn should_return_400_if_invalid_input() {
                 .await
                 .expect("Could not deserialize response body to ErrorResponse")
                 .error,
-            "Invalid credentials".to_owned()
+            expected_error.to_string()
         );
     }
     app.clean_up().await;
@@ -144,3 +169,88 @@ async fn should_return_409_if_email_already_exists() {
     );
     app.clean_up().await;
 }
+
+#[tokio::test]
+async fn should_return_409_if_email_differs_only_in_case() {
+    let app = TestApp::new().await;
+    let email = get_random_email();
+
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": email,
+            "password": "password123!",
+            "requires2FA": false
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": email.to_uppercase(),
+            "password": "password123!",
+            "requires2FA": false
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 409);
+    app.clean_up().await;
+}
+
+// Only `example.com` has a mail server.
+struct StubMxResolver;
+
+#[async_trait::async_trait]
+impl MxResolver for StubMxResolver {
+    async fn has_mx(&self, domain: &str) -> color_eyre::Result<bool> {
+        Ok(domain == "example.com")
+    }
+}
+
+#[tokio::test]
+async fn should_return_400_if_email_domain_not_allowed() {
+    let policy = EmailDomainPolicy::default()
+        .with_blocklist(["mailinator.com"])
+        .unwrap()
+        .with_allowlist(["example.com", "mailinator.com", "example.org"])
+        .unwrap()
+        .with_mx_resolver(Box::new(StubMxResolver));
+    let app = TestApp::with_email_domain_policy(policy).await;
+
+    for (email, expected_error) in [
+        (
+            "bob@eu.mailinator.com",
+            "Email addresses from this domain are not accepted",
+        ),
+        (
+            "bob@example.net",
+            "Only email addresses from approved domains are accepted",
+        ),
+        ("bob@example.org", "Email domain does not accept mail"),
+    ] {
+        let response = app
+            .post_signup(&serde_json::json!({
+                "email": email,
+                "password": "password123!",
+                "requires2FA": false
+            }))
+            .await;
+        assert_eq!(response.status().as_u16(), 400, "Failed for {}", email);
+        assert_eq!(
+            response
+                .json::<ErrorResponse>()
+                .await
+                .expect("Could not deserialize response body to ErrorResponse")
+                .error,
+            expected_error
+        );
+    }
+
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": get_random_email(),
+            "password": "password123!",
+            "requires2FA": false
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+    app.clean_up().await;
+}
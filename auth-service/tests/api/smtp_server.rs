@@ -0,0 +1,194 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+// A message as it was handed over to the SMTP stand-in.
+#[derive(Debug, Clone)]
+pub struct ReceivedEmail {
+    pub from: String,
+    pub to: Vec<String>,
+    pub data: String,
+}
+
+impl ReceivedEmail {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        let (headers, _) = self.data.split_once("\r\n\r\n")?;
+        headers.lines().find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            key.eq_ignore_ascii_case(name).then(|| value.trim())
+        })
+    }
+
+    pub fn body(&self) -> &str {
+        self.data
+            .split_once("\r\n\r\n")
+            .map_or("", |(_, body)| body)
+    }
+}
+
+// Just enough of an SMTP server (plain text, no auth) for the email client to deliver to,
+// recording every message it receives so that tests can read them back.
+pub struct SmtpServer {
+    pub port: u16,
+    received: Arc<Mutex<Vec<ReceivedEmail>>>,
+    behaviour: Arc<Behaviour>,
+}
+
+// How the stand-in misbehaves, so that tests can see how failing deliveries are handled.
+#[derive(Default)]
+struct Behaviour {
+    reject_recipients: AtomicBool,
+    delay: Mutex<Duration>,
+    unresponsive: AtomicBool,
+}
+
+impl SmtpServer {
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Failed to bind SMTP stand-in");
+        let port = listener.local_addr().unwrap().port();
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let behaviour = Arc::new(Behaviour::default());
+        let store = Arc::clone(&received);
+        let server_behaviour = Arc::clone(&behaviour);
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(handle_connection(
+                    stream,
+                    Arc::clone(&store),
+                    Arc::clone(&server_behaviour),
+                ));
+            }
+        });
+        SmtpServer {
+            port,
+            received,
+            behaviour,
+        }
+    }
+
+    pub fn messages(&self) -> Vec<ReceivedEmail> {
+        self.received.lock().unwrap().clone()
+    }
+
+    // Emails are delivered in the background, so tests wait for them to arrive.
+    pub async fn wait_for_messages(&self, count: usize) -> Vec<ReceivedEmail> {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        loop {
+            let messages = self.messages();
+            if messages.len() >= count {
+                return messages;
+            }
+            assert!(
+                Instant::now() < deadline,
+                "expected {} emails, got {}",
+                count,
+                messages.len()
+            );
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    // Answers every RCPT TO with a permanent failure while set.
+    pub fn reject_recipients(&self, reject: bool) {
+        self.behaviour
+            .reject_recipients
+            .store(reject, Ordering::SeqCst);
+    }
+
+    // Waits this long before accepting a message, like a slow provider.
+    pub fn set_delay(&self, delay: Duration) {
+        *self.behaviour.delay.lock().unwrap() = delay;
+    }
+
+    // Accepts new connections but never greets them while set, like a provider that hangs.
+    pub fn set_unresponsive(&self, unresponsive: bool) {
+        self.behaviour
+            .unresponsive
+            .store(unresponsive, Ordering::SeqCst);
+    }
+}
+
+async fn handle_connection(
+    stream: TcpStream,
+    received: Arc<Mutex<Vec<ReceivedEmail>>>,
+    behaviour: Arc<Behaviour>,
+) {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    let mut from = String::new();
+    let mut to = Vec::new();
+
+    if behaviour.unresponsive.load(Ordering::SeqCst) {
+        return std::future::pending().await;
+    }
+    if writer.write_all(b"220 localhost ESMTP\r\n").await.is_err() {
+        return;
+    }
+    while let Ok(Some(line)) = lines.next_line().await {
+        let command = line.to_ascii_uppercase();
+        let reply: &[u8] = if command.starts_with("EHLO") {
+            b"250-localhost\r\n250 8BITMIME\r\n"
+        } else if command.starts_with("HELO") || command.starts_with("NOOP") {
+            b"250 OK\r\n"
+        } else if command.starts_with("MAIL FROM:") {
+            from = address(&line);
+            to.clear();
+            b"250 OK\r\n"
+        } else if command.starts_with("RCPT TO:") {
+            if behaviour.reject_recipients.load(Ordering::SeqCst) {
+                b"550 Mailbox unavailable\r\n"
+            } else {
+                to.push(address(&line));
+                b"250 OK\r\n"
+            }
+        } else if command.starts_with("RSET") {
+            from.clear();
+            to.clear();
+            b"250 OK\r\n"
+        } else if command.starts_with("DATA") {
+            if writer
+                .write_all(b"354 End data with <CR><LF>.<CR><LF>\r\n")
+                .await
+                .is_err()
+            {
+                return;
+            }
+            let mut data = String::new();
+            while let Ok(Some(line)) = lines.next_line().await {
+                if line == "." {
+                    break;
+                }
+                // undo dot-stuffing
+                data.push_str(line.strip_prefix('.').unwrap_or(&line));
+                data.push_str("\r\n");
+            }
+            let delay = *behaviour.delay.lock().unwrap();
+            tokio::time::sleep(delay).await;
+            received.lock().unwrap().push(ReceivedEmail {
+                from: std::mem::take(&mut from),
+                to: std::mem::take(&mut to),
+                data,
+            });
+            b"250 OK\r\n"
+        } else if command.starts_with("QUIT") {
+            let _ = writer.write_all(b"221 Bye\r\n").await;
+            return;
+        } else {
+            b"502 Command not implemented\r\n"
+        };
+        if writer.write_all(reply).await.is_err() {
+            return;
+        }
+    }
+}
+
+fn address(line: &str) -> String {
+    line.split_once('<')
+        .and_then(|(_, rest)| rest.split_once('>'))
+        .map_or(String::new(), |(address, _)| address.to_owned())
+}
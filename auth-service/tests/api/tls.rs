@@ -0,0 +1,186 @@
+use crate::helpers::{get_random_email, test_settings, TestApp};
+use auth_service::domain::email_domain_policy::EmailDomainPolicy;
+use auth_service::settings::Settings;
+use rcgen::{BasicConstraints, Certificate, CertificateParams, IsCa, KeyPair};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+// A CA, and files holding a server certificate it issued, for `localhost` and `127.0.0.1`.
+struct TestCertificates {
+    dir: PathBuf,
+    ca: Certificate,
+    ca_key: KeyPair,
+}
+
+impl TestCertificates {
+    fn new() -> Self {
+        let dir = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        std::fs::create_dir(&dir).unwrap();
+        let ca_key = KeyPair::generate().unwrap();
+        let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca = params.self_signed(&ca_key).unwrap();
+        let certificates = TestCertificates { dir, ca, ca_key };
+        certificates.issue();
+        certificates
+    }
+
+    // Writes a new server certificate and key; returns the certificate's DER.
+    fn issue(&self) -> Vec<u8> {
+        let key = KeyPair::generate().unwrap();
+        let params =
+            CertificateParams::new(vec!["localhost".to_owned(), "127.0.0.1".to_owned()]).unwrap();
+        let certificate = params.signed_by(&key, &self.ca, &self.ca_key).unwrap();
+        let chain = format!("{}{}", certificate.pem(), self.ca.pem());
+        std::fs::write(self.cert_file(), chain).unwrap();
+        std::fs::write(self.key_file(), key.serialize_pem()).unwrap();
+        certificate.der().to_vec()
+    }
+
+    fn cert_file(&self) -> PathBuf {
+        self.dir.join("cert.pem")
+    }
+
+    fn key_file(&self) -> PathBuf {
+        self.dir.join("key.pem")
+    }
+
+    fn settings(&self) -> Settings {
+        let mut settings = test_settings();
+        settings.tls.enabled = true;
+        settings.tls.cert_file = Some(self.cert_file());
+        settings.tls.key_file = Some(self.key_file());
+        settings
+    }
+
+    // The certificate the server presents to a new connection.
+    async fn served_certificate(&self, url: &str) -> Vec<u8> {
+        let ca = reqwest::Certificate::from_pem(self.ca.pem().as_bytes()).unwrap();
+        let client = reqwest::Client::builder()
+            .add_root_certificate(ca)
+            .tls_info(true)
+            .build()
+            .unwrap();
+        let response = client.get(url).send().await.unwrap();
+        let tls_info = response
+            .extensions()
+            .get::<reqwest::tls::TlsInfo>()
+            .unwrap();
+        tls_info.peer_certificate().unwrap().to_vec()
+    }
+}
+
+impl Drop for TestCertificates {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[tokio::test]
+async fn should_serve_https_and_mark_cookies_secure() {
+    let certificates = TestCertificates::new();
+    let mut settings = certificates.settings();
+    settings.auth_cookie.host_prefix = true;
+    let app = TestApp::with_settings(settings, EmailDomainPolicy::default()).await;
+    assert!(app.address.starts_with("https://"));
+
+    let email = get_random_email();
+    let user = serde_json::json!({
+        "email": email,
+        "password": "password123!",
+        "requires2FA": false
+    });
+    assert_eq!(app.post_signup(&user).await.status().as_u16(), 201);
+    let response = app.post_login(&user).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == "__Host-jwt")
+        .expect("No auth cookie found");
+    assert!(cookie.secure());
+    assert_eq!(cookie.domain(), None);
+    // the cookie is sent back under its prefixed name
+    assert_eq!(app.post_logout().await.status().as_u16(), 200);
+
+    // no plain HTTP on the same port
+    let plain = app.address.replacen("https://", "http://", 1);
+    let response = reqwest::get(format!("{}/health/live", plain)).await;
+    assert!(response.map_or(true, |response| !response.status().is_success()));
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_redirect_plain_http_to_https() {
+    let certificates = TestCertificates::new();
+    let mut settings = certificates.settings();
+    settings.tls.redirect_port = Some(0);
+    let app = TestApp::with_settings(settings, EmailDomainPolicy::default()).await;
+    let redirect_address = app.redirect_address.clone().expect("no redirect listener");
+
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap();
+    let response = client
+        .get(format!("{}/health/live?probe=1", redirect_address))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 308);
+    let location = response.headers()["location"].to_str().unwrap();
+    assert_eq!(location, format!("{}/health/live?probe=1", app.address));
+    let response = app.http_client.get(location).send().await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_redirect_to_the_public_https_port() {
+    let certificates = TestCertificates::new();
+    let mut settings = certificates.settings();
+    settings.tls.redirect_port = Some(0);
+    settings.tls.public_port = Some(8443);
+    let app = TestApp::with_settings(settings, EmailDomainPolicy::default()).await;
+    let redirect_address = app.redirect_address.clone().expect("no redirect listener");
+
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap();
+    let response = client
+        .get(format!("{}/health/live", redirect_address))
+        .header("host", "auth.example.com:8080")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 308);
+    assert_eq!(
+        response.headers()["location"],
+        "https://auth.example.com:8443/health/live"
+    );
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_serve_a_replaced_certificate_without_restarting() {
+    let certificates = TestCertificates::new();
+    let mut settings = certificates.settings();
+    settings.tls.reload_interval = Duration::from_millis(50);
+    let app = TestApp::with_settings(settings, EmailDomainPolicy::default()).await;
+    let url = format!("{}/health/live", app.address);
+    let original = certificates.served_certificate(&url).await;
+
+    let replacement = certificates.issue();
+    assert_ne!(original, replacement);
+    let started = Instant::now();
+    while certificates.served_certificate(&url).await != replacement {
+        assert!(
+            started.elapsed() < Duration::from_secs(5),
+            "the new certificate was never served"
+        );
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    app.clean_up().await;
+}
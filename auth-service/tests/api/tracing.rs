@@ -0,0 +1,75 @@
+use crate::helpers::{test_settings, TestApp};
+use crate::otlp_collector::{OtlpCollector, ReceivedSpan};
+use auth_service::domain::email_domain_policy::EmailDomainPolicy;
+use auth_service::util::tracing::{build_subscriber, Telemetry};
+use std::time::Duration;
+
+const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+const PARENT_SPAN_ID: &str = "00f067aa0ba902b7";
+
+// The request span is closed, and queued for export, only after the response has been sent.
+async fn wait_for_span(
+    collector: &OtlpCollector,
+    telemetry: &Telemetry,
+    name: &str,
+) -> ReceivedSpan {
+    for _ in 0..100 {
+        telemetry.force_flush().unwrap();
+        if let Some(span) = collector.spans().into_iter().find(|span| span.name == name) {
+            return span;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("no span {} exported: {:?}", name, collector.spans());
+}
+
+#[tokio::test]
+async fn should_export_request_spans_in_the_callers_trace() {
+    let collector = OtlpCollector::start();
+    let mut settings = test_settings();
+    settings.tracing.otlp.enabled = true;
+    settings.tracing.otlp.endpoint = collector.endpoint.clone();
+    // the test's runtime runs on this thread only, so the app's spans go to this subscriber
+    let (subscriber, telemetry) = build_subscriber(&settings.tracing).unwrap();
+    let _guard = tracing::subscriber::set_default(subscriber);
+    let app = TestApp::with_settings(settings, EmailDomainPolicy::default()).await;
+
+    let response = app
+        .http_client
+        .post(format!("{}/verify-token", &app.address))
+        .header(
+            "traceparent",
+            format!("00-{}-{}-01", TRACE_ID, PARENT_SPAN_ID),
+        )
+        .header("tracestate", "vendor=value")
+        .json(&serde_json::json!({ "token": "invalid" }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 401);
+
+    let span = wait_for_span(&collector, &telemetry, "POST /verify-token").await;
+    assert_eq!(span.trace_id, TRACE_ID);
+    assert_eq!(span.parent_span_id, PARENT_SPAN_ID);
+    assert_eq!(span.service_name.as_deref(), Some("auth-service"));
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_start_a_new_trace_without_traceparent() {
+    let collector = OtlpCollector::start();
+    let mut settings = test_settings();
+    settings.tracing.otlp.enabled = true;
+    settings.tracing.otlp.endpoint = collector.endpoint.clone();
+    let (subscriber, telemetry) = build_subscriber(&settings.tracing).unwrap();
+    let _guard = tracing::subscriber::set_default(subscriber);
+    let app = TestApp::with_settings(settings, EmailDomainPolicy::default()).await;
+
+    let response = app.get("health/live").await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let span = wait_for_span(&collector, &telemetry, "GET /health/live").await;
+    assert_ne!(span.trace_id, TRACE_ID);
+    assert_eq!(span.parent_span_id, "");
+    app.clean_up().await;
+}